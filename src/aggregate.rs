@@ -0,0 +1,785 @@
+use crate::{
+    run::{ChainResult, RequestStatus, StepResult},
+    sla::Sla,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, SystemTime},
+};
+
+/// Number of bits of precision kept by the histogram, 2^6 = 64 buckets per power of two
+const SUB_BUCKET_BITS: u32 = 6;
+const SUB_BUCKET_COUNT: u64 = 1 << SUB_BUCKET_BITS;
+const SUB_BUCKET_HALF: u64 = SUB_BUCKET_COUNT / 2;
+/// Enough buckets to hold any u64
+const BUCKET_COUNT: usize =
+    ((64 - SUB_BUCKET_BITS + 1) as u64 * SUB_BUCKET_HALF + SUB_BUCKET_HALF) as usize;
+/// Maximum number of distinct errors tracked per client
+const MAX_TRACKED_ERRORS: usize = 16;
+
+/// Log-linear histogram with a fixed number of buckets. Values below 64 are exact,
+/// larger values are recorded with a relative error of at most 1/32.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    min: u64,
+    max: u64,
+}
+impl Histogram {
+    pub fn new() -> Self {
+        Self {
+            counts: vec![0; BUCKET_COUNT],
+            count: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+    fn index(value: u64) -> usize {
+        if value < SUB_BUCKET_COUNT {
+            return value as usize;
+        }
+        let shift = 63 - value.leading_zeros() - (SUB_BUCKET_BITS - 1);
+        (shift as u64 * SUB_BUCKET_HALF + (value >> shift)) as usize
+    }
+    /// Smallest and largest value that fall into bucket `index`
+    fn bucket_range(index: usize) -> (u64, u64) {
+        let index = index as u64;
+        if index < SUB_BUCKET_COUNT {
+            return (index, index);
+        }
+        let shift = index / SUB_BUCKET_HALF - 1;
+        let low = (index % SUB_BUCKET_HALF + SUB_BUCKET_HALF) << shift;
+        (low, low + ((1 << shift) - 1))
+    }
+    /// Number of recorded values
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+    pub fn record(&mut self, value: u64) {
+        self.counts[Self::index(value)] += 1;
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+    /// Adds every value recorded by `other`
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other;
+        }
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+    /// Empirical cumulative distribution at `points` evenly spaced fractions, every pair is a
+    /// value and the fraction of recorded values at or below it. Empty if nothing has been
+    /// recorded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::aggregate::Histogram;
+    ///
+    /// let mut histogram = Histogram::new();
+    /// for value in 1..=40 {
+    ///     histogram.record(value);
+    /// }
+    /// assert_eq!(histogram.cdf(4), vec![(10, 0.25), (20, 0.5), (30, 0.75), (40, 1.0)]);
+    /// assert!(Histogram::new().cdf(4).is_empty());
+    /// ```
+    pub fn cdf(&self, points: usize) -> Vec<(u64, f64)> {
+        if self.count == 0 {
+            return vec![];
+        }
+        (1..=points)
+            .map(|point| {
+                let fraction = point as f64 / points as f64;
+                (self.value_at_percentile(fraction * 100.0), fraction)
+            })
+            .collect()
+    }
+    /// Value below which `percentile` percent of recorded values fall, `percentile` is in (0, 100].
+    /// Returns 0 if nothing has been recorded
+    pub fn value_at_percentile(&self, percentile: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((percentile / 100.0 * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let (low, high) = Self::bucket_range(index);
+                return (low + (high - low) / 2).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+}
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Running mean and variance using Welford's algorithm
+#[derive(Clone, Debug, Default)]
+pub struct Moments {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+impl Moments {
+    pub fn record(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+    /// Combines the moments of two sets of values
+    pub fn merge(&mut self, other: &Moments) {
+        let count = self.count + other.count;
+        if count == 0 {
+            return;
+        }
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 += other.m2 + delta * delta * (self.count * other.count) as f64 / count as f64;
+        self.count = count;
+    }
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+    /// Population variance, 0 if nothing has been recorded
+    pub fn variance(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.m2 / self.count as f64
+        }
+    }
+    /// Standard error of the mean, the sample standard deviation over the square root of the
+    /// count. `None` with fewer than 2 values, where it is undefined.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::aggregate::Moments;
+    ///
+    /// let mut moments = Moments::default();
+    /// moments.record(1.0);
+    /// assert_eq!(moments.standard_error(), None);
+    /// moments.record(3.0);
+    /// // Sample standard deviation of 1 and 3 is sqrt(2)
+    /// assert!((moments.standard_error().unwrap() - 1.0).abs() < 1e-12);
+    /// ```
+    pub fn standard_error(&self) -> Option<f64> {
+        if self.count < 2 {
+            None
+        } else {
+            Some((self.m2 / (self.count - 1) as f64 / self.count as f64).sqrt())
+        }
+    }
+    /// 95% confidence interval of the mean as `(low, high)`, a t-interval up to 31 values and
+    /// `mean ± 1.96 * stderr` above. `None` with fewer than 2 values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::aggregate::Moments;
+    ///
+    /// let mut moments = Moments::default();
+    /// moments.record(1.0);
+    /// moments.record(3.0);
+    /// // One degree of freedom, the t value is 12.706 and the standard error 1
+    /// let (low, high) = moments.confidence_interval().unwrap();
+    /// assert!((low - (2.0 - 12.706)).abs() < 1e-9 && (high - (2.0 + 12.706)).abs() < 1e-9);
+    /// ```
+    pub fn confidence_interval(&self) -> Option<(f64, f64)> {
+        let stderr = self.standard_error()?;
+        let margin = t_critical_95(self.count - 1) * stderr;
+        Some((self.mean - margin, self.mean + margin))
+    }
+}
+/// Two sided 95% critical value of the t distribution with `degrees` degrees of freedom,
+/// the normal 1.96 past 30
+fn t_critical_95(degrees: u64) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    match degrees {
+        0 => f64::INFINITY,
+        1..=30 => TABLE[degrees as usize - 1],
+        _ => 1.96,
+    }
+}
+
+/// Approximate most frequent items using the space saving algorithm, holds at most
+/// `capacity` items
+#[derive(Clone, Debug)]
+pub struct TopN<T> {
+    capacity: usize,
+    items: Vec<(T, u64)>,
+}
+impl<T: PartialEq> TopN<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            items: Vec::with_capacity(capacity),
+        }
+    }
+    pub fn record(&mut self, item: T) {
+        self.record_n(item, 1)
+    }
+    /// Records `item` as if it was seen `n` times
+    pub fn record_n(&mut self, item: T, n: u64) {
+        if let Some((_, count)) = self.items.iter_mut().find(|(i, _)| *i == item) {
+            *count += n;
+        } else if self.items.len() < self.capacity {
+            self.items.push((item, n));
+        } else if let Some(smallest) = self.items.iter_mut().min_by_key(|(_, count)| *count) {
+            *smallest = (item, smallest.1 + n);
+        }
+    }
+    /// Items ordered from most to least common
+    pub fn most_common(&self) -> Vec<&T> {
+        let mut items = self.items.iter().collect::<Vec<_>>();
+        items.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        items.drain(..).map(|(item, _)| item).collect()
+    }
+}
+
+/// Status of a whole chain
+#[derive(Clone, Debug)]
+pub struct ChainStatus {
+    /// First error of the chain or the summed delays if every step succeeded
+    pub status: RequestStatus,
+    /// Summed latency of the steps that succeeded before the first failure, kept even if the
+    /// chain failed
+    pub successful_latency: Duration,
+    /// Latency of the successful steps plus the time taken by the first failing step,
+    /// `None` if the chain succeeded
+    pub time_to_failure: Option<Duration>,
+}
+/// Collapses the statuses of a chain into one status. The first error is returned,
+/// if all steps succeed the delays are summed along with any recorded think time. Skipped
+/// steps are ignored. A chain without errors but with an ignored timeout is
+/// [`RequestStatus::TimeoutIgnored`].
+pub fn get_chain_status(steps: &[StepResult]) -> ChainStatus {
+    let mut successful_latency = Duration::default();
+    let mut failure: Option<&StepResult> = None;
+    let mut timeout_ignored = false;
+    for step in steps.iter() {
+        match &step.status {
+            RequestStatus::Sucess { delay, .. } => {
+                successful_latency += *delay + step.think_time.unwrap_or_default()
+            }
+            RequestStatus::Skipped => {}
+            RequestStatus::TimeoutIgnored => timeout_ignored = true,
+            _ => {
+                // Steps after the first failure do not count toward the time to failure
+                failure = Some(step);
+                break;
+            }
+        }
+    }
+    match failure {
+        Some(step) => ChainStatus {
+            status: step.status.clone(),
+            successful_latency,
+            time_to_failure: Some(successful_latency + step.elapsed),
+        },
+        None if timeout_ignored => ChainStatus {
+            status: RequestStatus::TimeoutIgnored,
+            successful_latency,
+            time_to_failure: None,
+        },
+        None => ChainStatus {
+            status: RequestStatus::Sucess {
+                delay: successful_latency,
+                url: String::new(),
+            },
+            successful_latency,
+            time_to_failure: None,
+        },
+    }
+}
+
+/// Class of an http status code such as `2xx`, `none` if no response arrived
+pub fn status_class(status_code: Option<u16>) -> String {
+    match status_code {
+        Some(code) => format!("{}xx", code / 100),
+        None => "none".to_string(),
+    }
+}
+
+/// Running statistics for a single named client
+#[derive(Clone, Debug)]
+pub struct ClientAggregate {
+    pub total: u64,
+    pub failed: u64,
+    pub truncated: u64,
+    /// Chains that failed on a response over its `max_response_bytes`
+    pub oversized: u64,
+    /// Steps that succeeded slower than their `max_latency`
+    pub sla_breaches: u64,
+    /// Steps that got a response
+    pub responses: u64,
+    /// Steps whose response came over a reused connection
+    pub reused_connections: u64,
+    /// Successful chain latencies in nanoseconds
+    pub latency: Histogram,
+    /// Successful chain latencies in seconds
+    pub moments: Moments,
+    pub errors: TopN<RequestStatus>,
+    /// Time until failure of failed chains in nanoseconds
+    pub time_to_failure: Histogram,
+    /// Time until failure of failed chains in seconds
+    pub time_to_failure_moments: Moments,
+    /// Latency of individual steps in nanoseconds by the class of their status code
+    pub status_classes: BTreeMap<String, Histogram>,
+    /// Body sizes of individual responses in bytes
+    pub response_sizes: Histogram,
+    /// Body sizes of individual requests as sent in bytes
+    pub request_sizes: Histogram,
+    /// Steps whose latency only covers the response head
+    pub headers_only: u64,
+    /// Steps not run because their `run_if` condition was not met
+    pub skipped: u64,
+    /// Steps with body assertions that ran them
+    pub assertions_checked: u64,
+    /// Steps with body assertions that skipped them because of `assert_sample_rate`
+    pub assertions_skipped: u64,
+    /// Chains left out of `total` because a timeout was ignored
+    pub ignored_timeouts: u64,
+    /// Start of the earliest step
+    pub first_request_at: Option<SystemTime>,
+    /// Start of the latest step
+    pub last_request_at: Option<SystemTime>,
+    /// The first step of every chain on its own, `None` unless chains have more than one step
+    pub first_step: Option<FirstStepAggregate>,
+}
+impl ClientAggregate {
+    pub fn new() -> Self {
+        Self {
+            total: 0,
+            failed: 0,
+            truncated: 0,
+            oversized: 0,
+            sla_breaches: 0,
+            responses: 0,
+            reused_connections: 0,
+            latency: Histogram::new(),
+            moments: Moments::default(),
+            errors: TopN::new(MAX_TRACKED_ERRORS),
+            time_to_failure: Histogram::new(),
+            time_to_failure_moments: Moments::default(),
+            status_classes: BTreeMap::new(),
+            response_sizes: Histogram::new(),
+            request_sizes: Histogram::new(),
+            headers_only: 0,
+            skipped: 0,
+            assertions_checked: 0,
+            assertions_skipped: 0,
+            ignored_timeouts: 0,
+            first_request_at: None,
+            last_request_at: None,
+            first_step: None,
+        }
+    }
+    pub fn record(&mut self, steps: &[StepResult]) {
+        self.skipped += steps
+            .iter()
+            .filter(|step| step.status == RequestStatus::Skipped)
+            .count() as u64;
+        self.sla_breaches += steps.iter().filter(|step| step.sla_breached).count() as u64;
+        let ran = steps
+            .iter()
+            .filter(|step| step.status != RequestStatus::Skipped);
+        for step in ran
+            .clone()
+            .filter(|step| step.status != RequestStatus::TimeoutIgnored)
+        {
+            self.status_classes
+                .entry(status_class(step.status_code))
+                .or_default()
+                .record(step.elapsed.as_nanos() as u64);
+        }
+        self.headers_only += steps.iter().filter(|step| step.headers_only).count() as u64;
+        for checked in steps.iter().filter_map(|step| step.body_checked) {
+            if checked {
+                self.assertions_checked += 1;
+            } else {
+                self.assertions_skipped += 1;
+            }
+        }
+        for step in ran {
+            self.record_request_at(Some(step.started_at));
+        }
+        for bytes in steps.iter().filter_map(|step| step.response_bytes) {
+            self.response_sizes.record(bytes);
+        }
+        for bytes in steps.iter().filter_map(|step| step.request_bytes) {
+            self.request_sizes.record(bytes);
+        }
+        for reused in steps.iter().filter_map(|step| step.reused_connection) {
+            self.responses += 1;
+            if reused {
+                self.reused_connections += 1;
+            }
+        }
+        if steps.len() > 1 {
+            self.first_step
+                .get_or_insert_with(FirstStepAggregate::default)
+                .record(&steps[0]);
+        }
+        let chain = get_chain_status(steps);
+        if let Some(time_to_failure) = chain.time_to_failure {
+            self.time_to_failure
+                .record(time_to_failure.as_nanos() as u64);
+            self.time_to_failure_moments
+                .record(time_to_failure.as_secs_f64());
+        }
+        match chain.status {
+            RequestStatus::TimeoutIgnored => self.ignored_timeouts += 1,
+            RequestStatus::Sucess { .. } => {
+                self.total += 1;
+                self.latency
+                    .record(chain.successful_latency.as_nanos() as u64);
+                self.moments.record(chain.successful_latency.as_secs_f64());
+            }
+            error => {
+                self.total += 1;
+                self.failed += 1;
+                match error {
+                    RequestStatus::TruncatedResponse { .. } => self.truncated += 1,
+                    RequestStatus::ResponseTooLarge { .. } => self.oversized += 1,
+                    _ => {}
+                }
+                self.errors.record(error);
+            }
+        }
+    }
+}
+impl ClientAggregate {
+    /// Counts the chain and whether it failed without recording anything else about it
+    pub fn count(&mut self, steps: &[StepResult]) {
+        match get_chain_status(steps).status {
+            RequestStatus::TimeoutIgnored => self.ignored_timeouts += 1,
+            RequestStatus::Sucess { .. } => self.total += 1,
+            _ => {
+                self.total += 1;
+                self.failed += 1;
+            }
+        }
+    }
+    /// Widens the first and last request times to include `at`
+    fn record_request_at(&mut self, at: Option<SystemTime>) {
+        if let Some(at) = at {
+            self.first_request_at = Some(self.first_request_at.map_or(at, |first| first.min(at)));
+            self.last_request_at = Some(self.last_request_at.map_or(at, |last| last.max(at)));
+        }
+    }
+    /// Adds the chains recorded by `other`
+    pub fn merge(&mut self, other: &ClientAggregate) {
+        self.total += other.total;
+        self.failed += other.failed;
+        self.truncated += other.truncated;
+        self.oversized += other.oversized;
+        self.sla_breaches += other.sla_breaches;
+        self.responses += other.responses;
+        self.reused_connections += other.reused_connections;
+        self.latency.merge(&other.latency);
+        self.moments.merge(&other.moments);
+        for (error, count) in other.errors.items.iter() {
+            self.errors.record_n(error.clone(), *count);
+        }
+        self.time_to_failure.merge(&other.time_to_failure);
+        self.time_to_failure_moments
+            .merge(&other.time_to_failure_moments);
+        for (class, latency) in other.status_classes.iter() {
+            self.status_classes
+                .entry(class.clone())
+                .or_default()
+                .merge(latency);
+        }
+        self.response_sizes.merge(&other.response_sizes);
+        self.request_sizes.merge(&other.request_sizes);
+        self.headers_only += other.headers_only;
+        self.skipped += other.skipped;
+        self.assertions_checked += other.assertions_checked;
+        self.assertions_skipped += other.assertions_skipped;
+        self.ignored_timeouts += other.ignored_timeouts;
+        self.record_request_at(other.first_request_at);
+        self.record_request_at(other.last_request_at);
+        if let Some(first_step) = &other.first_step {
+            self.first_step
+                .get_or_insert_with(FirstStepAggregate::default)
+                .merge(first_step);
+        }
+    }
+}
+impl Default for ClientAggregate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Running statistics of the first step of the chains of a client, in the order the steps
+/// are declared, for when only the first hop of a journey matters
+#[derive(Clone, Debug, Default)]
+pub struct FirstStepAggregate {
+    pub total: u64,
+    pub failed: u64,
+    /// Latencies of successful first steps in nanoseconds
+    pub latency: Histogram,
+    /// Latencies of successful first steps in seconds
+    pub moments: Moments,
+}
+impl FirstStepAggregate {
+    /// Records the first step of a chain, steps that were skipped or whose timeout was
+    /// ignored are left out like they are for chains
+    pub fn record(&mut self, step: &StepResult) {
+        match step.status {
+            RequestStatus::Skipped | RequestStatus::TimeoutIgnored => {}
+            RequestStatus::Sucess { .. } => {
+                self.total += 1;
+                self.latency.record(step.elapsed.as_nanos() as u64);
+                self.moments.record(step.elapsed.as_secs_f64());
+            }
+            _ => {
+                self.total += 1;
+                self.failed += 1;
+            }
+        }
+    }
+    pub fn merge(&mut self, other: &FirstStepAggregate) {
+        self.total += other.total;
+        self.failed += other.failed;
+        self.latency.merge(&other.latency);
+        self.moments.merge(&other.moments);
+    }
+}
+
+/// Request counts for a single url
+#[derive(Clone, Debug, Default)]
+pub struct UrlAggregate {
+    pub total: u64,
+    pub failed: u64,
+    pub sla_breaches: u64,
+}
+
+/// Chains run by a single virtual user
+#[derive(Clone, Debug, Default)]
+pub struct WorkerAggregate {
+    pub chains: u64,
+    pub failed: u64,
+}
+
+/// Incrementally aggregates chain results as they complete. Memory use only depends on the
+/// number of clients unless `detail` is retained.
+pub struct Aggregator {
+    pub clients: HashMap<String, ClientAggregate>,
+    /// Counts of individual steps by url
+    pub urls: HashMap<String, UrlAggregate>,
+    /// Chains run by each virtual user in concurrency mode
+    pub workers: HashMap<usize, WorkerAggregate>,
+    /// Every chain result by client name, only kept when requested
+    pub detail: Option<HashMap<String, Vec<Vec<StepResult>>>>,
+    /// Number of individual requests made
+    pub requests: u64,
+    /// Connections opened during the run
+    pub new_connections: u64,
+    /// Most connections that were being established at once
+    pub max_concurrent_handshakes: u64,
+    /// DNS lookups made during the run by host
+    pub dns_lookups: BTreeMap<String, u64>,
+    /// Labels of each client
+    pub labels: HashMap<String, BTreeMap<String, String>>,
+    /// SLA of each client that has one
+    pub slas: HashMap<String, Sla>,
+    /// Wall time from the first dispatch until the last chain finished
+    pub elapsed: Duration,
+    /// Wall time from the first until the last dispatch
+    pub dispatch_elapsed: Duration,
+    /// Duration the group was configured to run for
+    pub duration: Duration,
+    /// Chains per second the group asked for, `None` in concurrency mode
+    pub target_rate: Option<f64>,
+    /// How late chains started compared to the schedule in nanoseconds
+    pub scheduling_delay: Histogram,
+    /// How late chains started compared to the schedule in seconds
+    pub scheduling_delay_moments: Moments,
+    /// How late the sleeps for think times woke up in nanoseconds
+    pub timer_lag: Histogram,
+    /// How late the sleeps for think times woke up in seconds
+    pub timer_lag_moments: Moments,
+    /// Chains still running when the max duration or the cooldown passed
+    pub abandoned: u64,
+    /// Chains that finished during the cooldown, `None` without a cooldown
+    pub cooldown_drained: Option<u64>,
+    /// Virtual users of a concurrency run
+    pub concurrency: Option<usize>,
+    /// Window over which virtual users started their first chain
+    pub ramp_start: Option<Duration>,
+    /// Time since the start of the run and the number of requests in flight at that time,
+    /// empty unless sampling was requested
+    pub in_flight: Vec<(Duration, i64)>,
+    /// Time since the start of the run and the resident memory of amawk in bytes at that
+    /// time, empty unless sampling was requested
+    pub memory: Vec<(Duration, u64)>,
+    /// Failed step that stopped a fail fast run
+    pub first_failure: Option<StepResult>,
+    /// Only count chains and their failures, nothing else is recorded
+    pub count_only: bool,
+    /// Name, configured share and average share over the run of every named request whose
+    /// weight drifted, empty without weight drift
+    pub weight_mix: Vec<(String, f64, f64)>,
+    /// Why writing the raw log failed, the raw log is incomplete when set
+    pub raw_log_error: Option<String>,
+}
+impl Aggregator {
+    pub fn new(retain_detail: bool) -> Self {
+        Self {
+            clients: HashMap::new(),
+            urls: HashMap::new(),
+            workers: HashMap::new(),
+            requests: 0,
+            new_connections: 0,
+            max_concurrent_handshakes: 0,
+            dns_lookups: BTreeMap::new(),
+            labels: HashMap::new(),
+            slas: HashMap::new(),
+            elapsed: Duration::default(),
+            dispatch_elapsed: Duration::default(),
+            duration: Duration::default(),
+            target_rate: None,
+            scheduling_delay: Histogram::new(),
+            scheduling_delay_moments: Moments::default(),
+            timer_lag: Histogram::new(),
+            timer_lag_moments: Moments::default(),
+            abandoned: 0,
+            cooldown_drained: None,
+            concurrency: None,
+            ramp_start: None,
+            in_flight: vec![],
+            memory: vec![],
+            first_failure: None,
+            count_only: false,
+            weight_mix: vec![],
+            raw_log_error: None,
+            detail: if retain_detail {
+                Some(HashMap::new())
+            } else {
+                None
+            },
+        }
+    }
+    /// Merges the clients that share a value for `label`, the merged clients are named after
+    /// the value. Clients without the label are merged into `(none)`. Everything but the
+    /// clients and their labels is copied unchanged, except for the detail and SLAs which are
+    /// dropped.
+    pub fn group_by(&self, label: &str) -> Aggregator {
+        let mut clients: HashMap<String, ClientAggregate> = HashMap::new();
+        let mut labels = HashMap::new();
+        for (name, client) in self.clients.iter() {
+            let value = self
+                .labels
+                .get(name)
+                .and_then(|labels| labels.get(label))
+                .cloned()
+                .unwrap_or_else(|| "(none)".to_string());
+            clients.entry(value.clone()).or_default().merge(client);
+            labels
+                .entry(value.clone())
+                .or_insert_with(|| BTreeMap::from([(label.to_string(), value)]));
+        }
+        Aggregator {
+            clients,
+            urls: self.urls.clone(),
+            workers: self.workers.clone(),
+            detail: None,
+            requests: self.requests,
+            new_connections: self.new_connections,
+            max_concurrent_handshakes: self.max_concurrent_handshakes,
+            dns_lookups: self.dns_lookups.clone(),
+            labels,
+            slas: HashMap::new(),
+            elapsed: self.elapsed,
+            dispatch_elapsed: self.dispatch_elapsed,
+            duration: self.duration,
+            target_rate: self.target_rate,
+            scheduling_delay: self.scheduling_delay.clone(),
+            scheduling_delay_moments: self.scheduling_delay_moments.clone(),
+            timer_lag: self.timer_lag.clone(),
+            timer_lag_moments: self.timer_lag_moments.clone(),
+            abandoned: self.abandoned,
+            cooldown_drained: self.cooldown_drained,
+            concurrency: self.concurrency,
+            ramp_start: self.ramp_start,
+            in_flight: self.in_flight.clone(),
+            memory: self.memory.clone(),
+            first_failure: self.first_failure.clone(),
+            count_only: self.count_only,
+            weight_mix: self.weight_mix.clone(),
+            raw_log_error: self.raw_log_error.clone(),
+        }
+    }
+    pub fn record(&mut self, result: ChainResult) {
+        let ChainResult {
+            name,
+            worker,
+            scheduling_delay,
+            steps,
+        } = result;
+        if self.count_only {
+            self.requests += steps
+                .iter()
+                .filter(|step| step.status != RequestStatus::Skipped)
+                .count() as u64;
+            self.clients.entry(name).or_default().count(&steps);
+            return;
+        }
+        if let Some(delay) = scheduling_delay {
+            self.scheduling_delay.record(delay.as_nanos() as u64);
+            self.scheduling_delay_moments.record(delay.as_secs_f64());
+        }
+        for lag in steps.iter().filter_map(|step| step.timer_lag) {
+            self.timer_lag.record(lag.as_nanos() as u64);
+            self.timer_lag_moments.record(lag.as_secs_f64());
+        }
+        self.clients.entry(name.clone()).or_default().record(&steps);
+        if let Some(worker) = worker {
+            let worker = self.workers.entry(worker).or_default();
+            worker.chains += 1;
+            if !matches!(
+                get_chain_status(&steps).status,
+                RequestStatus::Sucess { .. } | RequestStatus::TimeoutIgnored
+            ) {
+                worker.failed += 1;
+            }
+        }
+        for step in steps
+            .iter()
+            .filter(|step| step.status != RequestStatus::Skipped)
+        {
+            self.requests += 1;
+            if step.status == RequestStatus::TimeoutIgnored {
+                continue;
+            }
+            let url = self.urls.entry(step.url.clone()).or_default();
+            url.total += 1;
+            if !matches!(step.status, RequestStatus::Sucess { .. }) {
+                url.failed += 1;
+            }
+            if step.sla_breached {
+                url.sla_breaches += 1;
+            }
+        }
+        if let Some(detail) = self.detail.as_mut() {
+            detail.entry(name).or_default().push(steps);
+        }
+    }
+}
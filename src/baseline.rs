@@ -0,0 +1,100 @@
+use crate::stats::{write_table, Statistics, StatisticsClient};
+use serde::Deserialize;
+
+/// Json output of a previous run, only the statistics are used
+#[derive(Clone, Debug, Deserialize)]
+pub struct Baseline {
+    pub statistics: Statistics,
+}
+
+/// A single metric of a single client compared against the baseline
+#[derive(Clone, Debug)]
+pub struct ComparisonRow {
+    pub name: String,
+    pub metric: &'static str,
+    pub old: f64,
+    pub new: f64,
+    pub regressed: bool,
+}
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub rows: Vec<ComparisonRow>,
+}
+impl Comparison {
+    pub fn regressed(&self) -> bool {
+        self.rows.iter().any(|row| row.regressed)
+    }
+}
+impl std::fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut rows = vec![["name", "metric", "old", "new", "delta", "regressed"]
+            .iter()
+            .map(|h| h.to_string())
+            .collect::<Vec<_>>()];
+        for row in self.rows.iter() {
+            rows.push(vec![
+                row.name.clone(),
+                row.metric.to_string(),
+                row.old.to_string(),
+                row.new.to_string(),
+                (row.new - row.old).to_string(),
+                row.regressed.to_string(),
+            ]);
+        }
+        write_table(f, &rows)
+    }
+}
+fn error_rate(client: &StatisticsClient) -> f64 {
+    if client.total == 0 {
+        0.0
+    } else {
+        client.number_of_failed_requests as f64 / client.total as f64
+    }
+}
+fn p95(client: &StatisticsClient) -> Option<f64> {
+    client
+        .percentiles
+        .iter()
+        .find(|p| p.percentile == 95.0)
+        .map(|p| p.latency.as_secs_f64())
+}
+/// Compares the mean latency, p95 latency and error rate of every client present in both runs.
+/// Latency regresses when it grows by more than `latency_tolerance` percent, the error rate
+/// regresses when it grows by more than `error_tolerance` percentage points.
+pub fn compare(
+    baseline: &Statistics,
+    current: &Statistics,
+    latency_tolerance: f64,
+    error_tolerance: f64,
+) -> Comparison {
+    let mut rows = vec![];
+    for new in current.clients.iter() {
+        let old = match baseline.clients.iter().find(|c| c.name == new.name) {
+            Some(old) => old,
+            None => continue,
+        };
+        let latency_row = |metric, old: f64, new_value: f64| ComparisonRow {
+            name: new.name.clone(),
+            metric,
+            old,
+            new: new_value,
+            regressed: new_value > old * (1.0 + latency_tolerance / 100.0),
+        };
+        rows.push(latency_row(
+            "mean (s)",
+            old.average_total_load_time.as_secs_f64(),
+            new.average_total_load_time.as_secs_f64(),
+        ));
+        if let (Some(old_p95), Some(new_p95)) = (p95(old), p95(new)) {
+            rows.push(latency_row("p95 (s)", old_p95, new_p95));
+        }
+        rows.push(ComparisonRow {
+            name: new.name.clone(),
+            metric: "error rate",
+            old: error_rate(old),
+            new: error_rate(new),
+            regressed: error_rate(new) - error_rate(old) > error_tolerance / 100.0,
+        });
+    }
+    Comparison { rows }
+}
@@ -0,0 +1,392 @@
+use crate::{
+    config::{
+        default_cost, default_drain_body, default_max_body_bytes, default_max_drift_factor,
+        default_max_redirects, default_percentiles, DChainLatency, DDataSelection, DRankedRequest,
+        DRequest, DRequestGroup, DSelection, DTlsVersion, DWeightDrift,
+    },
+    request::RequestGroup,
+};
+use std::{convert::TryInto, time::Duration};
+
+/// Builds a [`RequestGroup`] without going through YML. The group is validated the same way
+/// a parsed config is when [`RequestGroupBuilder::build`] is called.
+///
+/// # Examples
+///
+/// ```
+/// use amawk::builder::RequestGroupBuilder;
+/// use std::time::Duration;
+///
+/// let group = RequestGroupBuilder::new()
+///     .ranked_request("home", 3.0, |b| b.request("http://localhost:8080/", Duration::ZERO))
+///     .ranked_request("login", 1.0, |b| {
+///         b.sequential(true)
+///             .request("http://localhost:8080/login", Duration::from_millis(500))
+///             .request("http://localhost:8080/account", Duration::ZERO)
+///     })
+///     .number_of_requests(100)
+///     .duration(Duration::from_secs(10))
+///     .build()
+///     .unwrap();
+/// assert_eq!(group.requests.len(), 2);
+/// ```
+///
+/// Invalid groups are rejected:
+///
+/// ```
+/// use amawk::builder::RequestGroupBuilder;
+///
+/// assert!(RequestGroupBuilder::new().build().is_err());
+/// assert!(RequestGroupBuilder::new()
+///     .ranked_request("home", 0.0, |b| b.request("http://localhost/", Default::default()))
+///     .build()
+///     .is_err());
+/// ```
+#[derive(Clone, Debug)]
+pub struct RequestGroupBuilder {
+    group: DRequestGroup,
+}
+impl RequestGroupBuilder {
+    pub fn new() -> Self {
+        Self {
+            group: DRequestGroup {
+                requests: vec![],
+                number_of_requests: 0,
+                duration_s: 0.0,
+                seed: None,
+                percentiles: default_percentiles(),
+                pool_max_idle_per_host: None,
+                pool_idle_timeout_s: None,
+                max_concurrent_handshakes: None,
+                concurrency: None,
+                connections_per_user: None,
+                inject_request_id: None,
+                selection: Default::default(),
+                ramp_start_s: None,
+                address_family: Default::default(),
+                base_url: None,
+                health_check: None,
+                accept: None,
+                proxy: None,
+                cooldown_s: None,
+                tls_min_version: None,
+                tls_max_version: None,
+                capacity: None,
+                chain_latency: Default::default(),
+                weight_drift: None,
+            },
+        }
+    }
+    /// Adds a chain of requests named `name` that is picked `weight` times as often as a
+    /// chain with a weight of 1, weights can be fractional
+    pub fn ranked_request<F>(mut self, name: &str, weight: f64, build: F) -> Self
+    where
+        F: FnOnce(RankedRequestBuilder) -> RankedRequestBuilder,
+    {
+        let ranked = build(RankedRequestBuilder {
+            request: DRankedRequest {
+                weight,
+                name: name.to_string(),
+                requests: vec![],
+                sequential: false,
+                shuffle_steps: false,
+                rate_rps: None,
+                sla: None,
+                cost: default_cost(),
+                labels: Default::default(),
+                data_file: None,
+                data_selection: Default::default(),
+            },
+        });
+        self.group.requests.push(ranked.request);
+        self
+    }
+    /// Total number of chains to run
+    pub fn number_of_requests(mut self, number_of_requests: u32) -> Self {
+        self.group.number_of_requests = number_of_requests;
+        self
+    }
+    /// Time over which to smear the chains
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.group.duration_s = duration.as_secs_f64();
+        self
+    }
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.group.seed = Some(seed);
+        self
+    }
+    pub fn percentiles(mut self, percentiles: Vec<f64>) -> Self {
+        self.group.percentiles = percentiles;
+        self
+    }
+    /// Maximum number of idle keep-alive connections kept per host
+    pub fn pool_max_idle_per_host(mut self, max_idle: usize) -> Self {
+        self.group.pool_max_idle_per_host = Some(max_idle);
+        self
+    }
+    /// Time after which idle keep-alive connections are closed
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.group.pool_idle_timeout_s = Some(timeout.as_secs_f64());
+        self
+    }
+    /// Maximum number of connections being established at once
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::builder::RequestGroupBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let group = RequestGroupBuilder::new()
+    ///     .ranked_request("home", 1.0, |b| b.request("http://localhost/", Duration::ZERO))
+    ///     .max_concurrent_handshakes(8)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(group.max_concurrent_handshakes, Some(8));
+    /// ```
+    pub fn max_concurrent_handshakes(mut self, limit: usize) -> Self {
+        self.group.max_concurrent_handshakes = Some(limit);
+        self
+    }
+    /// Runs `concurrency` virtual users back to back for the duration instead of a fixed
+    /// number of chains
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.group.concurrency = Some(concurrency);
+        self
+    }
+    /// Lets every virtual user run `connections` chains in parallel, like a browser opening
+    /// several connections to a host
+    pub fn connections_per_user(mut self, connections: usize) -> Self {
+        self.group.connections_per_user = Some(connections);
+        self
+    }
+    /// Spreads the first chain of every virtual user uniformly over `ramp_start` instead of
+    /// starting them all at once
+    pub fn ramp_start(mut self, ramp_start: Duration) -> Self {
+        self.group.ramp_start_s = Some(ramp_start.as_secs_f64());
+        self
+    }
+    /// Gives chains still running at the end of the duration `cooldown` to finish, chains
+    /// running after it are abandoned
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.group.cooldown_s = Some(cooldown.as_secs_f64());
+        self
+    }
+    /// Oldest TLS version connections may negotiate
+    pub fn tls_min_version(mut self, version: DTlsVersion) -> Self {
+        self.group.tls_min_version = Some(version);
+        self
+    }
+    /// Newest TLS version connections may negotiate
+    pub fn tls_max_version(mut self, version: DTlsVersion) -> Self {
+        self.group.tls_max_version = Some(version);
+        self
+    }
+    /// Sends a fresh UUID in the `header` of every request
+    pub fn inject_request_id(mut self, header: &str) -> Self {
+        self.group.inject_request_id = Some(header.to_string());
+        self
+    }
+    /// Picks chains with probability proportional to `weight / cost` instead of `weight`, see
+    /// [`RankedRequestBuilder::cost`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{builder::RequestGroupBuilder, run, RunOptions};
+    /// use std::time::Duration;
+    ///
+    /// // Equal weights, but `cheap` costs a third of `expensive` so it is picked three
+    /// // times as often
+    /// let group = RequestGroupBuilder::new()
+    ///     .ranked_request("cheap", 1.0, |b| b.cost(1.0).request("http://127.0.0.1:1/", Duration::ZERO))
+    ///     .ranked_request("expensive", 1.0, |b| {
+    ///         b.cost(3.0).request("http://127.0.0.1:1/", Duration::ZERO)
+    ///     })
+    ///     .cost_weighted(true)
+    ///     .number_of_requests(400)
+    ///     .duration(Duration::from_millis(100))
+    ///     .seed(7)
+    ///     .build()
+    ///     .unwrap();
+    /// let result = tokio::runtime::Runtime::new()
+    ///     .unwrap()
+    ///     .block_on(run(&group, RunOptions::default()));
+    /// let cheap = result.statistics.clients.iter().find(|c| c.name == "cheap").unwrap();
+    /// assert!((270..=330).contains(&cheap.total));
+    /// ```
+    pub fn cost_weighted(mut self, cost_weighted: bool) -> Self {
+        self.group.selection = if cost_weighted {
+            DSelection::CostWeighted
+        } else {
+            DSelection::Proportion
+        };
+        self
+    }
+    /// Lets the weights of the named requests drift in a random walk, every `interval` each
+    /// weight is multiplied by `e^(step * z)` for a standard normal `z`, staying within a
+    /// factor of 4 of its configured weight
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{builder::RequestGroupBuilder, run, RunOptions};
+    /// use std::time::Duration;
+    ///
+    /// let group = RequestGroupBuilder::new()
+    ///     .ranked_request("a", 1.0, |b| b.request("http://127.0.0.1:1/a", Duration::ZERO))
+    ///     .ranked_request("b", 1.0, |b| b.request("http://127.0.0.1:1/b", Duration::ZERO))
+    ///     .weight_drift(Duration::from_millis(20), 0.5)
+    ///     .number_of_requests(200)
+    ///     .duration(Duration::from_millis(200))
+    ///     .seed(3)
+    ///     .build()
+    ///     .unwrap();
+    /// let runtime = tokio::runtime::Runtime::new().unwrap();
+    /// let first = runtime.block_on(run(&group, RunOptions::default())).statistics;
+    /// let second = runtime.block_on(run(&group, RunOptions::default())).statistics;
+    /// // A fixed seed gives the same walk and, dispatching on a schedule, the same picks
+    /// let totals = |clients: &[amawk::stats::StatisticsClient]| {
+    ///     clients.iter().map(|c| c.total).collect::<Vec<_>>()
+    /// };
+    /// assert_eq!(totals(&first.clients), totals(&second.clients));
+    /// assert_eq!(first.weight_mix[0].configured_share, 0.5);
+    /// assert_eq!(first.weight_mix[0].average_share, second.weight_mix[0].average_share);
+    /// ```
+    pub fn weight_drift(mut self, interval: Duration, step: f64) -> Self {
+        self.group.weight_drift = Some(DWeightDrift {
+            interval_s: interval.as_secs_f64(),
+            step,
+            max_factor: default_max_drift_factor(),
+        });
+        self
+    }
+    /// Sets whether the think time after each step counts toward the latency of a chain
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{builder::RequestGroupBuilder, config::DChainLatency};
+    /// use std::time::Duration;
+    ///
+    /// let group = RequestGroupBuilder::new()
+    ///     .ranked_request("journey", 1.0, |b| {
+    ///         b.request("http://localhost/", Duration::from_secs(1))
+    ///     })
+    ///     .chain_latency(DChainLatency::IncludeThinkTime)
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(group.include_think_time);
+    /// ```
+    pub fn chain_latency(mut self, chain_latency: DChainLatency) -> Self {
+        self.group.chain_latency = chain_latency;
+        self
+    }
+    /// Resolves request urls without a scheme against `base_url`, urls with a scheme are
+    /// used as they are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::builder::RequestGroupBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let group = RequestGroupBuilder::new()
+    ///     .base_url("https://staging.example.com/api/")
+    ///     .ranked_request("users", 1.0, |b| {
+    ///         b.request("/health", Duration::ZERO)
+    ///             .request("users?page=2", Duration::ZERO)
+    ///             .request("http://localhost:8080/status", Duration::ZERO)
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// let urls: Vec<String> = group.requests[0]
+    ///     .requests
+    ///     .iter()
+    ///     .map(|request| request.uri.to_string())
+    ///     .collect();
+    /// assert_eq!(
+    ///     urls,
+    ///     [
+    ///         "https://staging.example.com/health",
+    ///         "https://staging.example.com/api/users?page=2",
+    ///         "http://localhost:8080/status",
+    ///     ]
+    /// );
+    /// ```
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.group.base_url = Some(base_url.to_string());
+        self
+    }
+    /// Sends `accept` as the `Accept` header of every request
+    pub fn accept(mut self, accept: &str) -> Self {
+        self.group.accept = Some(accept.to_string());
+        self
+    }
+    /// Sends every request through the http proxy at `proxy`
+    pub fn proxy(mut self, proxy: &str) -> Self {
+        self.group.proxy = Some(proxy.to_string());
+        self
+    }
+    pub fn build(self) -> Result<RequestGroup, Box<dyn std::error::Error + Send + Sync>> {
+        self.group.try_into()
+    }
+}
+impl Default for RequestGroupBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds a single chain, see [`RequestGroupBuilder::ranked_request`]
+#[derive(Clone, Debug)]
+pub struct RankedRequestBuilder {
+    request: DRankedRequest,
+}
+impl RankedRequestBuilder {
+    /// Appends a step that requests `url` and then waits for `delay`
+    pub fn request(mut self, url: &str, delay: Duration) -> Self {
+        self.request.requests.push(DRequest {
+            url: url.to_string(),
+            delay_s: delay.as_secs_f64(),
+            max_body_bytes: default_max_body_bytes(),
+            max_redirects: default_max_redirects(),
+            drain_body: default_drain_body(),
+            ..Default::default()
+        });
+        self
+    }
+    pub fn sequential(mut self, sequential: bool) -> Self {
+        self.request.sequential = sequential;
+        self
+    }
+    pub fn shuffle_steps(mut self, shuffle_steps: bool) -> Self {
+        self.request.shuffle_steps = shuffle_steps;
+        self
+    }
+    /// Relative cost of the chain, only used when picking chains weighted by cost
+    pub fn cost(mut self, cost: f64) -> Self {
+        self.request.cost = cost;
+        self
+    }
+    /// Starts the steps of a sequential chain `1 / rate_rps` seconds apart, a step that takes
+    /// longer than that is followed by the next one right away
+    pub fn rate_rps(mut self, rate_rps: f64) -> Self {
+        self.request.rate_rps = Some(rate_rps);
+        self
+    }
+    /// Tags the chain with `key: value`, results can be grouped by `key`
+    pub fn label(mut self, key: &str, value: &str) -> Self {
+        self.request
+            .labels
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+    /// Binds a row of the json file at `path` to every execution of the chain, picked by
+    /// `selection`
+    pub fn data_file(mut self, path: &str, selection: DDataSelection) -> Self {
+        self.request.data_file = Some(path.to_string());
+        self.request.data_selection = selection;
+        self
+    }
+}
@@ -0,0 +1,234 @@
+use crate::{
+    config::DCapacity,
+    request::RequestGroup,
+    run::{run, RunOptions},
+    stats::write_table,
+};
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, time::Duration};
+use tracing::info;
+
+/// Ramps the rate up by `step_rps` every `window` until the 95th percentile latency or the
+/// error rate crosses its limit or `max_rps` is reached
+#[derive(Clone, Debug)]
+pub struct CapacitySearch {
+    pub start_rps: f64,
+    pub step_rps: f64,
+    pub max_rps: f64,
+    /// Time each rate is held for
+    pub window: Duration,
+    pub max_p95: Option<Duration>,
+    /// Highest fraction of chains that may fail, in [0, 1]
+    pub max_error_rate: Option<f64>,
+}
+impl TryFrom<&DCapacity> for CapacitySearch {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(capacity: &DCapacity) -> Result<Self, Self::Error> {
+        for (name, value) in [
+            ("start_rps", capacity.start_rps),
+            ("step_rps", capacity.step_rps),
+            ("window_s", capacity.window_s),
+        ]
+        .iter()
+        {
+            if !(*value > 0.0 && value.is_finite()) {
+                return Err(format!("capacity {} of {} is invalid", name, value).into());
+            }
+        }
+        if !(capacity.max_rps >= capacity.start_rps && capacity.max_rps.is_finite()) {
+            return Err(format!(
+                "capacity max_rps of {} is below start_rps",
+                capacity.max_rps
+            )
+            .into());
+        }
+        if capacity.max_p95_ms.is_none() && capacity.max_error_rate.is_none() {
+            return Err("capacity needs max_p95_ms, max_error_rate or both".into());
+        }
+        if let Some(error_rate) = capacity.max_error_rate {
+            if !(0.0..=1.0).contains(&error_rate) {
+                return Err(
+                    format!("capacity max_error_rate {} is not in [0, 1]", error_rate).into(),
+                );
+            }
+        }
+        let max_p95 = match capacity.max_p95_ms {
+            Some(ms) if ms > 0.0 && ms.is_finite() => Some(
+                Duration::try_from_secs_f64(ms / 1000.0)
+                    .map_err(|_| format!("capacity max_p95_ms of {} is invalid", ms))?,
+            ),
+            Some(ms) => return Err(format!("capacity max_p95_ms of {} is invalid", ms).into()),
+            None => None,
+        };
+        Ok(Self {
+            start_rps: capacity.start_rps,
+            step_rps: capacity.step_rps,
+            max_rps: capacity.max_rps,
+            window: Duration::from_secs_f64(capacity.window_s),
+            max_p95,
+            max_error_rate: capacity.max_error_rate,
+        })
+    }
+}
+/// One window of a capacity search
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CapacityStep {
+    /// Chains per second the window asked for
+    pub target_rps: f64,
+    pub achieved_rps: f64,
+    /// 95th percentile latency of successful chains
+    pub p95: Duration,
+    pub error_percentage: f64,
+    /// The window crossed a limit
+    pub breached: bool,
+}
+/// Outcome of [`find_capacity`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Capacity {
+    /// Every window in the order it ran, the last one breached unless `max_rps` was reached
+    pub steps: Vec<CapacityStep>,
+    /// Highest rate whose window stayed within the limits, `None` if the first one breached
+    pub sustainable_rps: Option<f64>,
+    /// Rate of the window that breached, `None` if `max_rps` was reached first
+    pub breached_rps: Option<f64>,
+}
+/// Runs `group` once per window at increasing rates until a window breaches the limits of
+/// `search`. The number of requests and duration of the group are replaced by the rate and
+/// window, `options` gives the options of every window.
+///
+/// # Examples
+///
+/// ```
+/// use amawk::{
+///     builder::RequestGroupBuilder,
+///     capacity::{find_capacity, CapacitySearch},
+///     RunOptions,
+/// };
+/// use std::time::Duration;
+///
+/// let group = RequestGroupBuilder::new()
+///     .ranked_request("home", 1.0, |b| b.request("http://127.0.0.1:1/", Duration::ZERO))
+///     .build()
+///     .unwrap();
+/// let search = CapacitySearch {
+///     start_rps: 10.0,
+///     step_rps: 10.0,
+///     max_rps: 50.0,
+///     window: Duration::from_millis(200),
+///     max_p95: None,
+///     max_error_rate: Some(0.1),
+/// };
+/// let capacity = tokio::runtime::Runtime::new()
+///     .unwrap()
+///     .block_on(find_capacity(&group, &search, RunOptions::default));
+/// // Nothing listens on port 1, so the first window already breaches
+/// assert_eq!(capacity.sustainable_rps, None);
+/// assert_eq!(capacity.breached_rps, Some(10.0));
+/// ```
+pub async fn find_capacity(
+    group: &RequestGroup,
+    search: &CapacitySearch,
+    mut options: impl FnMut() -> RunOptions,
+) -> Capacity {
+    let mut capacity = Capacity {
+        steps: vec![],
+        sustainable_rps: None,
+        breached_rps: None,
+    };
+    let mut group = group.clone();
+    group.concurrency = None;
+    group.duration = search.window;
+    for window in 0.. {
+        // Multiplied rather than summed so steps like 0.1 do not drift past `max_rps`
+        let rate = search.start_rps + search.step_rps * window as f64;
+        if rate > search.max_rps * (1.0 + 1e-9) {
+            break;
+        }
+        group.number_of_requests = (rate * search.window.as_secs_f64()).round() as u32;
+        let summary = run(&group, options()).await.statistics.summary;
+        let breached = search.max_p95.is_some_and(|max_p95| summary.p95 > max_p95)
+            || search
+                .max_error_rate
+                .is_some_and(|max_error_rate| summary.error_percentage / 100.0 > max_error_rate);
+        info!(
+            target_rps = rate,
+            p95_ms = summary.p95.as_secs_f64() * 1000.0,
+            error_percentage = summary.error_percentage,
+            breached,
+            "capacity window finished"
+        );
+        capacity.steps.push(CapacityStep {
+            target_rps: rate,
+            achieved_rps: summary.achieved_requests_per_second,
+            p95: summary.p95,
+            error_percentage: summary.error_percentage,
+            breached,
+        });
+        if breached {
+            capacity.breached_rps = Some(rate);
+            break;
+        }
+        capacity.sustainable_rps = Some(rate);
+    }
+    capacity
+}
+impl Capacity {
+    /// Header of the csv rows written by [`Capacity::curve`]
+    pub const CURVE_HEADER: &'static str =
+        "target_requests_per_second,achieved_requests_per_second,p95_ms,error_percentage";
+    /// A csv row per window under [`Capacity::CURVE_HEADER`]
+    pub fn curve(&self) -> String {
+        std::iter::once(Self::CURVE_HEADER.to_string())
+            .chain(self.steps.iter().map(|step| {
+                format!(
+                    "{:.1},{:.1},{:.1},{:.2}",
+                    step.target_rps,
+                    step.achieved_rps,
+                    step.p95.as_secs_f64() * 1000.0,
+                    step.error_percentage
+                )
+            }))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+/// A table of the windows followed by the sustainable rate
+impl std::fmt::Display for Capacity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut rows = vec![vec![
+            "target rps".to_string(),
+            "achieved rps".to_string(),
+            "p95 (ms)".to_string(),
+            "errors (%)".to_string(),
+            "breached".to_string(),
+        ]];
+        for step in self.steps.iter() {
+            rows.push(vec![
+                format!("{:.1}", step.target_rps),
+                format!("{:.1}", step.achieved_rps),
+                format!("{:.1}", step.p95.as_secs_f64() * 1000.0),
+                format!("{:.2}", step.error_percentage),
+                if step.breached { "yes" } else { "no" }.to_string(),
+            ]);
+        }
+        write_table(f, &rows)?;
+        match (self.sustainable_rps, self.breached_rps) {
+            (Some(sustainable), Some(breached)) => write!(
+                f,
+                "\n\nsustainable rate: {:.1} rps, breached at {:.1} rps",
+                sustainable, breached
+            ),
+            (Some(sustainable), None) => write!(
+                f,
+                "\n\nsustainable rate: {:.1} rps, max_rps reached without a breach",
+                sustainable
+            ),
+            (None, Some(breached)) => write!(
+                f,
+                "\n\nno sustainable rate, the first window at {:.1} rps breached",
+                breached
+            ),
+            (None, None) => write!(f, "\n\nno windows ran"),
+        }
+    }
+}
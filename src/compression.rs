@@ -0,0 +1,67 @@
+use crate::config::DCompression;
+
+/// Whether amawk was built with support for `compression`
+pub fn supported(compression: DCompression) -> bool {
+    match compression {
+        DCompression::None => true,
+        DCompression::Gzip => cfg!(feature = "gzip"),
+        DCompression::Zstd => cfg!(feature = "zstd"),
+    }
+}
+/// Value of the `Content-Encoding` header sent with a body compressed with `compression`
+pub fn content_encoding(compression: DCompression) -> Option<&'static str> {
+    match compression {
+        DCompression::None => None,
+        DCompression::Gzip => Some("gzip"),
+        DCompression::Zstd => Some("zstd"),
+    }
+}
+/// Compresses `body`, it is returned unchanged for [`DCompression::None`]. Panics if the
+/// compression is not [`supported`], which is checked when the config is loaded.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "gzip")]
+/// # {
+/// use amawk::{compression::compress, config::DCompression};
+/// use flate2::read::GzDecoder;
+/// use std::io::Read;
+///
+/// let compressed = compress(b"hello hello hello", DCompression::Gzip);
+/// let mut decoded = String::new();
+/// GzDecoder::new(&compressed[..])
+///     .read_to_string(&mut decoded)
+///     .unwrap();
+/// assert_eq!(decoded, "hello hello hello");
+/// # }
+/// ```
+///
+/// ```
+/// # #[cfg(feature = "zstd")]
+/// # {
+/// use amawk::{compression::compress, config::DCompression};
+///
+/// let compressed = compress(b"hello hello hello", DCompression::Zstd);
+/// assert_eq!(zstd::decode_all(&compressed[..]).unwrap(), b"hello hello hello");
+/// # }
+/// ```
+pub fn compress(body: &[u8], compression: DCompression) -> Vec<u8> {
+    match compression {
+        DCompression::None => body.to_vec(),
+        #[cfg(feature = "gzip")]
+        DCompression::Gzip => {
+            use std::io::Write;
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder
+                .write_all(body)
+                .expect("writing to a Vec can not fail");
+            encoder.finish().expect("writing to a Vec can not fail")
+        }
+        #[cfg(feature = "zstd")]
+        DCompression::Zstd => zstd::encode_all(body, 0).expect("reading from a slice can not fail"),
+        #[allow(unreachable_patterns)]
+        compression => panic!("{} compression was not compiled in", compression),
+    }
+}
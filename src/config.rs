@@ -0,0 +1,648 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Deserializble Request
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DRequest {
+    #[serde(default)]
+    pub url: String,
+    /// Equivalent urls one of which is requested on every execution, instead of `url`
+    #[serde(default)]
+    pub urls: Option<Vec<String>>,
+    /// How one of `urls` is picked
+    #[serde(default)]
+    pub url_selection: DDataSelection,
+    /// Query parameters added to the url of every request, each with a value picked at random
+    /// from its pool
+    #[serde(default)]
+    pub query_params: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub delay_s: f64,
+    /// Think time after the request drawn from a distribution, replaces `delay_s`
+    #[serde(default)]
+    pub delay: Option<DDelay>,
+    /// Think time after a response with the status code of the key, instead of `delay` or
+    /// `delay_s`
+    #[serde(default)]
+    pub delay_on_status: BTreeMap<u16, DDelay>,
+    /// Sent as the `Host` header instead of the host of `url`. The connection and TLS server
+    /// name still use the host of `url`.
+    #[serde(default)]
+    pub host_header: Option<String>,
+    /// Sent as the `Accept` header, overrides the `accept` of the group
+    #[serde(default)]
+    pub accept: Option<String>,
+    /// Http proxy the request is sent through, overrides the `proxy` of the group. `none`
+    /// connects directly even if the group has a proxy.
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Successful responses slower than this are counted as SLA breaches
+    #[serde(default)]
+    pub max_latency_s: Option<f64>,
+    /// Times a failed request is retried
+    #[serde(default)]
+    pub retries: u32,
+    /// Wait before the first retry, doubled for every following retry
+    #[serde(default)]
+    pub retry_backoff_s: f64,
+    /// Randomizes every wait by up to this many seconds less than the backoff, a jitter at
+    /// least as large as the backoff waits a uniformly random time between 0 and the backoff
+    #[serde(default)]
+    pub retry_jitter_s: Option<f64>,
+    /// The request fails unless the response body contains this text
+    #[serde(default)]
+    pub expect_body_contains: Option<String>,
+    /// The request fails if the response body contains this text
+    #[serde(default)]
+    pub expect_body_not_contains: Option<String>,
+    /// Most bytes of the body kept for body assertions, the rest is only counted
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: u64,
+    /// Follow the `Location` of redirect responses
+    #[serde(default)]
+    pub follow_redirects: bool,
+    /// Redirects followed before the request fails
+    #[serde(default = "default_max_redirects")]
+    pub max_redirects: u32,
+    /// HTTP version the request is sent with
+    #[serde(default)]
+    pub http_version: DHttpVersion,
+    /// HTTP method, `GET` by default
+    #[serde(default)]
+    pub method: Option<String>,
+    /// Body rendered for every request, see [`crate::template::Template`] for the tokens
+    #[serde(default)]
+    pub body_template: Option<String>,
+    /// Compress the rendered body and send it with a matching `Content-Encoding`
+    #[serde(default)]
+    pub compress_body: DCompression,
+    /// Response headers recorded in the result of the request, see `--detail`
+    #[serde(default)]
+    pub capture_headers: Vec<String>,
+    /// How a request that hits `--timeout` is counted
+    #[serde(default)]
+    pub timeout_as: DTimeoutAs,
+    /// Responses with a larger body fail and the rest of the body is not read
+    #[serde(default)]
+    pub max_response_bytes: Option<u64>,
+    /// Fraction of requests whose headers are captured, all of them if not set
+    #[serde(default)]
+    pub capture_headers_sample_rate: Option<f64>,
+    /// Variables bound to the value of a response header, by variable name. Later steps of a
+    /// sequential chain read them in their body template as `{{variable}}`.
+    #[serde(default)]
+    pub extract_headers: BTreeMap<String, String>,
+    /// Fraction of requests whose body is kept and checked by the body assertions, all of
+    /// them if not set
+    #[serde(default)]
+    pub assert_sample_rate: Option<f64>,
+    /// Read the whole response body, when false the latency only covers the response head
+    #[serde(default = "default_drain_body")]
+    pub drain_body: bool,
+    /// Condition for running the step in a sequential chain, it is skipped otherwise
+    #[serde(default)]
+    pub run_if: Option<DRunIf>,
+    /// Conditions a response has to meet to count as a success
+    #[serde(default)]
+    pub success: Option<DSuccess>,
+}
+/// Conditions a response has to meet to count as a success. They are checked in the order
+/// of the fields and the first one that is not met decides the error.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DSuccess {
+    /// Status codes that count as a success, any status does if empty
+    #[serde(default)]
+    pub status: Vec<u16>,
+    /// Text the body has to contain, the same as `expect_body_contains`
+    #[serde(default)]
+    pub body_contains: Option<String>,
+    /// Regular expression the body has to match
+    #[serde(default)]
+    pub body_regex: Option<String>,
+    /// JSON pointer such as `/data/ok` to a value in the body, needs `json_equals`
+    #[serde(default)]
+    pub json_pointer: Option<String>,
+    /// Value the body has at `json_pointer`
+    #[serde(default)]
+    pub json_equals: Option<serde_yaml::Value>,
+}
+/// Condition for running a step
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DRunIf {
+    /// The step run before it succeeded
+    PreviousSuccess,
+}
+/// HTTP version of a request, `http10` also closes the connection after every request
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DHttpVersion {
+    Http10,
+    #[default]
+    Http11,
+}
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DRankedRequest {
+    /// Relative share of the chains, it is picked `weight` times as often as a chain with a
+    /// weight of 1. `proportion` is accepted as another name for it.
+    #[serde(alias = "proportion")]
+    pub weight: f64,
+    pub name: String,
+    pub requests: Vec<DRequest>,
+    /// Run steps one after another, waiting for each step and its delay
+    /// before starting the next
+    #[serde(default)]
+    pub sequential: bool,
+    /// Execute the steps of a sequential chain in a random order every time
+    /// the chain runs. Results are still reported in the order the steps are
+    /// declared. Because the order is random, a step can not depend on values
+    /// from the response of an earlier step, so this is incompatible with
+    /// response-value chaining.
+    #[serde(default)]
+    pub shuffle_steps: bool,
+    /// Requests per second a sequential chain sends. Steps start `1 / rate_rps` seconds
+    /// apart, the time a step takes counts towards the gap, so steps can not also set
+    /// `delay_s`
+    #[serde(default)]
+    pub rate_rps: Option<f64>,
+    /// Thresholds checked against the statistics of this request after the run
+    #[serde(default)]
+    pub sla: Option<DSla>,
+    /// Relative cost of serving this request, only used with `selection: cost_weighted`
+    #[serde(default = "default_cost")]
+    pub cost: f64,
+    /// Arbitrary key value pairs used to group results, see `--group-by`
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Json file with an array of objects, every chain execution binds one of them and body
+    /// templates read its fields as `{{field}}`
+    #[serde(default)]
+    pub data_file: Option<String>,
+    /// How the row of each chain execution is picked from `data_file`
+    #[serde(default)]
+    pub data_selection: DDataSelection,
+}
+/// How rows are picked from a data file, or urls from the `urls` of a request
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DDataSelection {
+    /// One row after another, starting over after the last one
+    #[default]
+    RoundRobin,
+    /// A random row every time, drawn with the seeded random number generator
+    Random,
+}
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DRequestGroup {
+    pub requests: Vec<DRankedRequest>,
+    /// Total number of requests to send
+    pub number_of_requests: u32,
+    /// Duration of time over which to smear requests
+    pub duration_s: f64,
+    /// Seed for the random number generator, makes runs reproducible
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Latency percentiles to report, each in (0, 100]
+    #[serde(default = "default_percentiles")]
+    pub percentiles: Vec<f64>,
+    /// Maximum number of idle keep-alive connections kept per host
+    #[serde(default)]
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Time after which idle keep-alive connections are closed
+    #[serde(default)]
+    pub pool_idle_timeout_s: Option<f64>,
+    /// Maximum number of connections being established at once, further connections wait
+    /// for one of them to finish. Requests over established connections are not limited.
+    #[serde(default)]
+    pub max_concurrent_handshakes: Option<usize>,
+    /// Run this many virtual users that each run chains back to back for `duration_s`
+    /// instead of smearing `number_of_requests` chains over the duration
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+    /// Number of chains each virtual user runs in parallel, defaults to 1
+    #[serde(default)]
+    pub connections_per_user: Option<usize>,
+    /// Name of a header that gets a fresh UUID on every request
+    #[serde(default)]
+    pub inject_request_id: Option<String>,
+    /// How chains are picked
+    #[serde(default)]
+    pub selection: DSelection,
+    /// Spread the first chain of every virtual user uniformly over this many seconds
+    #[serde(default)]
+    pub ramp_start_s: Option<f64>,
+    /// Address family connections are made over
+    #[serde(default)]
+    pub address_family: DAddressFamily,
+    /// Urls of requests without a scheme are resolved against it, urls with a scheme are
+    /// used as they are
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Request that has to succeed before the run starts, a single one for the group rather
+    /// than one for every url or host
+    #[serde(default)]
+    pub health_check: Option<DHealthCheck>,
+    /// Sent as the `Accept` header of every request that does not set its own
+    #[serde(default)]
+    pub accept: Option<String>,
+    /// Http proxy of every request that does not set its own
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Seconds after `duration_s` that running chains get to finish before they are
+    /// abandoned, no new chains start during it
+    #[serde(default)]
+    pub cooldown_s: Option<f64>,
+    /// Oldest TLS version connections may negotiate
+    #[serde(default)]
+    pub tls_min_version: Option<DTlsVersion>,
+    /// Newest TLS version connections may negotiate
+    #[serde(default)]
+    pub tls_max_version: Option<DTlsVersion>,
+    /// Search for the highest rate the target sustains instead of running once
+    #[serde(default)]
+    pub capacity: Option<DCapacity>,
+    /// Whether the think time after each step counts toward the latency of a chain
+    #[serde(default)]
+    pub chain_latency: DChainLatency,
+    /// Lets the weights of the named requests drift over the run instead of staying fixed
+    #[serde(default)]
+    pub weight_drift: Option<DWeightDrift>,
+}
+/// Seeded random walk of the weights of the named requests, every weight is multiplied by
+/// `e^(step * z)` for a standard normal `z` every `interval_s`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DWeightDrift {
+    /// Seconds between steps of the walk
+    pub interval_s: f64,
+    /// Standard deviation of the change of the logarithm of a weight per step
+    pub step: f64,
+    /// Every weight stays within this factor of its configured weight
+    #[serde(default = "default_max_drift_factor")]
+    pub max_factor: f64,
+}
+/// Ramps the rate up window by window until a limit is crossed, see
+/// [`crate::capacity::find_capacity`]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DCapacity {
+    /// Rate of the first window
+    pub start_rps: f64,
+    /// Rate added after every window that stayed within the limits
+    pub step_rps: f64,
+    /// Rate after which the search stops even without a breach
+    pub max_rps: f64,
+    /// Seconds each rate is held for
+    pub window_s: f64,
+    /// Highest 95th percentile latency of successful chains in milliseconds
+    #[serde(default)]
+    pub max_p95_ms: Option<f64>,
+    /// Highest fraction of chains that may fail, in [0, 1]
+    #[serde(default)]
+    pub max_error_rate: Option<f64>,
+}
+/// TLS protocol version, written as `1.2` or `"1.2"`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "DTlsVersionRepr", into = "String")]
+pub enum DTlsVersion {
+    Tls10,
+    Tls11,
+    Tls12,
+    Tls13,
+}
+/// A bare `1.2` in yaml is a number, quoted it is a string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DTlsVersionRepr {
+    Number(f64),
+    Text(String),
+}
+impl std::convert::TryFrom<DTlsVersionRepr> for DTlsVersion {
+    type Error = String;
+    fn try_from(version: DTlsVersionRepr) -> Result<Self, Self::Error> {
+        let text = match version {
+            DTlsVersionRepr::Number(number) => format!("{:.1}", number),
+            DTlsVersionRepr::Text(text) => text,
+        };
+        match text.as_str() {
+            "1.0" => Ok(Self::Tls10),
+            "1.1" => Ok(Self::Tls11),
+            "1.2" => Ok(Self::Tls12),
+            "1.3" => Ok(Self::Tls13),
+            _ => Err(format!(
+                "unknown TLS version {}, expected 1.0, 1.1, 1.2 or 1.3",
+                text
+            )),
+        }
+    }
+}
+impl DTlsVersion {
+    /// The version as written in a config, like `1.2`
+    pub fn number(&self) -> &'static str {
+        match self {
+            Self::Tls10 => "1.0",
+            Self::Tls11 => "1.1",
+            Self::Tls12 => "1.2",
+            Self::Tls13 => "1.3",
+        }
+    }
+}
+impl From<DTlsVersion> for String {
+    fn from(version: DTlsVersion) -> Self {
+        version.number().to_string()
+    }
+}
+impl std::fmt::Display for DTlsVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TLS {}", self.number())
+    }
+}
+/// Request sent once before the run, the run is aborted unless it gets the expected status
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DHealthCheck {
+    pub url: String,
+    #[serde(default = "default_health_check_status")]
+    pub status: u16,
+}
+pub fn default_health_check_status() -> u16 {
+    200
+}
+/// Address family connections are made over
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DAddressFamily {
+    /// Whatever the resolver returns
+    #[default]
+    Auto,
+    Ipv4,
+    Ipv6,
+}
+impl DAddressFamily {
+    /// Whether connecting to `ip` is allowed
+    pub fn allows(&self, ip: &std::net::IpAddr) -> bool {
+        match self {
+            Self::Auto => true,
+            Self::Ipv4 => ip.is_ipv4(),
+            Self::Ipv6 => ip.is_ipv6(),
+        }
+    }
+}
+impl std::fmt::Display for DAddressFamily {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Auto => "auto",
+            Self::Ipv4 => "ipv4",
+            Self::Ipv6 => "ipv6",
+        })
+    }
+}
+/// Think time after a request, either a constant number of seconds or a distribution
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum DDelay {
+    Constant(f64),
+    Distribution(DDelayDistribution),
+}
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum DDelayDistribution {
+    /// Uniform between `min_s` and `max_s`
+    Uniform { min_s: f64, max_s: f64 },
+    /// Log-normal with a mean of `mean_s` seconds, `sigma` is the standard deviation of the
+    /// logarithm of the delay
+    Lognormal { mean_s: f64, sigma: f64 },
+}
+/// How a request that timed out is counted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DTimeoutAs {
+    #[default]
+    Failure,
+    /// A success that took as long as the timeout
+    Success,
+    /// Neither a success nor a failure
+    Ignore,
+}
+/// Encoding request bodies are compressed with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+impl std::fmt::Display for DCompression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        })
+    }
+}
+/// What the latency of a chain covers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DChainLatency {
+    /// Only the time spent on the requests, the server time
+    #[default]
+    ExcludeThinkTime,
+    /// The requests and the think time slept after each of them, the end to end journey time
+    IncludeThinkTime,
+}
+/// How chains are picked
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DSelection {
+    /// Picked with probability `weight / sum(weight)`
+    #[default]
+    Proportion,
+    /// Picked with probability `(weight / cost) / sum(weight / cost)`, so cheaper chains get
+    /// more traffic
+    CostWeighted,
+}
+/// Thresholds a named request has to meet, latencies are in milliseconds
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DSla {
+    #[serde(default)]
+    pub mean_ms: Option<f64>,
+    #[serde(default)]
+    pub p95_ms: Option<f64>,
+    #[serde(default)]
+    pub p99_ms: Option<f64>,
+    /// Highest fraction of chains that may fail, in [0, 1]
+    #[serde(default)]
+    pub error_rate: Option<f64>,
+}
+pub fn default_max_redirects() -> u32 {
+    10
+}
+pub fn default_cost() -> f64 {
+    1.0
+}
+pub fn default_max_drift_factor() -> f64 {
+    4.0
+}
+pub fn default_drain_body() -> bool {
+    true
+}
+pub fn default_max_body_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+pub fn default_percentiles() -> Vec<f64> {
+    vec![95.0, 99.0]
+}
+
+impl DRequestGroup {
+    /// The config as yaml that loads back into the same config. With `redact` the password of
+    /// every url and the values of query parameters named like a secret, see
+    /// [`is_secret_name`], are replaced with `REDACTED`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{config::DRequestGroup, request::{resolve_config, RequestGroup}};
+    /// use std::convert::TryFrom;
+    ///
+    /// let mut config: DRequestGroup = serde_yaml::from_str(
+    ///     "base_url: http://localhost:8080/
+    /// tls_min_version: 1.2
+    /// requests:
+    ///   - proportion: 2
+    ///     name: search
+    ///     requests:
+    ///       - {url: 'search?q=shoes&api_key=abc', delay: {type: uniform, min_s: 1, max_s: 2}}
+    /// number_of_requests: 10
+    /// duration_s: 1",
+    /// )
+    /// .unwrap();
+    /// resolve_config(&mut config, false).unwrap();
+    /// let dumped = config.to_yaml(false);
+    /// // Defaults are written out and urls are resolved against the base url
+    /// assert!(dumped.contains("max_redirects: 10"));
+    /// assert!(dumped.contains("http://localhost:8080/search?q=shoes&api_key=abc"));
+    /// // Loading the dump gives back the same config
+    /// let mut reloaded: DRequestGroup = serde_yaml::from_str(&dumped).unwrap();
+    /// resolve_config(&mut reloaded, false).unwrap();
+    /// assert_eq!(reloaded.to_yaml(false), dumped);
+    /// assert!(RequestGroup::try_from(reloaded).is_ok());
+    ///
+    /// let redacted = config.to_yaml(true);
+    /// assert!(redacted.contains("search?q=shoes&api_key=REDACTED"));
+    /// ```
+    pub fn to_yaml(&self, redact: bool) -> String {
+        let mut config = self.clone();
+        if redact {
+            config.redact();
+        }
+        serde_yaml::to_string(&config).expect("a config always serializes to yaml")
+    }
+    fn redact(&mut self) {
+        for url in self.base_url.iter_mut().chain(self.proxy.iter_mut()) {
+            *url = redact_url(url);
+        }
+        if let Some(health_check) = self.health_check.as_mut() {
+            health_check.url = redact_url(&health_check.url);
+        }
+        for ranked in self.requests.iter_mut() {
+            for request in ranked.requests.iter_mut() {
+                request.url = redact_url(&request.url);
+                for url in request.urls.iter_mut().flatten() {
+                    *url = redact_url(url);
+                }
+                for url in request.proxy.iter_mut() {
+                    *url = redact_url(url);
+                }
+                for (name, values) in request.query_params.iter_mut() {
+                    if is_secret_name(name) {
+                        *values = vec![REDACTED.to_string()];
+                    }
+                }
+            }
+        }
+    }
+}
+/// Stands in for a redacted secret
+const REDACTED: &str = "REDACTED";
+/// Whether a query parameter holds a secret going by its name, any name containing `token`,
+/// `secret`, `password`, `key`, `auth` or `signature` does
+pub fn is_secret_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["token", "secret", "password", "key", "auth", "signature"]
+        .iter()
+        .any(|secret| name.contains(secret))
+}
+/// Replaces the password of `url` and the values of its secret query parameters
+fn redact_url(url: &str) -> String {
+    let (url, fragment) = match url.find('#') {
+        Some(at) => url.split_at(at),
+        None => (url, ""),
+    };
+    let (url, query) = match url.find('?') {
+        Some(at) => (&url[..at], Some(&url[at + 1..])),
+        None => (url, None),
+    };
+    let mut redacted = match url.find("://") {
+        Some(scheme_end) => {
+            let authority_start = scheme_end + 3;
+            let authority_end = url[authority_start..]
+                .find('/')
+                .map_or(url.len(), |end| authority_start + end);
+            let authority = &url[authority_start..authority_end];
+            match authority.rfind('@') {
+                Some(at) if authority[..at].contains(':') => {
+                    let user = &authority[..authority[..at].find(':').unwrap_or(at)];
+                    format!(
+                        "{}{}:{}{}{}",
+                        &url[..authority_start],
+                        user,
+                        REDACTED,
+                        &authority[at..],
+                        &url[authority_end..]
+                    )
+                }
+                _ => url.to_string(),
+            }
+        }
+        None => url.to_string(),
+    };
+    if let Some(query) = query {
+        let pairs = query.split('&').map(|pair| match pair.split_once('=') {
+            Some((name, _)) if is_secret_name(name) => format!("{}={}", name, REDACTED),
+            _ => pair.to_string(),
+        });
+        redacted.push('?');
+        redacted.push_str(&pairs.collect::<Vec<_>>().join("&"));
+    }
+    redacted + fragment
+}
+
+/// Deserializes a config that has had its includes resolved. Unknown keys are silently
+/// dropped unless `strict` is set, in which case they are an error naming every unknown key.
+///
+/// # Examples
+///
+/// ```
+/// use amawk::config::parse_config;
+///
+/// let config: serde_yaml::Value = serde_yaml::from_str(
+///     "requests: []\nnumber_of_requests: 1\nduration_s: 1\nnumber_of_request: 2\n",
+/// )
+/// .unwrap();
+/// assert!(parse_config(config.clone(), false).is_ok());
+/// let error = parse_config(config, true).unwrap_err();
+/// assert!(error.to_string().contains("number_of_request"));
+/// ```
+pub fn parse_config(
+    config: serde_yaml::Value,
+    strict: bool,
+) -> Result<DRequestGroup, Box<dyn std::error::Error + Send + Sync>> {
+    let mut unknown = vec![];
+    let parsed: Result<DRequestGroup, _> =
+        serde_ignored::deserialize(config, |path| unknown.push(path.to_string()));
+    // A misspelt key often also shows up as a missing field, the unknown key is the more
+    // useful error
+    if strict && !unknown.is_empty() {
+        return Err(format!("unknown config keys: {}", unknown.join(", ")).into());
+    }
+    Ok(parsed?)
+}
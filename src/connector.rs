@@ -0,0 +1,478 @@
+use crate::{
+    config::{DAddressFamily, DTlsVersion},
+    request::RequestGroup,
+};
+use hyper::{
+    client::{
+        connect::{
+            dns::{GaiResolver, Name},
+            Connected, Connection,
+        },
+        HttpConnector,
+    },
+    service::Service,
+    Body, Client, Uri,
+};
+use hyper_tls::HttpsConnector;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::TcpStream,
+    sync::Semaphore,
+};
+
+/// Client of the requests of a run that share a proxy
+pub type HttpClient = Client<CountingConnector<HttpsConnector<ProxyConnector>>, Body>;
+
+/// Resolves names like [`GaiResolver`] but only keeps the addresses of one family
+#[derive(Clone)]
+pub struct FamilyResolver {
+    inner: GaiResolver,
+    family: DAddressFamily,
+    lookups: DnsLookups,
+}
+/// Number of lookups a [`FamilyResolver`] and its clones made per host. Hosts that are IP
+/// addresses are never looked up.
+#[derive(Clone, Debug, Default)]
+pub struct DnsLookups(Arc<Mutex<BTreeMap<String, u64>>>);
+impl DnsLookups {
+    /// Lookups made so far by host
+    pub fn by_host(&self) -> BTreeMap<String, u64> {
+        self.0.lock().unwrap().clone()
+    }
+}
+/// A name resolved to addresses, none of which are in the selected family
+#[derive(Debug)]
+pub struct NoAddressInFamily {
+    pub host: String,
+    pub family: DAddressFamily,
+}
+impl std::fmt::Display for NoAddressInFamily {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} has no {} address", self.host, self.family)
+    }
+}
+impl std::error::Error for NoAddressInFamily {}
+impl Service<Name> for FamilyResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, io::Error>> + Send>>;
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.inner.poll_ready(cx)
+    }
+    fn call(&mut self, name: Name) -> Self::Future {
+        let family = self.family;
+        let host = name.as_str().to_string();
+        *self
+            .lookups
+            .0
+            .lock()
+            .unwrap()
+            .entry(host.clone())
+            .or_default() += 1;
+        let resolve = self.inner.call(name);
+        Box::pin(async move {
+            let addrs = resolve
+                .await?
+                .filter(|addr| family.allows(&addr.ip()))
+                .collect::<Vec<_>>();
+            if addrs.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    NoAddressInFamily { host, family },
+                ));
+            }
+            Ok(addrs.into_iter())
+        })
+    }
+}
+
+/// Connects like [`HttpConnector`], or through `proxy` if one is set. Plain http requests
+/// are sent to the proxy in absolute form, https requests get a `CONNECT` tunnel that the
+/// TLS layer above then runs over.
+#[derive(Clone)]
+pub struct ProxyConnector {
+    inner: HttpConnector<FamilyResolver>,
+    proxy: Option<Uri>,
+}
+/// A proxy answered a `CONNECT` with something other than 200
+#[derive(Debug)]
+pub struct ProxyTunnelRefused {
+    pub proxy: String,
+    /// Status line of the proxy's response
+    pub response: String,
+}
+impl std::fmt::Display for ProxyTunnelRefused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "proxy {} refused the tunnel: {}",
+            self.proxy, self.response
+        )
+    }
+}
+impl std::error::Error for ProxyTunnelRefused {}
+impl Service<Uri> for ProxyConnector {
+    type Response = ProxyStream;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    type Future = Pin<Box<dyn Future<Output = Result<ProxyStream, Self::Error>> + Send>>;
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let proxy = self.proxy.clone();
+        let connecting = self
+            .inner
+            .call(proxy.clone().unwrap_or_else(|| uri.clone()));
+        Box::pin(async move {
+            let mut inner = connecting.await?;
+            let proxied = match proxy {
+                Some(proxy) if uri.scheme_str() == Some("https") => {
+                    tunnel(&mut inner, &proxy, &uri).await?;
+                    false
+                }
+                proxy => proxy.is_some(),
+            };
+            Ok(ProxyStream { inner, proxied })
+        })
+    }
+}
+/// Asks the proxy on `stream` to open a tunnel to the host of `uri`
+async fn tunnel(
+    stream: &mut TcpStream,
+    proxy: &Uri,
+    uri: &Uri,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let authority = format!(
+        "{}:{}",
+        uri.host().unwrap_or_default(),
+        uri.port_u16().unwrap_or(443)
+    );
+    stream
+        .write_all(format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", authority).as_bytes())
+        .await?;
+    // The server does not talk before the TLS handshake, so everything read is the response
+    let mut response = vec![];
+    let mut buf = [0; 1024];
+    while !response.windows(4).any(|window| window == b"\r\n\r\n") {
+        if response.len() > 16 * 1024 {
+            return Err(format!("proxy {} sent an oversized CONNECT response", proxy).into());
+        }
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Err(format!("proxy {} closed the connection during CONNECT", proxy).into());
+        }
+        response.extend_from_slice(&buf[..read]);
+    }
+    let status_line = String::from_utf8_lossy(&response)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    if status_line.split(' ').nth(1) != Some("200") {
+        return Err(ProxyTunnelRefused {
+            proxy: proxy.to_string(),
+            response: status_line,
+        }
+        .into());
+    }
+    Ok(())
+}
+/// Connection made by a [`ProxyConnector`], marked as proxied when hyper has to send
+/// requests over it in absolute form
+pub struct ProxyStream {
+    inner: TcpStream,
+    proxied: bool,
+}
+impl Connection for ProxyStream {
+    fn connected(&self) -> Connected {
+        self.inner.connected().proxy(self.proxied)
+    }
+}
+impl AsyncRead for ProxyStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+impl AsyncWrite for ProxyStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Wraps a connector and counts the connections it establishes, optionally limiting how
+/// many are being established at once
+#[derive(Clone)]
+pub struct CountingConnector<C> {
+    inner: C,
+    established: Arc<AtomicU64>,
+    /// Permits to establish a connection, unlimited if `None`
+    handshakes: Option<Arc<Semaphore>>,
+    connecting: Arc<AtomicU64>,
+    max_connecting: Arc<AtomicU64>,
+}
+impl<C> CountingConnector<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            established: Arc::new(AtomicU64::new(0)),
+            handshakes: None,
+            connecting: Arc::new(AtomicU64::new(0)),
+            max_connecting: Arc::new(AtomicU64::new(0)),
+        }
+    }
+    /// Lets at most `limit` connections be established at once by this connector and its
+    /// clones
+    pub fn with_handshake_limit(mut self, limit: usize) -> Self {
+        self.handshakes = Some(Arc::new(Semaphore::new(limit)));
+        self
+    }
+    /// Number of connections established so far by this connector and its clones
+    pub fn established(&self) -> u64 {
+        self.established.load(Ordering::Relaxed)
+    }
+    /// Most connections this connector and its clones were establishing at once, failed
+    /// attempts included
+    pub fn max_concurrent_handshakes(&self) -> u64 {
+        self.max_connecting.load(Ordering::Relaxed)
+    }
+    /// Shares the counts and the handshake limit of `other`
+    fn sharing<D>(inner: C, other: &CountingConnector<D>) -> Self {
+        Self {
+            inner,
+            established: other.established.clone(),
+            handshakes: other.handshakes.clone(),
+            connecting: other.connecting.clone(),
+            max_connecting: other.max_connecting.clone(),
+        }
+    }
+}
+/// Counts a connection as being established until dropped
+struct Connecting(Arc<AtomicU64>);
+impl Drop for Connecting {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+impl<C> Service<Uri> for CountingConnector<C>
+where
+    C: Service<Uri>,
+    C::Future: Send + 'static,
+{
+    type Response = TrackedStream<C::Response>;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, C::Error>> + Send>>;
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let established = self.established.clone();
+        let handshakes = self.handshakes.clone();
+        let (count, max) = (self.connecting.clone(), self.max_connecting.clone());
+        let connecting = self.inner.call(uri);
+        Box::pin(async move {
+            // The inner connector does nothing until polled, so waiting for a permit here
+            // holds back the whole connection setup
+            let _permit = match &handshakes {
+                Some(handshakes) => Some(
+                    handshakes
+                        .acquire()
+                        .await
+                        .expect("the handshake semaphore is never closed"),
+                ),
+                None => None,
+            };
+            max.fetch_max(count.fetch_add(1, Ordering::Relaxed) + 1, Ordering::Relaxed);
+            let _connecting = Connecting(count);
+            let connection = connecting.await?;
+            established.fetch_add(1, Ordering::Relaxed);
+            Ok(TrackedStream {
+                inner: connection,
+                used: ConnectionUse::default(),
+            })
+        })
+    }
+}
+
+/// Attached to every response, tells whether the connection had already served a response
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionUse(Arc<AtomicBool>);
+impl ConnectionUse {
+    /// Marks the connection as used and returns true if it already was
+    pub fn mark_used(&self) -> bool {
+        self.0.swap(true, Ordering::Relaxed)
+    }
+}
+
+/// Connection made by a [`CountingConnector`], adds a [`ConnectionUse`] to its responses
+pub struct TrackedStream<T> {
+    inner: T,
+    used: ConnectionUse,
+}
+impl<T: Connection> Connection for TrackedStream<T> {
+    fn connected(&self) -> Connected {
+        self.inner.connected().extra(self.used.clone())
+    }
+}
+impl<T: AsyncRead + Unpin> AsyncRead for TrackedStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+impl<T: AsyncWrite + Unpin> AsyncWrite for TrackedStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+fn protocol(version: DTlsVersion) -> native_tls::Protocol {
+    match version {
+        DTlsVersion::Tls10 => native_tls::Protocol::Tlsv10,
+        DTlsVersion::Tls11 => native_tls::Protocol::Tlsv11,
+        DTlsVersion::Tls12 => native_tls::Protocol::Tlsv12,
+        DTlsVersion::Tls13 => native_tls::Protocol::Tlsv13,
+    }
+}
+/// A client per proxy used by a group, so connections through different proxies are never
+/// pooled together
+#[derive(Clone)]
+pub struct Clients(HashMap<Option<Uri>, HttpClient>);
+impl Clients {
+    /// The client that connects through `proxy`, or directly if it is `None`
+    pub fn get(&self, proxy: Option<&Uri>) -> &HttpClient {
+        self.0
+            .get(&proxy.cloned())
+            .expect("a client is built for every proxy of the group")
+    }
+}
+/// Builds the clients used for a run along with handles to their connection and DNS lookup
+/// counts
+pub fn build_client(
+    group: &RequestGroup,
+) -> (
+    Clients,
+    CountingConnector<HttpsConnector<ProxyConnector>>,
+    DnsLookups,
+) {
+    let lookups = DnsLookups::default();
+    let mut http = HttpConnector::new_with_resolver(FamilyResolver {
+        inner: GaiResolver::new(),
+        family: group.address_family,
+        lookups: lookups.clone(),
+    });
+    http.enforce_http(false);
+    let mut tls = native_tls::TlsConnector::builder();
+    if let Some(version) = group.tls_min_version {
+        tls.min_protocol_version(Some(protocol(version)));
+    }
+    if let Some(version) = group.tls_max_version {
+        tls.max_protocol_version(Some(protocol(version)));
+    }
+    // Building only fails if the platform's TLS library is unusable, which is what
+    // `HttpsConnector::new` panics on as well
+    let tls = tokio_native_tls::TlsConnector::from(
+        tls.build().expect("failed to build the TLS connector"),
+    );
+    let mut builder = Client::builder();
+    if let Some(max_idle) = group.pool_max_idle_per_host {
+        builder.pool_max_idle_per_host(max_idle);
+    }
+    if let Some(idle_timeout) = group.pool_idle_timeout {
+        builder.pool_idle_timeout(idle_timeout);
+    }
+    let proxies = std::iter::once(group.proxy.clone())
+        .chain(
+            group
+                .requests
+                .iter()
+                .flat_map(|ranked| ranked.requests.iter().map(|request| request.proxy.clone())),
+        )
+        .collect::<HashSet<_>>();
+    // The connectors of every proxy share one connection count and handshake limit
+    let mut direct = CountingConnector::new(HttpsConnector::from((
+        ProxyConnector {
+            inner: http.clone(),
+            proxy: None,
+        },
+        tls.clone(),
+    )));
+    if let Some(limit) = group.max_concurrent_handshakes {
+        direct = direct.with_handshake_limit(limit);
+    }
+    let clients = proxies
+        .into_iter()
+        .map(|proxy| {
+            let connector = CountingConnector::sharing(
+                HttpsConnector::from((
+                    ProxyConnector {
+                        inner: http.clone(),
+                        proxy: proxy.clone(),
+                    },
+                    tls.clone(),
+                )),
+                &direct,
+            );
+            (proxy, builder.build(connector))
+        })
+        .collect();
+    (Clients(clients), direct, lookups)
+}
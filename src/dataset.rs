@@ -0,0 +1,92 @@
+use crate::config::DDataSelection;
+use rand::{rngs::StdRng, Rng};
+use serde_json::Value;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Rows of a `data_file`, every chain execution binds one of them for the body templates of
+/// its steps
+#[derive(Clone, Debug)]
+pub struct Dataset {
+    rows: Arc<Vec<Value>>,
+    selection: DDataSelection,
+    /// Row the next round robin pick returns, shared by every clone
+    next: Arc<AtomicUsize>,
+}
+impl Dataset {
+    /// Reads a json file holding an array of objects, the only format supported
+    pub fn load(
+        path: &str,
+        selection: DDataSelection,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if !path.ends_with(".json") {
+            return Err(format!("data_file {} is not a .json file", path).into());
+        }
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read data_file {}: {}", path, err))?;
+        let rows: Vec<Value> = serde_json::from_str(&contents)
+            .map_err(|err| format!("data_file {} is not a json array: {}", path, err))?;
+        Self::new(rows, selection).map_err(|err| format!("data_file {}: {}", path, err).into())
+    }
+    /// Builds a dataset from rows that are already in memory, every row has to be an object
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{config::DDataSelection, dataset::Dataset};
+    /// use rand::{rngs::StdRng, SeedableRng};
+    /// use serde_json::json;
+    ///
+    /// let data = Dataset::new(vec![json!({"id": 1}), json!({"id": 2})], DDataSelection::RoundRobin)
+    ///     .unwrap();
+    /// let mut rng = StdRng::seed_from_u64(1);
+    /// // Round robin starts over after the last row
+    /// let picked = (0..3).map(|_| data.pick(&mut rng)["id"].clone()).collect::<Vec<_>>();
+    /// assert_eq!(picked, vec![json!(1), json!(2), json!(1)]);
+    /// assert!(Dataset::new(vec![json!(1)], DDataSelection::Random).is_err());
+    /// ```
+    pub fn new(
+        rows: Vec<Value>,
+        selection: DDataSelection,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if rows.is_empty() {
+            return Err("has no rows".into());
+        }
+        if let Some(row) = rows.iter().position(|row| !row.is_object()) {
+            return Err(format!("row {} is not an object", row).into());
+        }
+        Ok(Self {
+            rows: Arc::new(rows),
+            selection,
+            next: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+    /// Row for the next chain execution, random picks are drawn from `rng`
+    pub fn pick(&self, rng: &mut StdRng) -> &Value {
+        let row = match self.selection {
+            DDataSelection::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            DDataSelection::Random => rng.gen_range(0..self.rows.len()),
+        };
+        &self.rows[row % self.rows.len()]
+    }
+    /// The row a round robin dataset starts with
+    pub fn first(&self) -> &Value {
+        &self.rows[0]
+    }
+    /// Fails with the first row that lacks one of `fields`, written like template tokens
+    pub fn check_fields(&self, fields: &[String]) -> Result<(), String> {
+        for field in fields.iter() {
+            let path = field.split('.').map(str::to_string).collect::<Vec<_>>();
+            if let Some(row) = self
+                .rows
+                .iter()
+                .position(|row| crate::template::lookup(row, &path).is_none())
+            {
+                return Err(format!("row {} has no {}", row, field));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use serde_yaml::Value;
+use std::path::{Path, PathBuf};
+
+/// Replaces the `include` key of `config` with the contents of the files it lists. Paths are
+/// relative to `dir`, the directory of the file `config` was read from. Included files may
+/// include other files, `stack` holds the files currently being included and is used to
+/// detect circular includes.
+///
+/// Included files are merged in order and the including file is merged last, so local
+/// definitions take precedence:
+/// - maps are merged key by key
+/// - lists are concatenated, items from included files come first
+/// - any other value is replaced
+pub fn resolve_includes(
+    mut config: Value,
+    dir: &Path,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+    let includes = match config.as_mapping_mut() {
+        Some(mapping) => mapping.remove(&Value::from("include")),
+        None => None,
+    };
+    let includes = match includes {
+        Some(Value::Sequence(includes)) => includes,
+        Some(Value::String(include)) => vec![Value::String(include)],
+        Some(_) => return Err("include must be a path or a list of paths".into()),
+        None => return Ok(config),
+    };
+    let mut merged = Value::Null;
+    for include in includes.iter() {
+        let path = include
+            .as_str()
+            .ok_or("include must be a path or a list of paths")?;
+        let path = dir
+            .join(path)
+            .canonicalize()
+            .map_err(|err| format!("failed to include {}: {}", path, err))?;
+        if stack.contains(&path) {
+            let chain = stack
+                .iter()
+                .chain(std::iter::once(&path))
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(format!("circular include: {}", chain).into());
+        }
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|err| format!("failed to include {}: {}", path.display(), err))?;
+        let included: Value = serde_yaml::from_str(&contents)?;
+        stack.push(path.clone());
+        let included = resolve_includes(
+            included,
+            path.parent().unwrap_or_else(|| Path::new(".")),
+            stack,
+        )?;
+        stack.pop();
+        merged = merge(merged, included);
+    }
+    Ok(merge(merged, config))
+}
+/// Merges `local` on top of `base`
+fn merge(base: Value, local: Value) -> Value {
+    match (base, local) {
+        (Value::Mapping(mut base), Value::Mapping(local)) => {
+            for (key, value) in local {
+                let value = match base.remove(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => value,
+                };
+                base.insert(key, value);
+            }
+            Value::Mapping(base)
+        }
+        (Value::Sequence(mut base), Value::Sequence(local)) => {
+            base.extend(local);
+            Value::Sequence(base)
+        }
+        (_, local) => local,
+    }
+}
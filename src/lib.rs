@@ -0,0 +1,22 @@
+//! Amawk is a simple HTTP load tester. Load tests are described by a [`request::RequestGroup`],
+//! either parsed from a YML [`config::DRequestGroup`] or built with [`builder::RequestGroupBuilder`],
+//! and run with [`run()`].
+pub mod aggregate;
+pub mod baseline;
+pub mod builder;
+pub mod capacity;
+pub mod compression;
+pub mod config;
+pub mod connector;
+pub mod dataset;
+pub mod include;
+pub mod memory;
+pub mod output;
+pub mod prometheus;
+pub mod request;
+pub mod run;
+pub mod sla;
+pub mod stats;
+pub mod template;
+
+pub use run::{run, RunOptions, RunResult};
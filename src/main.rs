@@ -1,372 +1,1193 @@
-use clap::{App, Arg};
+use amawk::{
+    aggregate::Aggregator,
+    baseline::{compare, Baseline},
+    capacity::find_capacity,
+    config::{parse_config, DRequestGroup},
+    include::resolve_includes,
+    output::{JsonFormatter, OutputFormatter, OutputRegistry, StatFormatter, SuiteRepeat},
+    prometheus,
+    request::{resolve_config, RequestGroup},
+    run,
+    run::{check_dns, check_health, probe, RequestStatus, SnapshotRequest, StepResult},
+    stats::{
+        repeat_summary, suite_summary, LatencyUnit, RepeatSummary, SortBy, Statistics, Summary,
+    },
+    RunOptions, RunResult,
+};
+use clap::{App, Arg, ArgMatches};
 use futures::future::join_all;
-use hyper::body::HttpBody as _;
-use hyper::{Client, Uri};
-use hyper_tls::HttpsConnector;
-use rand::distributions::{Distribution, Uniform};
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use std::{
-    collections::{BTreeMap, HashMap},
-    convert::{TryFrom, TryInto},
-    time::{Duration, Instant},
+    convert::TryInto,
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::{
+    fs::File,
+    io::AsyncReadExt,
+    sync::{mpsc, oneshot, watch},
 };
-use tokio::{fs::File, io::AsyncReadExt, time::sleep};
+use tracing_subscriber::EnvFilter;
 
-struct RequestGroup {
-    requests: Vec<RankedRequest>,
-    /// Total number of requests to send
-    number_of_requests: u32,
-    /// Duration of time over which to smear requests
-    duration: Duration,
-}
-struct RankedRequest {
-    proportion: usize,
-    /// used to tabulate statists
-    name: String,
-    requests: Vec<Request>,
+/// Reads a config file, `-` reads from stdin. Unknown keys are an error if `strict` is set
+async fn load_config(
+    config_file_path: &str,
+    strict: bool,
+) -> Result<DRequestGroup, Box<dyn std::error::Error + Send + Sync>> {
+    let mut file_contents = String::new();
+    let mut include_stack = vec![];
+    let config_dir = if config_file_path == "-" {
+        tokio::io::stdin()
+            .read_to_string(&mut file_contents)
+            .await?;
+        Path::new(".").to_path_buf()
+    } else {
+        let mut file = File::open(config_file_path).await?;
+        file.read_to_string(&mut file_contents).await?;
+        let path = Path::new(config_file_path).canonicalize()?;
+        include_stack.push(path.clone());
+        path.parent()
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf()
+    };
+    let config = resolve_includes(
+        serde_yaml::from_str(&file_contents)?,
+        &config_dir,
+        &mut include_stack,
+    )?;
+    parse_config(config, strict)
 }
 
-#[derive(Clone, Debug)]
-struct Request {
-    pub uri: Uri,
-    pub delay: Duration,
-}
-/// Deserializble Request
-#[derive(Clone, Debug, Deserialize)]
-pub struct DRequest {
-    pub url: String,
-    pub delay_s: f64,
-}
-#[derive(Clone, Debug, Deserialize)]
-pub struct DRankedRequest {
-    pub proportion: usize,
-    pub name: String,
-    pub requests: Vec<DRequest>,
+/// Re-reads the config file on every SIGHUP and sends its `number_of_requests` to the
+/// running group. Configs that fail to load are reported and ignored.
+#[cfg(unix)]
+fn watch_reload(
+    config_file_path: String,
+    strict: bool,
+    number_of_requests: u32,
+) -> Result<watch::Receiver<u32>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    let (sender, receiver) = watch::channel(number_of_requests);
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64();
+            let config = match load_config(&config_file_path, strict).await {
+                Ok(config) => config,
+                Err(e) => {
+                    eprintln!(
+                        "[{:.3}] failed to reload {}: {}",
+                        timestamp, config_file_path, e
+                    );
+                    continue;
+                }
+            };
+            let old = *sender.borrow();
+            if config.number_of_requests != old {
+                eprintln!(
+                    "[{:.3}] reloaded {}: number_of_requests {} -> {}",
+                    timestamp, config_file_path, old, config.number_of_requests
+                );
+                if sender.send(config.number_of_requests).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    Ok(receiver)
 }
-#[derive(Clone, Debug, Deserialize)]
-pub struct DRequestGroup {
-    pub requests: Vec<DRankedRequest>,
-    /// Total number of requests to send
-    pub number_of_requests: u32,
-    /// Duration of time over which to smear requests
-    pub duration_s: f64,
+#[cfg(not(unix))]
+fn watch_reload(
+    _config_file_path: String,
+    _strict: bool,
+    number_of_requests: u32,
+) -> Result<watch::Receiver<u32>, Box<dyn std::error::Error + Send + Sync>> {
+    // No SIGHUP, the sender is dropped straight away so the run never reloads
+    Ok(watch::channel(number_of_requests).1)
 }
-impl TryFrom<&DRankedRequest> for RankedRequest {
-    type Error = Box<dyn std::error::Error + Send + Sync>;
-    fn try_from(request: &DRankedRequest) -> Result<Self, Self::Error> {
-        let mut requests = vec![];
-        for r in request.requests.iter() {
-            let res: Result<Request, _> = r.try_into();
-            match res {
-                Ok(req) => requests.push(req),
-                Err(err) => return Err(err),
+
+/// Answers every SIGUSR1 with the statistics of the results received so far, printed like
+/// the `stat` output to stderr or written as json to `snapshot_file`, replacing the last one
+#[cfg(unix)]
+fn watch_snapshots(
+    stat: StatFormatter,
+    snapshot_file: Option<String>,
+) -> Result<mpsc::UnboundedReceiver<SnapshotRequest>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut user_signal = signal(SignalKind::user_defined1())?;
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while user_signal.recv().await.is_some() {
+            let (reply, snapshot) = oneshot::channel::<Statistics>();
+            if sender.send(reply).is_err() {
+                break;
+            }
+            // The run is over once the request goes unanswered
+            let statistics = match snapshot.await {
+                Ok(statistics) => statistics,
+                Err(_) => break,
+            };
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64();
+            match &snapshot_file {
+                Some(path) => {
+                    let json = serde_json::to_string(&statistics)
+                        .expect("failed to parse into valid json");
+                    match tokio::fs::write(path, json).await {
+                        Ok(()) => eprintln!("[{:.3}] snapshot written to {}", timestamp, path),
+                        Err(e) => eprintln!(
+                            "[{:.3}] failed to write the snapshot to {}: {}",
+                            timestamp, path, e
+                        ),
+                    }
+                }
+                None if stat.status_classes => eprintln!(
+                    "[{:.3}] snapshot\n{:#}",
+                    timestamp,
+                    statistics.display(stat.unit)
+                ),
+                None => eprintln!(
+                    "[{:.3}] snapshot\n{}",
+                    timestamp,
+                    statistics.display(stat.unit)
+                ),
             }
         }
-        Ok(Self {
-            proportion: request.proportion,
-            name: request.name.clone(),
-            requests,
-        })
-    }
+    });
+    Ok(receiver)
 }
-impl TryFrom<DRequestGroup> for RequestGroup {
-    type Error = Box<dyn std::error::Error + Send + Sync>;
-    fn try_from(request: DRequestGroup) -> Result<Self, Self::Error> {
-        let mut requests = vec![];
-        for r in request.requests.iter() {
-            let res: Result<RankedRequest, _> = r.try_into();
-            match res {
-                Ok(req) => requests.push(req),
-                Err(err) => return Err(err),
+#[cfg(not(unix))]
+fn watch_snapshots(
+    _stat: StatFormatter,
+    _snapshot_file: Option<String>,
+) -> Result<mpsc::UnboundedReceiver<SnapshotRequest>, Box<dyn std::error::Error + Send + Sync>> {
+    // No SIGUSR1, the sender is dropped straight away so no snapshot is ever taken
+    Ok(mpsc::unbounded_channel().1)
+}
+
+/// Expands directories into the `.yml` and `.yaml` files they contain, sorted by name
+async fn config_paths(
+    values: clap::Values<'_>,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut paths = vec![];
+    for value in values {
+        if value != "-" && tokio::fs::metadata(value).await?.is_dir() {
+            let mut entries = tokio::fs::read_dir(value).await?;
+            let mut files = vec![];
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if matches!(
+                    path.extension().and_then(|e| e.to_str()),
+                    Some("yml") | Some("yaml")
+                ) {
+                    files.push(path.to_string_lossy().into_owned());
+                }
+            }
+            if files.is_empty() {
+                return Err(format!("{} contains no configs", value).into());
             }
+            files.sort();
+            paths.extend(files);
+        } else {
+            paths.push(value.to_string());
         }
-        Ok(Self {
-            requests,
-            duration: Duration::from_secs_f64(request.duration_s),
-            number_of_requests: request.number_of_requests,
-        })
     }
+    Ok(paths)
 }
 
-impl TryFrom<&DRequest> for Request {
-    type Error = Box<dyn std::error::Error + Send + Sync>;
-    fn try_from(request: &DRequest) -> Result<Self, Self::Error> {
-        Ok(Self {
-            uri: request.url.parse()?,
-            delay: Duration::from_secs_f64(request.delay_s),
-        })
+/// Loads and validates a config, skipping broken named requests with `--lenient`. The
+/// resolved config the group was built from is returned with it.
+async fn load_group(
+    config_file_path: &str,
+    matches: &ArgMatches<'_>,
+) -> Result<(RequestGroup, DRequestGroup), Box<dyn std::error::Error + Send + Sync>> {
+    let mut config = load_config(config_file_path, matches.is_present("strict")).await?;
+    let lenient = matches.is_present("lenient");
+    for warning in resolve_config(&mut config, lenient)?.iter() {
+        eprintln!("warning: {}: {}", config_file_path, warning);
     }
+    let request_group: RequestGroup = config.clone().try_into()?;
+    Ok((request_group, config))
 }
-#[derive(Clone, Debug, Serialize, PartialEq, Eq, Hash)]
-enum RequestStatus {
-    Sucess { delay: Duration, url: String },
-    HttpParseError,
-    InvalidStatusCode,
-    Timeout,
-    Other(Option<String>),
+
+/// Points of the cumulative distribution printed by `-o cdf` when `--cdf` is not given
+const DEFAULT_CDF_POINTS: usize = 100;
+
+/// A fresh id for every invocation, so its output files can be told apart from others
+fn new_run_id() -> String {
+    uuid::Builder::from_random_bytes(rand::random())
+        .into_uuid()
+        .to_string()
 }
-impl std::fmt::Display for RequestStatus {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::Sucess { delay, url } => format!(
-                    "Success{{duration: {}, url: {} }}",
-                    delay.as_secs_f64(),
-                    url
-                ),
-                Self::HttpParseError => "HttpParseError".to_string(),
-                Self::InvalidStatusCode => "Invalid Status Code".to_string(),
-                Self::Timeout => "Timeout".to_string(),
-                Self::Other(s) => format!("Other error: {}", if let Some(s) = s { s } else { "" }),
-            }
-        )
+
+/// The seconds given to `--<name>` as a duration, an error names the flag if they are not a
+/// number or do not fit a duration
+fn seconds(
+    matches: &ArgMatches<'_>,
+    name: &str,
+) -> Result<Option<Duration>, Box<dyn std::error::Error + Send + Sync>> {
+    match matches.value_of(name) {
+        Some(value) => value
+            .parse()
+            .ok()
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .map(Some)
+            .ok_or_else(|| {
+                format!("--{} of {} is not a valid number of seconds", name, value).into()
+            }),
+        None => Ok(None),
     }
 }
-async fn run_request_group(group: &RequestGroup) -> HashMap<String, Vec<Vec<RequestStatus>>> {
-    let requests = group
-        .requests
-        .iter()
-        .map(|request| vec![(request.requests.clone(), request.name.clone()); request.proportion])
-        .flatten()
-        .collect::<Vec<_>>();
-    assert_ne!(requests.len(), 0);
-    let mut rng = rand::thread_rng();
-    let distribution = Uniform::from(0..requests.len());
-    let times = (0..group.number_of_requests).map(|_| {
-        (
-            Duration::from_secs_f64(rand::random::<f64>() * group.duration.as_secs_f64()),
-            distribution.sample(&mut rng),
-        )
-    });
-    let mut names = vec![];
-    let mut delay_times = join_all(times.map(|(starting_delay, index)| {
-        names.push(requests[index].1.clone());
-        run_request_chain(starting_delay, &requests[index].0)
+fn run_options(
+    matches: &ArgMatches<'_>,
+    run_id: &str,
+) -> Result<RunOptions, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(RunOptions {
+        run_id: Some(run_id.to_string()),
+        timeout: seconds(matches, "timeout")?,
+        retain_detail: matches.is_present("detail"),
+        per_url: matches.is_present("requests-per-client"),
+        // The cdf output has nothing to print without points
+        cdf_points: match matches.value_of("cdf") {
+            Some(points) => match points.parse()? {
+                0 => return Err("--cdf needs at least 1 point".into()),
+                points => Some(points),
+            },
+            None if matches.value_of("output") == Some("cdf") => Some(DEFAULT_CDF_POINTS),
+            None => None,
+        },
+        group_by: matches.value_of("group-by").map(str::to_string),
+        max_duration: seconds(matches, "max-duration")?,
+        memory_interval: match seconds(matches, "memory-interval")? {
+            Some(interval) if interval.is_zero() => {
+                return Err("--memory-interval must be over 0".into())
+            }
+            interval => interval,
+        },
+        in_flight_interval: match seconds(matches, "in-flight-interval")? {
+            Some(interval) if interval.is_zero() => {
+                return Err("--in-flight-interval must be over 0".into())
+            }
+            interval => interval,
+        },
+        fail_fast: matches.is_present("fail-fast"),
+        sort_by: match matches.value_of("sort-by").unwrap() {
+            "latency" => SortBy::Latency,
+            "errors" => SortBy::Errors,
+            _ => SortBy::Name,
+        },
+        ..Default::default()
+    })
+}
+
+/// The number given to `--<name>`, an error names the flag if it is not one
+fn number(
+    matches: &ArgMatches<'_>,
+    name: &str,
+) -> Result<f64, Box<dyn std::error::Error + Send + Sync>> {
+    let value = matches.value_of(name).unwrap();
+    value
+        .parse()
+        .map_err(|_| format!("--{} of {} is not a number", name, value).into())
+}
+/// `--baseline` and its tolerances
+struct BaselineCheck<'a> {
+    path: &'a str,
+    baseline: Baseline,
+    latency_tolerance: f64,
+    error_tolerance: f64,
+}
+/// Reads `--baseline` and its tolerances, before the run so that a wrong path or tolerance
+/// does not throw the run away
+async fn load_baseline<'a>(
+    matches: &'a ArgMatches<'_>,
+) -> Result<Option<BaselineCheck<'a>>, Box<dyn std::error::Error + Send + Sync>> {
+    let path = match matches.value_of("baseline") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let latency_tolerance = number(matches, "baseline-tolerance")?;
+    let error_tolerance = number(matches, "baseline-error-tolerance")?;
+    let mut contents = String::new();
+    File::open(path)
+        .await
+        .map_err(|err| format!("baseline {}: {}", path, err))?
+        .read_to_string(&mut contents)
+        .await?;
+    let baseline: Baseline =
+        serde_json::from_str(&contents).map_err(|err| format!("baseline {}: {}", path, err))?;
+    Ok(Some(BaselineCheck {
+        path,
+        baseline,
+        latency_tolerance,
+        error_tolerance,
     }))
-    .await;
-    let mut status_out = HashMap::new();
-    for (idx, delay) in delay_times.drain(..).enumerate() {
-        if !status_out.contains_key(&names[idx]) {
-            status_out.insert(names[idx].clone(), vec![]);
-        }
-        status_out.get_mut(&names[idx]).unwrap().push(delay);
-    }
-    return status_out;
 }
-async fn run_request_chain(starting_delay: Duration, requests: &[Request]) -> Vec<RequestStatus> {
-    sleep(starting_delay).await;
-    join_all(requests.iter().map(|req| run_request(req))).await
+
+/// Minimal pass or fail signal for scripts, written whatever the output format is. Fields
+/// are only ever added, `version` changes if one changes meaning or is removed.
+#[derive(Serialize)]
+struct ExitSummary<'a> {
+    version: u32,
+    run_id: &'a str,
+    total: u64,
+    failed: u64,
+    error_rate: f64,
+    p95_ms: f64,
+    /// No SLA failed, there was no regression against the baseline and the run did not
+    /// fail fast
+    passed: bool,
 }
-async fn run_request(request: &Request) -> RequestStatus {
-    let delay = get_url(request.uri.clone()).await;
-    sleep(request.delay).await;
-    delay
+async fn write_exit_summary(
+    path: &str,
+    run_id: &str,
+    summary: &Summary,
+    passed: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let exit_summary = ExitSummary {
+        version: 1,
+        run_id,
+        total: summary.total,
+        failed: summary.failed,
+        error_rate: summary.error_percentage / 100.0,
+        p95_ms: summary.p95.as_secs_f64() * 1000.0,
+        passed,
+    };
+    tokio::fs::write(path, serde_json::to_string(&exit_summary)?).await?;
+    Ok(())
 }
-/// Gets from url and returns time
-async fn get_url(uri: Uri) -> RequestStatus {
-    let https = HttpsConnector::new();
-    let client = Client::builder().build::<_, hyper::Body>(https);
-    let now = Instant::now();
-    let status = client.get(uri.clone()).await;
 
-    if status.is_ok() {
-        let mut resp = status.unwrap();
-        while resp.body_mut().data().await.is_some() {}
-        RequestStatus::Sucess {
-            url: format!("{}", uri),
-            delay: now.elapsed(),
-        }
-    } else {
-        let error = status.err().unwrap();
-        if error.is_parse() {
-            RequestStatus::HttpParseError
-        } else if error.is_timeout() {
-            RequestStatus::Timeout
-        } else if error.is_parse_status() {
-            RequestStatus::InvalidStatusCode
-        } else {
-            RequestStatus::Other(if let Some(cause) = error.into_cause() {
-                Some(cause.to_string())
-            } else {
-                None
-            })
-        }
+const FILE_DESCRIPTOR_HINT: &str =
+    "ran out of file descriptors, raise the limit with `ulimit -n` or lower the concurrency";
+/// Whether requests of the run failed for lack of file descriptors, the library only logs it
+fn ran_out_of_file_descriptors(statistics: &Statistics) -> bool {
+    statistics.clients.iter().any(|client| {
+        client
+            .common_errors
+            .contains(&RequestStatus::ResourceExhausted)
+    })
+}
+/// Describes the step that stopped a `--fail-fast` run
+fn fail_fast_message(failure: &StepResult) -> String {
+    match failure.status_code {
+        Some(status_code) => format!(
+            "fail fast: {} failed with status {}: {}",
+            failure.url, status_code, failure.status
+        ),
+        None => format!("fail fast: {} failed: {}", failure.url, failure.status),
     }
 }
-struct StatisticsClient {
-    pub name: String,
-    pub total: u64,
-    pub average_total_load_time: Duration,
-    pub standard_deviation: Duration,
-    pub number_of_failed_requests: u64,
-    pub common_errors: Vec<RequestStatus>,
+
+/// Chains and failures of every named request and of the whole run, the output of
+/// `--count-only`
+fn count_lines(aggregator: &Aggregator) -> String {
+    let mut clients = aggregator.clients.iter().collect::<Vec<_>>();
+    clients.sort_by(|a, b| a.0.cmp(b.0));
+    let mut lines = clients
+        .iter()
+        .map(|(name, client)| {
+            format!(
+                "{}: {} chains, {} failed",
+                name, client.total, client.failed
+            )
+        })
+        .collect::<Vec<_>>();
+    let total = clients.iter().map(|(_, client)| client.total).sum::<u64>();
+    let failed = clients.iter().map(|(_, client)| client.failed).sum::<u64>();
+    let error_percentage = if total == 0 {
+        0.0
+    } else {
+        failed as f64 / total as f64 * 100.0
+    };
+    lines.push(format!(
+        "total: {} chains, {} failed ({:.2}%)",
+        total, failed, error_percentage
+    ));
+    lines.join("\n")
 }
-struct Statistics {
-    pub clients: Vec<StatisticsClient>,
+
+/// `summary.json` of `--output-dir`
+#[derive(Serialize)]
+struct OutputDirSummary<'a> {
+    run_id: &'a str,
+    configs: Vec<OutputDirConfig<'a>>,
+    summary: &'a Summary,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    repeats: &'a [SuiteRepeat<'a>],
+}
+#[derive(Serialize)]
+struct OutputDirConfig<'a> {
+    config: &'a str,
+    /// Name of the json file of the run in the output directory
+    file: String,
+    summary: &'a Summary,
 }
-impl std::fmt::Display for Statistics {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{:<10}| {:<30} | {:<20} | {:<20} | {:<25} | {:<30}",
-            "name",
-            "total number of requests",
-            "avg load time (s)",
-            "std dev (s)",
-            "number of failed requests",
-            "Common Errors"
-        )?;
-        for c in self.clients.iter() {
-            write!(
-                f,
-                "\n{:<10}| {:<30} | {:<20} | {:<20} | {:<25} | {:<30}",
-                c.name,
-                c.total,
-                c.average_total_load_time.as_secs_f64(),
-                c.standard_deviation.as_secs_f64(),
-                c.number_of_failed_requests,
-                c.common_errors
-                    .iter()
-                    .take(2)
-                    .map(|e| format!("{}", e))
-                    .fold(String::new(), |acc, x| acc + &x)
-            )?
+/// Turns a config path such as `configs/api.yaml` into a file name without an extension
+/// such as `configs_api`, anything but ascii letters, digits, `-` and `_` is replaced
+fn output_file_stem(path: &str) -> String {
+    if path == "-" {
+        return "stdin".to_string();
+    }
+    let path = path
+        .strip_suffix(".yaml")
+        .or_else(|| path.strip_suffix(".yml"))
+        .unwrap_or(path);
+    let stem = path
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+    if stem.is_empty() {
+        "config".to_string()
+    } else {
+        stem
+    }
+}
+/// Writes `<stem>.json` and `<stem>.stat` for every run to `dir` and a `summary.json`
+/// covering all of them, stems that are taken get a number appended
+async fn write_output_dir(
+    dir: &str,
+    run_id: &str,
+    matches: &ArgMatches<'_>,
+    runs: &[(String, &str, &RunResult)],
+    summary: &Summary,
+    repeats: &[SuiteRepeat<'_>],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    tokio::fs::create_dir_all(dir).await?;
+    let mut taken = std::collections::HashSet::from(["summary".to_string()]);
+    let mut configs = vec![];
+    for (stem, label, result) in runs.iter() {
+        let mut unique = stem.clone();
+        let mut n = 1;
+        while !taken.insert(unique.clone()) {
+            n += 1;
+            unique = format!("{}_{}", stem, n);
         }
-        Ok(())
+        let json = JsonFormatter.render(label, result);
+        let stat = stat_formatter(matches).render(label, result) + "\n";
+        let dir = std::path::Path::new(dir);
+        tokio::fs::write(dir.join(format!("{}.json", unique)), json).await?;
+        tokio::fs::write(dir.join(format!("{}.stat", unique)), stat).await?;
+        configs.push(OutputDirConfig {
+            config: label,
+            file: format!("{}.json", unique),
+            summary: &result.statistics.summary,
+        });
     }
+    let summary = serde_json::to_string(&OutputDirSummary {
+        run_id,
+        configs,
+        summary,
+        repeats,
+    })?;
+    tokio::fs::write(std::path::Path::new(dir).join("summary.json"), summary).await?;
+    Ok(())
 }
-fn get_stat(data: &HashMap<String, Vec<Vec<RequestStatus>>>) -> Statistics {
-    let get_chain_status = |s: &[RequestStatus]| {
-        let mut duration = Duration::default();
-        for status in s.iter() {
-            match status {
-                RequestStatus::Sucess { delay, .. } => duration += *delay,
-                RequestStatus::HttpParseError => return RequestStatus::HttpParseError,
-                RequestStatus::Timeout => return RequestStatus::Timeout,
-                RequestStatus::InvalidStatusCode => return RequestStatus::InvalidStatusCode,
-                RequestStatus::Other(s) => return RequestStatus::Other(s.clone()),
-            }
+
+/// Runs every config `repeat` times as an independent group and reports each run along with
+/// a summary of the whole suite and, when repeated, the spread across the runs of each config
+async fn run_suite(
+    matches: &ArgMatches<'_>,
+    run_id: &str,
+    config_paths: &[String],
+    repeat: usize,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    for unsupported in [
+        "list",
+        "only",
+        "skip",
+        "baseline",
+        "prom-file",
+        "raw-log",
+        "count-only",
+        "probe",
+        "check-dns",
+        "dump-config",
+        "dump-config-file",
+    ]
+    .iter()
+    {
+        if matches.is_present(unsupported) {
+            return Err(format!("--{} needs a single config run once", unsupported).into());
+        }
+    }
+    let mut groups = vec![];
+    let mut labels = vec![];
+    let mut stems = vec![];
+    for path in config_paths.iter() {
+        let (group, _) = load_group(path, matches).await?;
+        if group.capacity.is_some() {
+            return Err(
+                format!("{}: capacity searches need a single config run once", path).into(),
+            );
         }
-        RequestStatus::Sucess {
-            delay: duration,
-            url: String::new(),
+        // Every config is checked before anything runs
+        if let Err(err) = check_health(&group, run_options(matches, run_id)?.timeout).await {
+            eprintln!("{}: {}, not starting the suite", path, err);
+            std::process::exit(1);
         }
+        for run in 1..=repeat {
+            groups.push(group.clone());
+            labels.push(if repeat > 1 {
+                format!("{} (run {})", path, run)
+            } else {
+                path.clone()
+            });
+            stems.push(if repeat > 1 {
+                format!("{}_run_{}", output_file_stem(path), run)
+            } else {
+                output_file_stem(path)
+            });
+        }
+    }
+    let start = Instant::now();
+    let results = if matches.is_present("parallel") {
+        let mut runs = vec![];
+        for group in groups.iter() {
+            runs.push(run(group, run_options(matches, run_id)?));
+        }
+        join_all(runs).await
+    } else {
+        let mut results = vec![];
+        for group in groups.iter() {
+            let result = run(group, run_options(matches, run_id)?).await;
+            let failed = result.aggregator.first_failure.is_some();
+            results.push(result);
+            // The configs after the failure are not run at all
+            if failed {
+                break;
+            }
+        }
+        results
     };
-    Statistics {
-        clients: data
+    let summary = suite_summary(
+        &results
             .iter()
-            .map(|(name, requests)| {
-                let num_sucess = requests
-                    .iter()
-                    .map(|r_chain| get_chain_status(r_chain))
-                    .filter_map(|req| match req {
-                        RequestStatus::Sucess { delay, .. } => Some(delay),
-                        _ => None,
-                    })
-                    .count();
-                let errors = requests
-                    .iter()
-                    .map(|r_chain| get_chain_status(r_chain))
-                    .filter_map(|req| match req {
-                        RequestStatus::Sucess { .. } => None,
-                        RequestStatus::HttpParseError => Some(RequestStatus::HttpParseError),
-                        RequestStatus::InvalidStatusCode => Some(RequestStatus::InvalidStatusCode),
-                        RequestStatus::Timeout => Some(RequestStatus::Timeout),
-                        RequestStatus::Other(s) => Some(RequestStatus::Other(s)),
-                    })
-                    .collect::<Vec<_>>();
-
-                let mut error_hashmap: HashMap<RequestStatus, usize> = HashMap::new();
-                for e in errors.iter() {
-                    if error_hashmap.contains_key(e) {
-                        *error_hashmap.get_mut(e).unwrap() += 1;
-                    } else {
-                        error_hashmap.insert(e.clone(), 0);
-                    }
-                }
-                let error_tree: BTreeMap<usize, RequestStatus> =
-                    error_hashmap.iter().map(|(k, v)| (*v, k.clone())).collect();
-                let common_errors = error_tree
-                    .iter()
-                    .map(|(_key, error)| error.clone())
-                    .collect();
-                let mean = requests
-                    .iter()
-                    .map(|r_chain| get_chain_status(r_chain))
-                    .filter_map(|req| match req {
-                        RequestStatus::Sucess { delay, .. } => Some(delay),
-                        _ => None,
-                    })
-                    .fold(Duration::default(), |acc, req| acc + req)
-                    / num_sucess as u32;
-                let standard_deviation: f64 = (requests
-                    .iter()
-                    .map(|r_chain| get_chain_status(r_chain))
-                    .filter_map(|req| match req {
-                        RequestStatus::Sucess { delay, .. } => Some(delay),
-                        _ => None,
-                    })
-                    .map(|r| (r.as_secs_f64() - mean.as_secs_f64()).powi(2))
-                    .sum::<f64>()
-                    / (num_sucess as f64))
-                    .sqrt();
-                let number_of_failed_requests = requests
-                    .iter()
-                    .map(|r_chain| get_chain_status(r_chain))
-                    .filter_map(|req| match req {
-                        RequestStatus::Sucess { .. } => None,
-                        _ => Some(()),
-                    })
-                    .count() as u64;
-                let total = requests.len() as u64;
-                StatisticsClient {
-                    name: name.clone(),
-                    average_total_load_time: mean,
-                    total,
-                    common_errors,
-                    standard_deviation: Duration::from_secs_f64(standard_deviation),
-                    number_of_failed_requests,
-                }
+            .map(|result| &result.aggregator)
+            .collect::<Vec<_>>(),
+        start.elapsed(),
+    );
+    // A run that failed fast leaves the runs after it out
+    let repeats: Vec<RepeatSummary> = if repeat > 1 {
+        results
+            .chunks(repeat)
+            .map(|runs| {
+                repeat_summary(
+                    &runs
+                        .iter()
+                        .map(|run| &run.statistics.summary)
+                        .collect::<Vec<_>>(),
+                )
             })
-            .collect(),
+            .collect()
+    } else {
+        vec![]
+    };
+    let suite_repeats = config_paths
+        .iter()
+        .zip(repeats.iter())
+        .map(|(path, summary)| SuiteRepeat {
+            config: path,
+            summary: summary.clone(),
+        })
+        .collect::<Vec<_>>();
+    if let Some(dir) = matches.value_of("output-dir") {
+        let runs = stems
+            .into_iter()
+            .zip(labels.iter())
+            .zip(results.iter())
+            .map(|((stem, label), result)| (stem, label.as_str(), result))
+            .collect::<Vec<_>>();
+        write_output_dir(dir, run_id, matches, &runs, &summary, &suite_repeats).await?;
+    }
+    let mut outputs = OutputRegistry::builtin();
+    outputs.register("stat", stat_formatter(matches));
+    let format = matches.value_of("output").unwrap();
+    let runs = labels
+        .iter()
+        .map(String::as_str)
+        .zip(results.iter())
+        .collect::<Vec<_>>();
+    let output = outputs
+        .get(format)
+        .ok_or_else(|| format!("unknown output format {}", format))?
+        .render_suite(&runs, &summary, &suite_repeats);
+    println!("{}", output);
+    let mut sla_failed = false;
+    let mut failed_fast = false;
+    for (path, result) in labels.iter().zip(results.iter()) {
+        if let Some(failure) = &result.aggregator.first_failure {
+            eprintln!("{}: {}", path, fail_fast_message(failure));
+            failed_fast = true;
+        } else if result.statistics.summary.abandoned > 0 {
+            eprintln!(
+                "warning: {}: {} chains were still running at --max-duration or the end of the cooldown and were abandoned",
+                path, result.statistics.summary.abandoned
+            );
+        }
+        if ran_out_of_file_descriptors(&result.statistics) {
+            eprintln!("warning: {}: {}", path, FILE_DESCRIPTOR_HINT);
+        }
+        for name in result.statistics.failed_slas() {
+            eprintln!("{}: sla of {} failed", path, name);
+            sla_failed = true;
+        }
+    }
+    if let Some(path) = matches.value_of("exit-summary-file") {
+        write_exit_summary(path, run_id, &summary, !sla_failed && !failed_fast).await?;
+    }
+    if sla_failed || failed_fast {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// The `stat` output as set by `--status-classes` and `--unit`
+fn stat_formatter(matches: &ArgMatches<'_>) -> StatFormatter {
+    StatFormatter {
+        status_classes: matches.is_present("status-classes"),
+        unit: match matches.value_of("unit").unwrap() {
+            "ms" => LatencyUnit::Milliseconds,
+            "us" => LatencyUnit::Microseconds,
+            _ => LatencyUnit::Seconds,
+        },
     }
 }
+/// Logs to stderr at `level` or as set by `RUST_LOG`, logging is off without either
+fn init_logging(level: Option<&str>) {
+    let filter = match level {
+        Some(level) => EnvFilter::new(format!("amawk={}", level)),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("off")),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+}
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let formats = OutputRegistry::builtin()
+        .names()
+        .into_iter()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
     let matches = App::new("Amawk")
         .version("0.1")
         .author("Nicholas Alexeev")
         .arg(
             Arg::with_name("config")
                 .short("c")
-                .help("YML flile that speficfies tests to run")
+                .help("YML flile that speficfies tests to run, - reads from stdin. Can be given more than once or be a directory to run every config as a suite")
+                .multiple(true)
+                .number_of_values(1)
                 .default_value("config.yml"),
         )
+        .arg(
+            Arg::with_name("parallel")
+                .long("parallel")
+                .help("Runs the configs of a suite at the same time instead of one after another"),
+        )
         .arg(
             Arg::with_name("output")
                 .short("o")
                 .help("Specifies output Format")
-                .possible_value("json")
-                .possible_value("stat")
+                .possible_values(&formats.iter().map(String::as_str).collect::<Vec<_>>())
                 .default_value("json"),
         )
+        .arg(
+            Arg::with_name("detail")
+                .long("detail")
+                .help("Keeps the result of every request and includes it in json output"),
+        )
+        .arg(
+            Arg::with_name("requests-per-client")
+                .long("requests-per-client")
+                .help("Also reports the number of requests and failures for every distinct url"),
+        )
+        .arg(
+            Arg::with_name("baseline")
+                .long("baseline")
+                .takes_value(true)
+                .help(
+                    "Json output of a previous run to compare against, exits with 1 on regression",
+                ),
+        )
+        .arg(
+            Arg::with_name("baseline-tolerance")
+                .long("baseline-tolerance")
+                .default_value("10")
+                .help("Percent mean and p95 latency may grow over the baseline"),
+        )
+        .arg(
+            Arg::with_name("baseline-error-tolerance")
+                .long("baseline-error-tolerance")
+                .default_value("1")
+                .help("Percentage points the error rate may grow over the baseline"),
+        )
+        .arg(
+            Arg::with_name("only")
+                .long("only")
+                .takes_value(true)
+                .use_delimiter(true)
+                .help("Only runs the named requests, separated by commas"),
+        )
+        .arg(
+            Arg::with_name("skip")
+                .long("skip")
+                .takes_value(true)
+                .use_delimiter(true)
+                .help("Does not run the named requests, separated by commas"),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .help("Seconds after which a request, including reading its body, times out"),
+        )
+        .arg(
+            Arg::with_name("max-duration")
+                .long("max-duration")
+                .takes_value(true)
+                .help("Seconds after which the run stops, chains still running are abandoned"),
+        )
+        .arg(
+            Arg::with_name("repeat")
+                .long("repeat")
+                .default_value("1")
+                .help("Runs every config this many times and reports the spread across the runs"),
+        )
+        .arg(
+            Arg::with_name("cdf")
+                .long("cdf")
+                .takes_value(true)
+                .help("Adds the cumulative distribution of the latency of every named request, sampled at this many points, to the json output. -o cdf prints it as csv, with 100 points unless this is set"),
+        )
+        .arg(
+            Arg::with_name("count-only")
+                .long("count-only")
+                .help("Only count chains and failures, print the counts instead of the statistics and exit with 1 if any chain failed"),
+        )
+        .arg(
+            Arg::with_name("fail-fast")
+                .long("fail-fast")
+                .help("Stop the run and exit with 1 at the first request that fails"),
+        )
+        .arg(
+            Arg::with_name("in-flight-interval")
+                .long("in-flight-interval")
+                .takes_value(true)
+                .help("Seconds between samples of the number of requests in flight"),
+        )
+        .arg(
+            Arg::with_name("memory-interval")
+                .long("memory-interval")
+                .takes_value(true)
+                .help("Seconds between samples of amawk's own resident memory, to tell a generator leak from a target problem on long soaks"),
+        )
+        .arg(
+            Arg::with_name("rate-tolerance")
+                .long("rate-tolerance")
+                .default_value("10")
+                .help("Percentage the achieved rate may fall below the target before warning"),
+        )
+        .arg(
+            Arg::with_name("group-by")
+                .long("group-by")
+                .takes_value(true)
+                .help("Aggregates the named requests that share a value for this label"),
+        )
+        .arg(
+            Arg::with_name("check-dns").long("check-dns").help(
+                "Looks up every host the config connects to and fails if one does not resolve, without sending any request",
+            ),
+        )
+        .arg(
+            Arg::with_name("dump-config").long("dump-config").help(
+                "Prints the resolved config as yaml without running it",
+            ),
+        )
+        .arg(
+            Arg::with_name("dump-config-file")
+                .long("dump-config-file")
+                .takes_value(true)
+                .help("Writes the resolved config to this file as yaml before running it"),
+        )
+        .arg(
+            Arg::with_name("redact-secrets").long("redact-secrets").help(
+                "Replaces passwords in urls and secret query parameters in the dumped config",
+            ),
+        )
+        .arg(
+            Arg::with_name("list").long("list").help(
+                "Prints the named requests of the config and their urls without running them",
+            ),
+        )
+        .arg(
+            Arg::with_name("output-dir")
+                .long("output-dir")
+                .takes_value(true)
+                .help("Writes the json and stat output of every config and a summary.json to this directory"),
+        )
+        .arg(
+            Arg::with_name("probe").long("probe").help(
+                "Sends every request once and prints whether it succeeded instead of running, exits with 1 if any failed",
+            ),
+        )
+        .arg(
+            Arg::with_name("raw-log")
+                .long("raw-log")
+                .takes_value(true)
+                .help("Streams every request to this file as a line of json as it completes"),
+        )
+        .arg(
+            Arg::with_name("sort-by")
+                .long("sort-by")
+                .possible_values(&["name", "latency", "errors"])
+                .default_value("name")
+                .help("Order of the named requests in the stat and json output"),
+        )
+        .arg(
+            Arg::with_name("unit")
+                .long("unit")
+                .possible_values(&["s", "ms", "us"])
+                .default_value("s")
+                .help("Unit of the latencies in the stat output, json always uses seconds and nanoseconds"),
+        )
+        .arg(
+            Arg::with_name("status-classes")
+                .long("status-classes")
+                .help("Adds latency percentiles by response status class to the stat output"),
+        )
+        .arg(
+            Arg::with_name("lenient")
+                .long("lenient")
+                .help("Skips named requests that fail to parse with a warning instead of failing"),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .help("Rejects configs with unknown keys instead of ignoring them"),
+        )
+        .arg(
+            Arg::with_name("exit-summary-file")
+                .long("exit-summary-file")
+                .takes_value(true)
+                .help("Writes a small json summary of the run and whether it passed to this file"),
+        )
+        .arg(
+            Arg::with_name("snapshot-file")
+                .long("snapshot-file")
+                .takes_value(true)
+                .help("Writes the statistics taken on SIGUSR1 to this file as json instead of printing them to stderr"),
+        )
+        .arg(
+            Arg::with_name("run-id")
+                .long("run-id")
+                .takes_value(true)
+                .help("Identifies the run in every output instead of a random UUID"),
+        )
+        .arg(
+            Arg::with_name("prom-file")
+                .long("prom-file")
+                .takes_value(true)
+                .help("Writes the final metrics to this file in the prometheus text format"),
+        )
+        .arg(
+            Arg::with_name("log-level")
+                .long("log-level")
+                .takes_value(true)
+                .possible_values(&["error", "warn", "info", "debug", "trace"])
+                .help("Logs at this level to stderr, overrides RUST_LOG"),
+        )
         .get_matches();
-    let config_file_path = matches.value_of("config").unwrap();
-    let mut file = File::open(config_file_path).await?;
-    let mut file_contents = String::new();
-    file.read_to_string(&mut file_contents).await?;
-    let parsed_config: DRequestGroup = serde_yaml::from_str(&file_contents)?;
-    let request_group: RequestGroup = parsed_config.try_into().expect("Failed to Parse");
-    let status = run_request_group(&request_group).await;
+    init_logging(matches.value_of("log-level"));
+    let config_paths = config_paths(matches.values_of("config").unwrap()).await?;
+    let run_id = matches
+        .value_of("run-id")
+        .map_or_else(new_run_id, str::to_string);
+    let repeat: usize = matches.value_of("repeat").unwrap().parse()?;
+    if repeat == 0 {
+        return Err("--repeat must be at least 1".into());
+    }
+    if config_paths.len() > 1 || repeat > 1 {
+        return run_suite(&matches, &run_id, &config_paths, repeat).await;
+    }
+    let config_file_path = config_paths[0].as_str();
+    let strict = matches.is_present("strict");
+    let (mut request_group, mut config) = load_group(config_file_path, &matches).await?;
+    request_group.filter(
+        &matches
+            .values_of("only")
+            .map(|names| names.collect::<Vec<_>>())
+            .unwrap_or_default(),
+        &matches
+            .values_of("skip")
+            .map(|names| names.collect::<Vec<_>>())
+            .unwrap_or_default(),
+    )?;
+    // The dump only has the named requests that are left after filtering
+    config.requests.retain(|ranked| {
+        request_group
+            .requests
+            .iter()
+            .any(|request| request.name == ranked.name)
+    });
+    let redact = matches.is_present("redact-secrets");
+    if let Some(path) = matches.value_of("dump-config-file") {
+        tokio::fs::write(path, config.to_yaml(redact)).await?;
+    }
+    if matches.is_present("dump-config") {
+        print!("{}", config.to_yaml(redact));
+        return Ok(());
+    }
+    if matches.is_present("list") {
+        for ranked in request_group.requests.iter() {
+            println!(
+                "{} (weight {}{})",
+                ranked.name,
+                ranked.weight,
+                if ranked.sequential {
+                    ", sequential"
+                } else {
+                    ""
+                }
+            );
+            for request in ranked.requests.iter() {
+                let uris = request.uris().iter().map(ToString::to_string);
+                println!("    {}", uris.collect::<Vec<_>>().join(" | "));
+            }
+        }
+        return Ok(());
+    }
+    if request_group.capacity.is_some() {
+        for unsupported in [
+            "baseline",
+            "raw-log",
+            "prom-file",
+            "count-only",
+            "output-dir",
+            "exit-summary-file",
+            "snapshot-file",
+        ]
+        .iter()
+        {
+            if matches.is_present(unsupported) {
+                return Err(
+                    format!("--{} does not apply to a capacity search", unsupported).into(),
+                );
+            }
+        }
+    }
+    let reload = if config_file_path == "-" {
+        None
+    } else {
+        Some(watch_reload(
+            config_file_path.to_string(),
+            strict,
+            request_group.number_of_requests,
+        )?)
+    };
+    let raw_log: Option<Box<dyn std::io::Write + Send>> = match matches.value_of("raw-log") {
+        Some(path) => Some(Box::new(std::io::BufWriter::new(std::fs::File::create(
+            path,
+        )?))),
+        None => None,
+    };
+    let snapshots = watch_snapshots(
+        stat_formatter(&matches),
+        matches.value_of("snapshot-file").map(str::to_string),
+    )?;
+    let options = RunOptions {
+        reload,
+        snapshots: Some(snapshots),
+        raw_log,
+        count_only: matches.is_present("count-only"),
+        ..run_options(&matches, &run_id)?
+    };
+    // Only used after the run, but a typo should not throw the run away
+    let rate_tolerance = number(&matches, "rate-tolerance")?;
+    // Before the health check, which already sends a request
+    if matches.is_present("check-dns") {
+        let mut failed = false;
+        for check in check_dns(&request_group).await.iter() {
+            match &check.result {
+                Ok(addresses) => println!(
+                    "ok {}: {}",
+                    check.host,
+                    addresses
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                Err(err) => {
+                    failed = true;
+                    println!("FAIL {}: {}", check.host, err);
+                }
+            }
+        }
+        if failed {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Err(err) = check_health(&request_group, options.timeout).await {
+        eprintln!("{}, not starting the run", err);
+        std::process::exit(1);
+    }
+    if matches.is_present("probe") {
+        let results = probe(&request_group, options.timeout).await;
+        let mut failed = false;
+        for probe in results.iter() {
+            let outcome = if probe.result.failed() { "FAIL" } else { "ok" };
+            failed |= probe.result.failed();
+            match probe.result.status_code {
+                Some(status_code) => println!(
+                    "{} {} {}: {} {}",
+                    outcome, probe.name, probe.result.url, status_code, probe.result.status
+                ),
+                None => println!(
+                    "{} {} {}: {}",
+                    outcome, probe.name, probe.result.url, probe.result.status
+                ),
+            }
+        }
+        if failed {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some(search) = &request_group.capacity {
+        // Every window is a run of its own, the reload receiver only fits one of them
+        drop(options);
+        let capacity = find_capacity(&request_group, search, || {
+            run_options(&matches, &run_id).expect("options were already parsed once")
+        })
+        .await;
+        let mut outputs = OutputRegistry::builtin();
+        outputs.register("stat", stat_formatter(&matches));
+        let format = matches.value_of("output").unwrap();
+        println!(
+            "{}",
+            outputs
+                .get(format)
+                .ok_or_else(|| format!("unknown output format {}", format))?
+                .render_capacity(&capacity)
+        );
+        if capacity.sustainable_rps.is_none() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let baseline = load_baseline(&matches).await?;
+    let result = run(&request_group, options).await;
+    if let Some(err) = &result.aggregator.raw_log_error {
+        eprintln!(
+            "warning: writing the raw log failed, it is incomplete: {}",
+            err
+        );
+    }
+    if matches.is_present("count-only") {
+        println!("{}", count_lines(&result.aggregator));
+        if let Some(failure) = &result.aggregator.first_failure {
+            eprintln!("{}", fail_fast_message(failure));
+        }
+        if result
+            .aggregator
+            .clients
+            .values()
+            .any(|client| client.failed > 0)
+        {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some(dir) = matches.value_of("output-dir") {
+        write_output_dir(
+            dir,
+            &run_id,
+            &matches,
+            &[(
+                output_file_stem(config_file_path),
+                config_file_path,
+                &result,
+            )],
+            &result.statistics.summary,
+            &[],
+        )
+        .await?;
+    }
+    let mut outputs = OutputRegistry::builtin();
+    outputs.register("stat", stat_formatter(&matches));
+    let format = matches.value_of("output").unwrap();
     println!(
         "{}",
-        match matches.value_of("output").unwrap() {
-            "json" => serde_json::to_string(&status).expect("failed to parse into valid json"),
-            "stat" => format!("{}", get_stat(&status)),
-            _ => String::new(),
-        }
+        outputs
+            .get(format)
+            .ok_or_else(|| format!("unknown output format {}", format))?
+            .render(config_file_path, &result)
     );
+    let RunResult {
+        aggregator: status,
+        statistics,
+    } = result;
+    if let Some(failure) = &status.first_failure {
+        eprintln!("{}", fail_fast_message(failure));
+    } else if statistics.summary.abandoned > 0 {
+        eprintln!(
+            "warning: {} chains were still running at --max-duration or the end of the cooldown and were abandoned",
+            statistics.summary.abandoned
+        );
+    }
+    if ran_out_of_file_descriptors(&statistics) {
+        eprintln!("warning: {}", FILE_DESCRIPTOR_HINT);
+    }
+    let shortfall = statistics.summary.rate_shortfall_percentage();
+    // Abandoning chains or failing fast cuts the dispatch short, which is not the generator
+    // falling behind
+    if shortfall > rate_tolerance
+        && statistics.summary.abandoned == 0
+        && status.first_failure.is_none()
+    {
+        eprintln!(
+            "warning: achieved {:.1} rps, {:.1}% below the target of {:.1} rps, the generator could not keep up",
+            statistics.summary.achieved_requests_per_second,
+            shortfall,
+            statistics.summary.target_requests_per_second.unwrap_or_default()
+        );
+    }
+    if let Some(prom_path) = matches.value_of("prom-file") {
+        tokio::fs::write(prom_path, prometheus::render(&statistics)).await?;
+    }
+    let failed_slas = statistics.failed_slas();
+    for name in failed_slas.iter() {
+        eprintln!("sla of {} failed", name);
+    }
+    let mut regressed = false;
+    if let Some(baseline) = baseline {
+        let comparison = compare(
+            &baseline.baseline.statistics,
+            &statistics,
+            baseline.latency_tolerance,
+            baseline.error_tolerance,
+        );
+        eprintln!("{}", comparison);
+        if comparison.regressed() {
+            eprintln!("regression against baseline {}", baseline.path);
+            regressed = true;
+        }
+    }
+    let passed = failed_slas.is_empty() && !regressed && status.first_failure.is_none();
+    if let Some(path) = matches.value_of("exit-summary-file") {
+        write_exit_summary(path, &run_id, &statistics.summary, passed).await?;
+    }
+    if !passed {
+        std::process::exit(1);
+    }
     Ok(())
 }
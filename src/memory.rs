@@ -0,0 +1,25 @@
+/// Resident set size of this process in bytes, `None` where it can not be read. Only Linux
+/// is supported, it is read from `/proc/self/status`.
+///
+/// # Examples
+///
+/// ```
+/// if cfg!(target_os = "linux") {
+///     assert!(amawk::memory::resident_bytes().unwrap() > 0);
+/// }
+/// ```
+pub fn resident_bytes() -> Option<u64> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line
+        .trim_start_matches("VmRSS:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
@@ -0,0 +1,283 @@
+use crate::{
+    capacity::Capacity,
+    run::{RunResult, StepResult},
+    stats::{LatencyUnit, RepeatSummary, Statistics, Summary},
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Turns the result of a run into text, the formats of `-o` implement it and library users can
+/// add their own to an [`OutputRegistry`]. Suites and capacity searches have their own methods,
+/// which a format only needs to implement if the defaults do not fit it.
+///
+/// # Examples
+///
+/// ```
+/// use amawk::{
+///     output::{OutputFormatter, OutputRegistry},
+///     RunResult,
+/// };
+///
+/// /// One `name,p95_ms` line per named request
+/// struct P95Csv;
+/// impl OutputFormatter for P95Csv {
+///     fn render(&self, _config: &str, result: &RunResult) -> String {
+///         result
+///             .statistics
+///             .clients
+///             .iter()
+///             .map(|client| {
+///                 let p95 = client.percentiles.iter().find(|p| p.percentile == 95.0);
+///                 let ms = p95.map(|p| p.latency.as_secs_f64() * 1000.0);
+///                 format!("{},{:.1}", client.name, ms.unwrap_or_default())
+///             })
+///             .collect::<Vec<_>>()
+///             .join("\n")
+///     }
+/// }
+///
+/// let mut registry = OutputRegistry::builtin();
+/// registry.register("p95-csv", P95Csv);
+/// assert!(registry.names().contains(&"p95-csv"));
+/// assert!(registry.get("p95-csv").is_some());
+/// ```
+pub trait OutputFormatter: Send + Sync {
+    /// Renders `result` of the run of the config named `config`
+    fn render(&self, config: &str, result: &RunResult) -> String;
+    /// Renders every run of a suite along with the config it ran, the `summary` of the whole
+    /// suite and the spread of each config's runs with `--repeat`. By default every run is
+    /// rendered under its config, followed by the summary and the spreads.
+    fn render_suite(
+        &self,
+        runs: &[(&str, &RunResult)],
+        summary: &Summary,
+        repeats: &[SuiteRepeat<'_>],
+    ) -> String {
+        runs.iter()
+            .map(|(config, result)| format!("{}\n{}\n\n", config, self.render(config, result)))
+            .chain(std::iter::once(format!("suite: {}", summary)))
+            .chain(repeat_lines(repeats))
+            .collect()
+    }
+    /// Renders the windows of a capacity search, a table followed by the sustainable rate by
+    /// default
+    fn render_capacity(&self, capacity: &Capacity) -> String {
+        format!("{}", capacity)
+    }
+}
+
+/// Spread across the repeated runs of one config of a suite
+#[derive(Clone, Debug, Serialize)]
+pub struct SuiteRepeat<'a> {
+    pub config: &'a str,
+    #[serde(flatten)]
+    pub summary: RepeatSummary,
+}
+/// A line per config with the spread across its runs
+fn repeat_lines<'a>(repeats: &'a [SuiteRepeat<'_>]) -> impl Iterator<Item = String> + 'a {
+    repeats
+        .iter()
+        .map(|repeat| format!("\nrepeat {}: {}", repeat.config, repeat.summary))
+}
+
+/// Json output, per request detail is only included when it was retained
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonFormatter;
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    statistics: &'a Statistics,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    requests: Option<&'a HashMap<String, Vec<Vec<StepResult>>>>,
+}
+impl OutputFormatter for JsonFormatter {
+    fn render(&self, _config: &str, result: &RunResult) -> String {
+        serde_json::to_string(&JsonOutput {
+            statistics: &result.statistics,
+            requests: result.aggregator.detail.as_ref(),
+        })
+        .expect("failed to parse into valid json")
+    }
+    fn render_suite(
+        &self,
+        runs: &[(&str, &RunResult)],
+        summary: &Summary,
+        repeats: &[SuiteRepeat<'_>],
+    ) -> String {
+        serde_json::to_string(&SuiteOutput {
+            configs: runs
+                .iter()
+                .map(|(config, result)| SuiteConfig {
+                    config,
+                    statistics: &result.statistics,
+                    requests: result.aggregator.detail.as_ref(),
+                })
+                .collect(),
+            summary,
+            repeats,
+        })
+        .expect("failed to parse into valid json")
+    }
+    fn render_capacity(&self, capacity: &Capacity) -> String {
+        serde_json::to_string(capacity).expect("failed to parse into valid json")
+    }
+}
+/// Json output of a suite
+#[derive(Serialize)]
+struct SuiteOutput<'a> {
+    configs: Vec<SuiteConfig<'a>>,
+    summary: &'a Summary,
+    /// Spread across the runs of each config, only with `--repeat`
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    repeats: &'a [SuiteRepeat<'a>],
+}
+#[derive(Serialize)]
+struct SuiteConfig<'a> {
+    config: &'a str,
+    statistics: &'a Statistics,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    requests: Option<&'a HashMap<String, Vec<Vec<StepResult>>>>,
+}
+
+/// The tables of [`Statistics`] with latencies in `unit`, with latency by status class if
+/// `status_classes` is set
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StatFormatter {
+    pub status_classes: bool,
+    pub unit: LatencyUnit,
+}
+impl OutputFormatter for StatFormatter {
+    fn render(&self, _config: &str, result: &RunResult) -> String {
+        if self.status_classes {
+            format!("{:#}", result.statistics.display(self.unit))
+        } else {
+            format!("{}", result.statistics.display(self.unit))
+        }
+    }
+}
+
+/// The one line [`Summary`] of the run, followed by its run id if it has one
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SummaryFormatter;
+impl OutputFormatter for SummaryFormatter {
+    fn render(&self, _config: &str, result: &RunResult) -> String {
+        match &result.statistics.run_id {
+            Some(run_id) => format!("{}, run {}", result.statistics.summary, run_id),
+            None => format!("{}", result.statistics.summary),
+        }
+    }
+    /// The summary line of every run and of the suite, the run id is left out since the
+    /// runs share it
+    fn render_suite(
+        &self,
+        runs: &[(&str, &RunResult)],
+        summary: &Summary,
+        repeats: &[SuiteRepeat<'_>],
+    ) -> String {
+        runs.iter()
+            .map(|(config, result)| format!("{}: {}\n", config, result.statistics.summary))
+            .chain(std::iter::once(format!("suite: {}", summary)))
+            .chain(repeat_lines(repeats))
+            .collect()
+    }
+}
+
+/// A csv header and the latency over throughput curve row of the run
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CurveFormatter;
+impl OutputFormatter for CurveFormatter {
+    fn render(&self, config: &str, result: &RunResult) -> String {
+        format!(
+            "{}\n{}",
+            Summary::CURVE_HEADER,
+            result.statistics.summary.curve_row(config)
+        )
+    }
+    /// One row per run under a single header, the suite summary is left out
+    fn render_suite(
+        &self,
+        runs: &[(&str, &RunResult)],
+        _summary: &Summary,
+        _repeats: &[SuiteRepeat<'_>],
+    ) -> String {
+        std::iter::once(Summary::CURVE_HEADER.to_string())
+            .chain(
+                runs.iter()
+                    .map(|(config, result)| result.statistics.summary.curve_row(config)),
+            )
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+    fn render_capacity(&self, capacity: &Capacity) -> String {
+        capacity.curve()
+    }
+}
+
+/// A csv of the latency distribution of every client, one `name,latency_s,fraction` row per
+/// point. The run needs [`crate::RunOptions::cdf_points`] set, otherwise only the header is
+/// written.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CdfFormatter;
+impl OutputFormatter for CdfFormatter {
+    fn render(&self, _config: &str, result: &RunResult) -> String {
+        let mut lines = vec!["name,latency_s,fraction".to_string()];
+        for client in result.statistics.clients.iter() {
+            for point in client.cdf.iter() {
+                lines.push(format!(
+                    "{},{:.9},{}",
+                    client.name,
+                    point.latency.as_secs_f64(),
+                    point.fraction
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+    /// A single csv of every run with the config as the first column,
+    /// `config,name,latency_s,fraction`
+    fn render_suite(
+        &self,
+        runs: &[(&str, &RunResult)],
+        _summary: &Summary,
+        _repeats: &[SuiteRepeat<'_>],
+    ) -> String {
+        let mut lines = vec!["config,name,latency_s,fraction".to_string()];
+        for (config, result) in runs.iter() {
+            for line in self.render(config, result).lines().skip(1) {
+                lines.push(format!("{},{}", config, line));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// Output formats by name
+#[derive(Default)]
+pub struct OutputRegistry {
+    formatters: BTreeMap<String, Box<dyn OutputFormatter>>,
+}
+impl OutputRegistry {
+    /// A registry with the built in `json`, `stat`, `summary`, `curve` and `cdf` formats
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        registry.register("json", JsonFormatter);
+        registry.register("stat", StatFormatter::default());
+        registry.register("summary", SummaryFormatter);
+        registry.register("curve", CurveFormatter);
+        registry.register("cdf", CdfFormatter);
+        registry
+    }
+    /// Adds `formatter` as `name`, replacing any format already registered under it
+    pub fn register(&mut self, name: &str, formatter: impl OutputFormatter + 'static) {
+        self.formatters
+            .insert(name.to_string(), Box::new(formatter));
+    }
+    pub fn get(&self, name: &str) -> Option<&dyn OutputFormatter> {
+        self.formatters
+            .get(name)
+            .map(|formatter| formatter.as_ref())
+    }
+    /// Names of every registered format in order
+    pub fn names(&self) -> Vec<&str> {
+        self.formatters.keys().map(String::as_str).collect()
+    }
+}
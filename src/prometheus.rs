@@ -0,0 +1,94 @@
+use crate::stats::{Statistics, StatisticsClient};
+use std::fmt::Write;
+
+/// Reads a counter from the statistics of a client
+type Counter = fn(&StatisticsClient) -> u64;
+
+/// Escapes a label value for the prometheus text format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+/// Renders the final statistics of a run in the prometheus text exposition format, every
+/// series is labelled with the run id if there is one
+pub fn render(stats: &Statistics) -> String {
+    let mut out = String::new();
+    let run = match &stats.run_id {
+        Some(run_id) => format!(",run_id=\"{}\"", escape(run_id)),
+        None => String::new(),
+    };
+    let counters: [(&str, &str, Counter); 5] = [
+        ("amawk_requests_total", "Chains run", |c| c.total),
+        ("amawk_failed_requests_total", "Chains that failed", |c| {
+            c.number_of_failed_requests
+        }),
+        (
+            "amawk_truncated_responses_total",
+            "Responses whose body did not match their Content-Length",
+            |c| c.number_of_truncated_responses,
+        ),
+        (
+            "amawk_oversized_responses_total",
+            "Responses larger than their max_response_bytes",
+            |c| c.number_of_oversized_responses,
+        ),
+        (
+            "amawk_sla_breaches_total",
+            "Requests slower than their max_latency_s",
+            |c| c.sla_breaches,
+        ),
+    ];
+    for (metric, help, value) in counters.iter() {
+        writeln!(out, "# HELP {} {}", metric, help).unwrap();
+        writeln!(out, "# TYPE {} counter", metric).unwrap();
+        for c in stats.clients.iter() {
+            writeln!(
+                out,
+                "{}{{name=\"{}\"{}}} {}",
+                metric,
+                escape(&c.name),
+                run,
+                value(c)
+            )
+            .unwrap();
+        }
+    }
+    writeln!(
+        out,
+        "# HELP amawk_latency_seconds Latency of successful chains"
+    )
+    .unwrap();
+    writeln!(out, "# TYPE amawk_latency_seconds summary").unwrap();
+    for c in stats.clients.iter() {
+        let name = escape(&c.name);
+        for p in c.percentiles.iter() {
+            writeln!(
+                out,
+                "amawk_latency_seconds{{name=\"{}\"{},quantile=\"{}\"}} {}",
+                name,
+                run,
+                p.percentile / 100.0,
+                p.latency.as_secs_f64()
+            )
+            .unwrap();
+        }
+        let successes = c.total - c.number_of_failed_requests;
+        writeln!(
+            out,
+            "amawk_latency_seconds_sum{{name=\"{}\"{}}} {}",
+            name,
+            run,
+            c.average_total_load_time.as_secs_f64() * successes as f64
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "amawk_latency_seconds_count{{name=\"{}\"{}}} {}",
+            name, run, successes
+        )
+        .unwrap();
+    }
+    out
+}
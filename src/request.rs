@@ -0,0 +1,1265 @@
+use crate::{
+    capacity::CapacitySearch,
+    compression,
+    config::{
+        DAddressFamily, DChainLatency, DCompression, DDataSelection, DDelay, DDelayDistribution,
+        DHttpVersion, DRankedRequest, DRequest, DRequestGroup, DRunIf, DSelection, DTimeoutAs,
+        DTlsVersion, DWeightDrift,
+    },
+    dataset::Dataset,
+    sla::Sla,
+    template::Template,
+};
+use hyper::{
+    header::{HeaderName, HeaderValue},
+    http::uri::PathAndQuery,
+    Method, Uri,
+};
+use rand::{rngs::StdRng, Rng};
+use regex::Regex;
+use std::{
+    collections::BTreeMap,
+    convert::{TryFrom, TryInto},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+#[derive(Clone)]
+pub struct RequestGroup {
+    pub requests: Vec<Arc<RankedRequest>>,
+    /// Total number of requests to send
+    pub number_of_requests: u32,
+    /// Duration of time over which to smear requests
+    pub duration: Duration,
+    /// Seed for the random number generator, random if not set
+    pub seed: Option<u64>,
+    /// Latency percentiles to report, each in (0, 100]
+    pub percentiles: Vec<f64>,
+    /// Maximum number of idle keep-alive connections kept per host
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Time after which idle keep-alive connections are closed
+    pub pool_idle_timeout: Option<Duration>,
+    /// Maximum number of connections being established at once
+    pub max_concurrent_handshakes: Option<usize>,
+    /// Number of virtual users running chains back to back, when set `number_of_requests`
+    /// is ignored
+    pub concurrency: Option<usize>,
+    /// Number of chains each virtual user runs in parallel
+    pub connections_per_user: usize,
+    /// Header that gets a fresh UUID on every request
+    pub inject_request_id: Option<HeaderName>,
+    /// Pick chains weighted by `weight / cost` instead of `weight`
+    pub cost_weighted: bool,
+    /// Count the think time after each step toward the latency of a chain
+    pub include_think_time: bool,
+    /// Window over which virtual users start their first chain, only used in concurrency
+    /// mode
+    pub ramp_start: Option<Duration>,
+    /// Address family connections are made over
+    pub address_family: DAddressFamily,
+    /// Checked once before the run, see [`crate::run::check_health`]
+    pub health_check: Option<HealthCheck>,
+    /// Time after the duration that running chains get to finish, they wait for as long as
+    /// they take if `None`
+    pub cooldown: Option<Duration>,
+    /// Oldest TLS version connections may negotiate, the TLS backend's default if `None`
+    pub tls_min_version: Option<DTlsVersion>,
+    /// Newest TLS version connections may negotiate, the newest the backend supports if
+    /// `None`
+    pub tls_max_version: Option<DTlsVersion>,
+    /// Search for the highest sustainable rate instead of running once, see
+    /// [`crate::capacity::find_capacity`]
+    pub capacity: Option<CapacitySearch>,
+    /// Http proxy of the health check, requests carry their own in [`Request::proxy`]
+    pub proxy: Option<Uri>,
+    /// Random walk of the weights, they stay fixed if `None`
+    pub weight_drift: Option<WeightDrift>,
+}
+/// Validated [`DWeightDrift`]
+#[derive(Clone, Debug)]
+pub struct WeightDrift {
+    pub interval: Duration,
+    /// Standard deviation of the change of the logarithm of a weight per interval
+    pub step: f64,
+    /// Every weight stays within this factor of its configured weight
+    pub max_factor: f64,
+}
+impl TryFrom<&DWeightDrift> for WeightDrift {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(drift: &DWeightDrift) -> Result<Self, Self::Error> {
+        if !(drift.interval_s > 0.0 && drift.interval_s.is_finite()) {
+            return Err(
+                format!("weight_drift interval_s of {} is invalid", drift.interval_s).into(),
+            );
+        }
+        if !(drift.step >= 0.0 && drift.step.is_finite()) {
+            return Err(format!("weight_drift step of {} is invalid", drift.step).into());
+        }
+        if !(drift.max_factor >= 1.0 && drift.max_factor.is_finite()) {
+            return Err(format!(
+                "weight_drift max_factor of {} is not at least 1",
+                drift.max_factor
+            )
+            .into());
+        }
+        Ok(Self {
+            interval: Duration::from_secs_f64(drift.interval_s),
+            step: drift.step,
+            max_factor: drift.max_factor,
+        })
+    }
+}
+/// Request that has to get `status` before the run starts
+#[derive(Clone, Debug)]
+pub struct HealthCheck {
+    pub uri: Uri,
+    pub status: u16,
+}
+impl RequestGroup {
+    /// Converts `config` like [`TryFrom`] but leaves out ranked requests that fail to
+    /// convert instead of failing, the remaining requests keep their relative proportions.
+    /// Returns the errors of the requests that were left out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{config::DRequestGroup, request::RequestGroup};
+    ///
+    /// let config: DRequestGroup = serde_yaml::from_str(
+    ///     "requests:
+    ///   - {proportion: 1, name: good, requests: [{url: 'http://localhost/'}]}
+    ///   - {proportion: 1, name: bad, requests: [{url: 'ftp://localhost/'}]}
+    /// number_of_requests: 1
+    /// duration_s: 1",
+    /// )
+    /// .unwrap();
+    /// let (group, skipped) = RequestGroup::try_from_lenient(config).unwrap();
+    /// assert_eq!(group.requests.len(), 1);
+    /// assert_eq!(skipped.len(), 1);
+    /// ```
+    pub fn try_from_lenient(
+        mut config: DRequestGroup,
+    ) -> Result<(Self, Vec<String>), Box<dyn std::error::Error + Send + Sync>> {
+        let skipped = resolve_config(&mut config, true)?;
+        Ok((config.try_into()?, skipped))
+    }
+    /// Keeps only the ranked requests named in `only`, or every one if it is empty, and then
+    /// drops the ones named in `skip`. The remaining requests keep their relative proportions.
+    /// Naming a request that does not exist or filtering out every request is an error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::builder::RequestGroupBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let mut group = RequestGroupBuilder::new()
+    ///     .ranked_request("home", 3.0, |b| b.request("http://localhost/", Duration::ZERO))
+    ///     .ranked_request("login", 1.0, |b| b.request("http://localhost/login", Duration::ZERO))
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(group.filter(&["checkout"], &[]).is_err());
+    /// group.filter(&[], &["home"]).unwrap();
+    /// assert_eq!(group.requests.len(), 1);
+    /// assert_eq!(group.requests[0].name, "login");
+    /// ```
+    pub fn filter(
+        &mut self,
+        only: &[&str],
+        skip: &[&str],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        for name in only.iter().chain(skip.iter()) {
+            if !self.requests.iter().any(|request| request.name == *name) {
+                return Err(format!("no request named {}", name).into());
+            }
+        }
+        self.requests.retain(|request| {
+            (only.is_empty() || only.contains(&request.name.as_str()))
+                && !skip.contains(&request.name.as_str())
+        });
+        if self.requests.is_empty() {
+            return Err("every request was filtered out".into());
+        }
+        Ok(())
+    }
+}
+pub struct RankedRequest {
+    /// Relative share of the chains
+    pub weight: f64,
+    /// Relative cost of the chain, only used when picking chains weighted by cost
+    pub cost: f64,
+    /// used to tabulate statists
+    pub name: String,
+    pub requests: Vec<Request>,
+    /// Run steps one after another instead of all at once
+    pub sequential: bool,
+    /// Shuffle step order on every chain execution, only used when sequential
+    pub shuffle_steps: bool,
+    /// Key value pairs results can be grouped by
+    pub labels: BTreeMap<String, String>,
+    /// Thresholds checked after the run
+    pub sla: Option<Sla>,
+    /// Rows bound to chain executions for the body templates of the steps
+    pub data: Option<Dataset>,
+}
+
+/// Think time after a request
+#[derive(Clone, Debug, PartialEq)]
+pub enum Delay {
+    Constant(Duration),
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+    /// Log-normal, the logarithm of the delay in seconds is normal with mean `mu` and
+    /// standard deviation `sigma`
+    LogNormal {
+        mu: f64,
+        sigma: f64,
+    },
+}
+impl Delay {
+    /// Draws a delay, a constant delay does not use `rng`. Draws too long for a [`Duration`]
+    /// saturate to [`Duration::MAX`] instead of panicking.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::request::Delay;
+    /// use rand::{rngs::StdRng, SeedableRng};
+    /// use std::time::Duration;
+    ///
+    /// let mut rng = StdRng::seed_from_u64(1);
+    /// let huge = Delay::LogNormal { mu: 1000.0, sigma: 0.0 };
+    /// assert_eq!(huge.sample(&mut rng), Duration::MAX);
+    /// ```
+    pub fn sample(&self, rng: &mut StdRng) -> Duration {
+        match self {
+            Self::Constant(delay) => *delay,
+            Self::Uniform { min, max } => rng.gen_range(*min..=*max),
+            Self::LogNormal { mu, sigma } => {
+                // Box-Muller, 1 - u keeps the logarithm finite
+                let u: f64 = 1.0 - rng.gen::<f64>();
+                let v: f64 = rng.gen();
+                let z = (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos();
+                Duration::try_from_secs_f64((mu + sigma * z).exp()).unwrap_or(Duration::MAX)
+            }
+        }
+    }
+}
+impl TryFrom<&DDelay> for Delay {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(delay: &DDelay) -> Result<Self, Self::Error> {
+        let valid = |seconds: f64| seconds >= 0.0 && seconds.is_finite();
+        match delay {
+            DDelay::Constant(delay_s) => {
+                if !valid(*delay_s) {
+                    return Err(format!("delay of {} is invalid", delay_s).into());
+                }
+                Ok(Self::Constant(Duration::from_secs_f64(*delay_s)))
+            }
+            DDelay::Distribution(DDelayDistribution::Uniform { min_s, max_s }) => {
+                if !(valid(*min_s) && valid(*max_s) && min_s <= max_s) {
+                    return Err(format!(
+                        "uniform delay needs 0 <= min_s <= max_s, got {} and {}",
+                        min_s, max_s
+                    )
+                    .into());
+                }
+                Ok(Self::Uniform {
+                    min: Duration::from_secs_f64(*min_s),
+                    max: Duration::from_secs_f64(*max_s),
+                })
+            }
+            DDelay::Distribution(DDelayDistribution::Lognormal { mean_s, sigma }) => {
+                if !(*mean_s > 0.0 && mean_s.is_finite() && valid(*sigma)) {
+                    return Err(format!(
+                        "lognormal delay needs mean_s > 0 and sigma >= 0, got {} and {}",
+                        mean_s, sigma
+                    )
+                    .into());
+                }
+                // The mean of a log-normal is exp(mu + sigma^2 / 2)
+                Ok(Self::LogNormal {
+                    mu: mean_s.ln() - sigma * sigma / 2.0,
+                    sigma: *sigma,
+                })
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    /// Url of the request, the first of `urls` if there are several. For a url template it is
+    /// the template with its tokens left out.
+    pub uri: Uri,
+    /// Rendered into the path and query of the url of every execution, `None` if the url has
+    /// no tokens
+    pub url_template: Option<Template>,
+    /// Urls picked from on every execution, `None` if the request only has `uri`
+    pub urls: Option<UrlPool>,
+    /// Names and pools of values of the query parameters added to every request, percent
+    /// encoded
+    pub query_params: Vec<(String, Vec<String>)>,
+    /// Think time after the request
+    pub delay: Delay,
+    /// Think time after a response with the status code of the key, instead of `delay`
+    pub delay_on_status: BTreeMap<u16, Delay>,
+    /// Overrides the `Host` header derived from `uri`
+    pub host_header: Option<HeaderTemplate>,
+    /// Sent as the `Accept` header, none is sent if `None`
+    pub accept: Option<HeaderTemplate>,
+    /// Http proxy the request is sent through, it connects directly if `None`
+    pub proxy: Option<Uri>,
+    /// Successful responses slower than this breach the SLA of the request
+    pub max_latency: Option<Duration>,
+    /// Times a failed request is retried
+    pub retries: u32,
+    /// Wait before the first retry, doubled for every following retry
+    pub retry_backoff: Duration,
+    /// Most time randomly taken off every wait, zero for no jitter
+    pub retry_jitter: Duration,
+    /// Time from the start of this step until the next may start, used instead of `delay`
+    pub interval: Option<Duration>,
+    /// Text the response body must contain
+    pub expect_body_contains: Option<String>,
+    /// Text the response body must not contain, checked after `expect_body_contains`
+    pub expect_body_not_contains: Option<String>,
+    /// Status codes that count as a success, any if empty
+    pub expect_status: Vec<u16>,
+    /// Regular expression the body must match
+    pub expect_body_regex: Option<Regex>,
+    /// JSON pointer into the body and the value it must point at
+    pub expect_json: Option<(String, serde_json::Value)>,
+    /// Most bytes of the body kept for body assertions
+    pub max_body_bytes: u64,
+    /// Responses with a larger body fail with [`crate::run::RequestStatus::ResponseTooLarge`]
+    pub max_response_bytes: Option<u64>,
+    /// Follow the `Location` of redirect responses
+    pub follow_redirects: bool,
+    /// Redirects followed before the request fails
+    pub max_redirects: u32,
+    /// Sent as HTTP/1.0 without keep-alive
+    pub http10: bool,
+    pub method: Method,
+    /// Rendered into the body of every request
+    pub body_template: Option<Template>,
+    /// Encoding the rendered body is compressed with
+    pub compress_body: DCompression,
+    /// Response headers recorded in the result
+    pub capture_headers: Vec<HeaderName>,
+    /// How a timeout is counted
+    pub timeout_as: DTimeoutAs,
+    /// Fraction of requests whose headers are captured
+    pub capture_headers_sample_rate: f64,
+    /// Variables bound to the value of a header of the final response
+    pub extract_headers: Vec<(String, HeaderName)>,
+    /// Fraction of requests the body assertions are run on, the others skip them
+    pub assert_sample_rate: f64,
+    /// Read the whole response body instead of stopping at the response head
+    pub drain_body: bool,
+    /// Skip the step unless the step run before it succeeded
+    pub run_if_previous_success: bool,
+}
+impl TryFrom<&DRankedRequest> for RankedRequest {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(request: &DRankedRequest) -> Result<Self, Self::Error> {
+        if !(request.weight > 0.0 && request.weight.is_finite()) {
+            return Err(format!("{} has a weight of {}", request.name, request.weight).into());
+        }
+        if request.requests.is_empty() {
+            return Err(format!("{} has no requests", request.name).into());
+        }
+        if !request.sequential && request.requests.iter().any(|r| r.run_if.is_some()) {
+            return Err(format!("{} uses run_if but is not sequential", request.name).into());
+        }
+        if !(request.cost > 0.0 && request.cost.is_finite()) {
+            return Err(format!("{} has a cost of {}", request.name, request.cost).into());
+        }
+        let mut requests = vec![];
+        for (step, r) in request.requests.iter().enumerate() {
+            let res: Result<Request, _> = r.try_into();
+            match res {
+                Ok(req) => requests.push(req),
+                // Errors of a step without a url to name it name the step instead
+                Err(err) if r.url.is_empty() && r.urls.as_ref().is_none_or(Vec::is_empty) => {
+                    return Err(format!("{} step {}: {}", request.name, step + 1, err).into())
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        let data = match &request.data_file {
+            Some(path) => Some(
+                Dataset::load(path, request.data_selection)
+                    .map_err(|err| format!("{}: {}", request.name, err))?,
+            ),
+            None => None,
+        };
+        let extracts = requests.iter().any(|r| !r.extract_headers.is_empty());
+        if extracts && (!request.sequential || request.shuffle_steps) {
+            return Err(format!(
+                "{} uses extract_headers but is not sequential in a fixed order",
+                request.name
+            )
+            .into());
+        }
+        // Variables extracted by earlier steps, fields of the data row are checked below
+        let mut variables = std::collections::HashSet::new();
+        for r in requests.iter() {
+            let fields = r
+                .template_fields()
+                .into_iter()
+                .filter(|field| !variables.contains(field))
+                .collect::<Vec<_>>();
+            variables.extend(
+                r.extract_headers
+                    .iter()
+                    .map(|(variable, _)| variable.clone()),
+            );
+            match (&data, fields.first()) {
+                (_, None) => {}
+                (None, Some(field)) => {
+                    return Err(format!(
+                        "{}: unknown template token {{{{{}}}}}, fields need a data_file or an earlier step's extract_headers",
+                        request.name, field
+                    )
+                    .into())
+                }
+                (Some(data), Some(_)) => data
+                    .check_fields(&fields)
+                    .map_err(|err| format!("{}: data_file {}", request.name, err))?,
+            }
+        }
+        if let Some(rate) = request.rate_rps {
+            if !(rate > 0.0 && rate.is_finite()) {
+                return Err(format!("{} has a rate_rps of {}", request.name, rate).into());
+            }
+            if !request.sequential {
+                return Err(format!("{} sets rate_rps but is not sequential", request.name).into());
+            }
+            if request
+                .requests
+                .iter()
+                .any(|r| r.delay_s != 0.0 || r.delay.is_some() || !r.delay_on_status.is_empty())
+            {
+                return Err(format!("{} sets both rate_rps and a delay", request.name).into());
+            }
+            for r in requests.iter_mut() {
+                r.interval = Some(Duration::from_secs_f64(1.0 / rate));
+            }
+        }
+        Ok(Self {
+            weight: request.weight,
+            cost: request.cost,
+            name: request.name.clone(),
+            requests,
+            sequential: request.sequential,
+            shuffle_steps: request.shuffle_steps,
+            labels: request.labels.clone(),
+            data,
+            sla: match &request.sla {
+                Some(sla) => {
+                    Some(Sla::try_from(sla).map_err(|err| format!("{}: {}", request.name, err))?)
+                }
+                None => None,
+            },
+        })
+    }
+}
+/// Applies the `base_url`, `accept` and `proxy` of the group to its requests, and if
+/// `lenient` drops the named requests that are invalid and returns why. The result is the
+/// config a group is built from, resolving it again changes nothing.
+pub fn resolve_config(
+    config: &mut DRequestGroup,
+    lenient: bool,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    apply_base_url(config)?;
+    apply_accept(config);
+    apply_proxy(config);
+    let mut skipped = vec![];
+    if lenient {
+        config
+            .requests
+            .retain(|request| match RankedRequest::try_from(request) {
+                Ok(_) => true,
+                Err(err) => {
+                    skipped.push(format!("skipped {}: {}", request.name, err));
+                    false
+                }
+            });
+    }
+    Ok(skipped)
+}
+fn apply_base_url(
+    config: &mut DRequestGroup,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let base: Uri = match &config.base_url {
+        Some(base_url) => base_url
+            .parse()
+            .map_err(|_| format!("invalid base_url {}", base_url))?,
+        None => return Ok(()),
+    };
+    if !matches!(base.scheme_str(), Some("http") | Some("https")) || base.host().is_none() {
+        return Err(format!("base_url {} needs an http or https scheme and a host", base).into());
+    }
+    if let Some(health_check) = config.health_check.as_mut() {
+        health_check.url = resolve_url(&base, &health_check.url)
+            .ok_or_else(|| {
+                format!(
+                    "health check {}: can not be resolved against {}",
+                    health_check.url, base
+                )
+            })?
+            .to_string();
+    }
+    for ranked in config.requests.iter_mut() {
+        for request in ranked.requests.iter_mut() {
+            if let Some(urls) = request.urls.as_mut() {
+                for url in urls.iter_mut() {
+                    *url = resolve_url(&base, url)
+                        .ok_or_else(|| format!("{}: can not be resolved against {}", url, base))?
+                        .to_string();
+                }
+                continue;
+            }
+            request.url = resolve_url(&base, &request.url)
+                .ok_or_else(|| format!("{}: can not be resolved against {}", request.url, base))?
+                .to_string();
+        }
+    }
+    Ok(())
+}
+/// Gives every request without an `accept` the `accept` of `config`
+fn apply_accept(config: &mut DRequestGroup) {
+    let accept = match &config.accept {
+        Some(accept) => accept,
+        None => return,
+    };
+    for ranked in config.requests.iter_mut() {
+        for request in ranked.requests.iter_mut() {
+            if request.accept.is_none() {
+                request.accept = Some(accept.clone());
+            }
+        }
+    }
+}
+/// Gives every request without a `proxy` the `proxy` of `config`
+fn apply_proxy(config: &mut DRequestGroup) {
+    let proxy = match &config.proxy {
+        Some(proxy) => proxy,
+        None => return,
+    };
+    for ranked in config.requests.iter_mut() {
+        for request in ranked.requests.iter_mut() {
+            if request.proxy.is_none() {
+                request.proxy = Some(proxy.clone());
+            }
+        }
+    }
+}
+/// Parses the url of an http proxy, `none` is no proxy
+fn parse_proxy(proxy: &str) -> Result<Option<Uri>, String> {
+    if proxy == "none" {
+        return Ok(None);
+    }
+    let uri = proxy
+        .parse::<Uri>()
+        .map_err(|err| format!("invalid proxy {}: {}", proxy, err))?;
+    if uri.scheme_str() != Some("http") {
+        return Err(format!("proxy {} is not an http:// url", proxy));
+    }
+    if uri.host().is_none() {
+        return Err(format!("proxy {} has no host", proxy));
+    }
+    Ok(Some(uri))
+}
+/// Resolves `reference` against `base`, a reference with a scheme is returned unchanged
+pub(crate) fn resolve_url(base: &Uri, reference: &str) -> Option<Uri> {
+    if let Ok(uri) = reference.parse::<Uri>() {
+        if uri.scheme().is_some() {
+            return Some(uri);
+        }
+    }
+    let reference: PathAndQuery = reference.parse().ok()?;
+    let reference = reference.as_str();
+    let path = if reference.starts_with('/') {
+        reference.to_string()
+    } else if reference.starts_with('?') {
+        format!("{}{}", base.path(), reference)
+    } else {
+        let base_path = base.path();
+        format!("{}{}", &base_path[..=base_path.rfind('/')?], reference)
+    };
+    let mut parts = base.clone().into_parts();
+    parts.path_and_query = Some(path.parse().ok()?);
+    Uri::from_parts(parts).ok()
+}
+impl TryFrom<DRequestGroup> for RequestGroup {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(mut request: DRequestGroup) -> Result<Self, Self::Error> {
+        resolve_config(&mut request, false)?;
+        if request.requests.is_empty() {
+            return Err("no requests to run".into());
+        }
+        let mut requests = vec![];
+        for r in request.requests.iter() {
+            let res: Result<RankedRequest, _> = r.try_into();
+            match res {
+                Ok(req) => requests.push(Arc::new(req)),
+                Err(err) => return Err(err),
+            }
+        }
+        if request.concurrency == Some(0) {
+            return Err("concurrency must be at least 1".into());
+        }
+        if request.connections_per_user == Some(0) {
+            return Err("connections_per_user must be at least 1".into());
+        }
+        let inject_request_id = match &request.inject_request_id {
+            Some(header) => Some(
+                HeaderName::from_bytes(header.as_bytes())
+                    .map_err(|_| format!("invalid request id header {}", header))?,
+            ),
+            None => None,
+        };
+        if let Some(ramp_start) = request.ramp_start_s {
+            if request.concurrency.is_none() {
+                return Err("ramp_start_s needs concurrency".into());
+            }
+            if !(ramp_start >= 0.0 && ramp_start.is_finite()) {
+                return Err(format!("ramp_start_s of {} is invalid", ramp_start).into());
+            }
+        }
+        // Addresses in urls are not resolved, so they are checked here instead
+        for ranked in requests.iter() {
+            for step in ranked.requests.iter() {
+                for uri in step.uris() {
+                    let host = uri.host().unwrap_or_default();
+                    let ip = host.trim_start_matches('[').trim_end_matches(']').parse();
+                    if let Ok(ip) = ip {
+                        if !request.address_family.allows(&ip) {
+                            return Err(format!(
+                                "{} is not an {} address",
+                                host, request.address_family
+                            )
+                            .into());
+                        }
+                    }
+                }
+            }
+        }
+        let health_check = match &request.health_check {
+            Some(health_check) => {
+                let uri: Uri = health_check
+                    .url
+                    .parse()
+                    .map_err(|_| format!("invalid health check url {}", health_check.url))?;
+                if !matches!(uri.scheme_str(), Some("http") | Some("https")) || uri.host().is_none()
+                {
+                    return Err(format!(
+                        "health check url {} needs an http or https scheme and a host",
+                        health_check.url
+                    )
+                    .into());
+                }
+                Some(HealthCheck {
+                    uri,
+                    status: health_check.status,
+                })
+            }
+            None => None,
+        };
+        if request.max_concurrent_handshakes == Some(0) {
+            return Err("max_concurrent_handshakes has to be at least 1".into());
+        }
+        if let Some(idle_timeout) = request.pool_idle_timeout_s {
+            if !(idle_timeout >= 0.0 && idle_timeout.is_finite()) {
+                return Err(format!("pool_idle_timeout_s of {} is invalid", idle_timeout).into());
+            }
+        }
+        if let Some(cooldown) = request.cooldown_s {
+            if !(cooldown >= 0.0 && cooldown.is_finite()) {
+                return Err(format!("cooldown_s of {} is invalid", cooldown).into());
+            }
+        }
+        if let (Some(min), Some(max)) = (request.tls_min_version, request.tls_max_version) {
+            if min > max {
+                return Err(format!(
+                    "tls_min_version {} is newer than tls_max_version {}",
+                    min, max
+                )
+                .into());
+            }
+        }
+        let capacity = match &request.capacity {
+            Some(capacity) => {
+                if request.concurrency.is_some() {
+                    return Err(
+                        "capacity ramps the rate and cannot be used with concurrency".into(),
+                    );
+                }
+                Some(CapacitySearch::try_from(capacity)?)
+            }
+            None => None,
+        };
+        let proxy = match &request.proxy {
+            Some(proxy) => parse_proxy(proxy)?,
+            None => None,
+        };
+        let weight_drift = match &request.weight_drift {
+            Some(drift) => Some(WeightDrift::try_from(drift)?),
+            None => None,
+        };
+        // Chains are picked from the weights, or weight / cost, scaled by up to the drift's
+        // max_factor either way, so they have to stay positive and add up to a finite total
+        let cost_weighted = request.selection == DSelection::CostWeighted;
+        let factor = weight_drift.as_ref().map_or(1.0, |drift| drift.max_factor);
+        let mut total = 0.0;
+        for ranked in requests.iter() {
+            let weight = if cost_weighted {
+                ranked.weight / ranked.cost
+            } else {
+                ranked.weight
+            };
+            if !(weight / factor > 0.0 && weight * factor < f64::INFINITY) {
+                let what = if cost_weighted {
+                    "weight / cost"
+                } else {
+                    "weight"
+                };
+                return Err(format!(
+                    "{} has a {} of {}, too extreme to pick from",
+                    ranked.name, what, weight
+                )
+                .into());
+            }
+            total += weight * factor;
+        }
+        if !total.is_finite() {
+            return Err(
+                "the weights of the requests add up to more than can be picked from".into(),
+            );
+        }
+        for percentile in request.percentiles.iter() {
+            if !(*percentile > 0.0 && *percentile <= 100.0) {
+                return Err(format!("percentile {} is not in (0, 100]", percentile).into());
+            }
+        }
+        Ok(Self {
+            requests,
+            duration: Duration::from_secs_f64(request.duration_s),
+            number_of_requests: request.number_of_requests,
+            seed: request.seed,
+            percentiles: request.percentiles,
+            pool_max_idle_per_host: request.pool_max_idle_per_host,
+            pool_idle_timeout: request.pool_idle_timeout_s.map(Duration::from_secs_f64),
+            max_concurrent_handshakes: request.max_concurrent_handshakes,
+            concurrency: request.concurrency,
+            connections_per_user: request.connections_per_user.unwrap_or(1),
+            inject_request_id,
+            cost_weighted,
+            include_think_time: request.chain_latency == DChainLatency::IncludeThinkTime,
+            ramp_start: request.ramp_start_s.map(Duration::from_secs_f64),
+            address_family: request.address_family,
+            health_check,
+            cooldown: request.cooldown_s.map(Duration::from_secs_f64),
+            tls_min_version: request.tls_min_version,
+            tls_max_version: request.tls_max_version,
+            capacity,
+            proxy,
+            weight_drift,
+        })
+    }
+}
+
+/// Percent encodes everything but the unreserved characters of RFC 3986
+fn encode_query_component(component: &str) -> String {
+    component
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+/// Parses a url a request can be sent to
+fn parse_url(url: &str) -> Result<Uri, Box<dyn std::error::Error + Send + Sync>> {
+    let uri: Uri = url.parse()?;
+    match uri.scheme_str() {
+        Some("http") | Some("https") => (),
+        Some(scheme) => return Err(format!("{}: unsupported scheme {}", url, scheme).into()),
+        None => {
+            return Err(format!(
+                "{}: url has no scheme, use an absolute url or set base_url",
+                url
+            )
+            .into())
+        }
+    }
+    if uri.host().is_none() {
+        return Err(format!("{}: url has no host", url).into());
+    }
+    Ok(uri)
+}
+/// Parses a url with template tokens in its path or query. The url without its tokens is
+/// checked by the caller, values put in their place are percent encoded and keep it valid.
+fn parse_url_template(url: &str) -> Result<Template, Box<dyn std::error::Error + Send + Sync>> {
+    let template = Template::with_fields(url).map_err(|err| format!("{}: {}", url, err))?;
+    // The host has to be known without rendering, to resolve and check it at load
+    let path_start = url.find("://").and_then(|scheme_end| {
+        let authority = scheme_end + 3;
+        url[authority..].find(['/', '?']).map(|end| authority + end)
+    });
+    match (path_start, url.find("{{")) {
+        (Some(path_start), Some(token)) if token > path_start => Ok(template),
+        (None, _) => Err(format!("{}: url has no path for template tokens", url).into()),
+        _ => Err(format!(
+            "{}: template tokens are only supported in the path and query of a url",
+            url
+        )
+        .into()),
+    }
+}
+/// Value of a header, rendered from a template on every execution if it has tokens
+#[derive(Clone, Debug)]
+pub enum HeaderTemplate {
+    Fixed(HeaderValue),
+    Template(Template),
+}
+impl HeaderTemplate {
+    fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let template = Template::with_fields(value)?;
+        if template.has_tokens() {
+            // The text around the tokens has to be valid on its own
+            HeaderValue::from_str(&template.text())?;
+            Ok(Self::Template(template))
+        } else {
+            Ok(Self::Fixed(HeaderValue::from_str(value)?))
+        }
+    }
+    /// Value for the next execution, tokens are drawn from `rng` and fields read from `row`
+    pub fn render(&self, rng: &mut StdRng, row: Option<&serde_json::Value>) -> HeaderValue {
+        match self {
+            Self::Fixed(value) => value.clone(),
+            Self::Template(template) => {
+                HeaderValue::from_bytes(template.render_header(rng, row).as_bytes())
+                    .expect("control characters are left out of rendered header values")
+            }
+        }
+    }
+}
+/// Equivalent urls of a request, one of them is picked on every execution like a client side
+/// load balancer would
+#[derive(Clone, Debug)]
+pub struct UrlPool {
+    uris: Arc<Vec<Uri>>,
+    selection: DDataSelection,
+    /// Url the next round robin pick returns, shared by every clone
+    next: Arc<AtomicUsize>,
+}
+impl UrlPool {
+    fn new(uris: Vec<Uri>, selection: DDataSelection) -> Self {
+        Self {
+            uris: Arc::new(uris),
+            selection,
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+    /// Url for the next execution, random picks are drawn from `rng`
+    pub fn pick(&self, rng: &mut StdRng) -> &Uri {
+        let index = match self.selection {
+            DDataSelection::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            DDataSelection::Random => rng.gen_range(0..self.uris.len()),
+        };
+        &self.uris[index % self.uris.len()]
+    }
+}
+impl Request {
+    /// Every url the request may be sent to, `uri` first
+    pub fn uris(&self) -> &[Uri] {
+        match &self.urls {
+            Some(urls) => &urls.uris,
+            None => std::slice::from_ref(&self.uri),
+        }
+    }
+    /// Url for the next execution, `uri` unless several `urls` were configured
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{config::DRequest, request::Request};
+    /// use rand::{rngs::StdRng, SeedableRng};
+    /// use std::convert::TryFrom;
+    ///
+    /// let config: DRequest =
+    ///     serde_yaml::from_str("urls: ['http://a.test/', 'http://b.test/']").unwrap();
+    /// let request = Request::try_from(&config).unwrap();
+    /// let mut rng = StdRng::seed_from_u64(1);
+    /// let picked = (0..3)
+    ///     .map(|_| request.pick_uri(&mut rng).host().unwrap().to_string())
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(picked, vec!["a.test", "b.test", "a.test"]);
+    ///
+    /// let empty: DRequest = serde_yaml::from_str("urls: []").unwrap();
+    /// assert!(Request::try_from(&empty).is_err());
+    /// ```
+    pub fn pick_uri(&self, rng: &mut StdRng) -> &Uri {
+        match &self.urls {
+            Some(urls) => urls.pick(rng),
+            None => &self.uri,
+        }
+    }
+    /// `uri` with a value from the pool of every query parameter appended, after the query
+    /// `uri` already has. Values are drawn from `rng` and `uri` is returned unchanged if
+    /// there are no query parameters.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{config::DRequest, request::Request};
+    /// use rand::{rngs::StdRng, SeedableRng};
+    /// use std::convert::TryFrom;
+    ///
+    /// let config: DRequest = serde_yaml::from_str(
+    ///     "{url: 'http://search.test/find?lang=en', query_params: {q: ['fish & chips']}}",
+    /// )
+    /// .unwrap();
+    /// let request = Request::try_from(&config).unwrap();
+    /// let mut rng = StdRng::seed_from_u64(1);
+    /// assert_eq!(
+    ///     request.with_query(&request.uri, &mut rng).to_string(),
+    ///     "http://search.test/find?lang=en&q=fish%20%26%20chips"
+    /// );
+    /// ```
+    pub fn with_query(&self, uri: &Uri, rng: &mut StdRng) -> Uri {
+        if self.query_params.is_empty() {
+            return uri.clone();
+        }
+        let added = self
+            .query_params
+            .iter()
+            .map(|(name, values)| format!("{}={}", name, values[rng.gen_range(0..values.len())]))
+            .collect::<Vec<_>>()
+            .join("&");
+        let path_and_query = match uri.query() {
+            Some(query) if !query.is_empty() => format!("{}?{}&{}", uri.path(), query, added),
+            _ => format!("{}?{}", uri.path(), added),
+        };
+        let mut parts = uri.clone().into_parts();
+        parts.path_and_query = Some(
+            path_and_query
+                .parse()
+                .expect("percent encoded query parameters are always valid"),
+        );
+        Uri::from_parts(parts).expect("only the query of a valid url changed")
+    }
+    /// Think time after a response with `status_code`, the `delay_on_status` of the code if
+    /// there is one and `delay` otherwise
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{config::DRequest, request::{Delay, Request}};
+    /// use std::{convert::TryFrom, time::Duration};
+    ///
+    /// let config: DRequest = serde_yaml::from_str(
+    ///     "{url: 'http://localhost/', delay_s: 0.01, delay_on_status: {429: 0.5, 503: 2}}",
+    /// )
+    /// .unwrap();
+    /// let request = Request::try_from(&config).unwrap();
+    /// assert_eq!(request.delay_for(Some(200)), &request.delay);
+    /// assert_eq!(
+    ///     request.delay_for(Some(429)),
+    ///     &Delay::Constant(Duration::from_millis(500))
+    /// );
+    /// ```
+    pub fn delay_for(&self, status_code: Option<u16>) -> &Delay {
+        status_code
+            .and_then(|status_code| self.delay_on_status.get(&status_code))
+            .unwrap_or(&self.delay)
+    }
+    /// Fields of the data row the templates of the url, headers and body read
+    pub fn template_fields(&self) -> Vec<String> {
+        let headers = [&self.host_header, &self.accept];
+        let headers = headers.iter().filter_map(|header| match header {
+            Some(HeaderTemplate::Template(template)) => Some(template),
+            _ => None,
+        });
+        self.url_template
+            .iter()
+            .chain(headers)
+            .chain(self.body_template.iter())
+            .flat_map(Template::fields)
+            .collect()
+    }
+    /// Whether the body has to be kept to decide if the request succeeded
+    pub fn needs_body(&self) -> bool {
+        self.expect_body_contains.is_some()
+            || self.expect_body_not_contains.is_some()
+            || self.expect_body_regex.is_some()
+            || self.expect_json.is_some()
+    }
+}
+impl TryFrom<&DRequest> for Request {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(request: &DRequest) -> Result<Self, Self::Error> {
+        let urls = match &request.urls {
+            Some(_) if !request.url.is_empty() => {
+                return Err(format!("{}: sets both url and urls", request.url).into())
+            }
+            Some(urls) if urls.is_empty() => return Err("urls is empty".into()),
+            Some(urls) => urls.clone(),
+            None if request.url.is_empty() => return Err("request has no url".into()),
+            None => vec![request.url.clone()],
+        };
+        // Errors name the first url
+        let url = urls[0].as_str();
+        let url_template = match &request.urls {
+            Some(urls) if urls.iter().any(|url| url.contains("{{")) => {
+                return Err(format!("{}: template tokens are only supported in url", url).into())
+            }
+            Some(_) => None,
+            None if url.contains("{{") => Some(parse_url_template(url)?),
+            None => None,
+        };
+        let uris = match &url_template {
+            Some(template) => vec![parse_url(&template.text())?],
+            None => urls
+                .iter()
+                .map(|url| parse_url(url))
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+        let host_header = match &request.host_header {
+            Some(host) => Some(
+                HeaderTemplate::parse(host)
+                    .map_err(|err| format!("{}: invalid host header {}: {}", url, host, err))?,
+            ),
+            None => None,
+        };
+        let accept = match &request.accept {
+            Some(accept) => Some(
+                HeaderTemplate::parse(accept)
+                    .map_err(|err| format!("{}: invalid accept header {}: {}", url, accept, err))?,
+            ),
+            None => None,
+        };
+        let proxy = match &request.proxy {
+            Some(proxy) => parse_proxy(proxy).map_err(|err| format!("{}: {}", url, err))?,
+            None => None,
+        };
+        let success = request.success.clone().unwrap_or_default();
+        let expect_body_contains = match (&request.expect_body_contains, success.body_contains) {
+            (Some(_), Some(_)) => {
+                return Err(format!(
+                    "{}: set either expect_body_contains or success.body_contains",
+                    url
+                )
+                .into())
+            }
+            (expected, body_contains) => expected.clone().or(body_contains),
+        };
+        let expect_body_regex = match &success.body_regex {
+            Some(pattern) => Some(
+                Regex::new(pattern)
+                    .map_err(|err| format!("{}: invalid body_regex: {}", url, err))?,
+            ),
+            None => None,
+        };
+        let expect_json = match (success.json_pointer, success.json_equals) {
+            (Some(pointer), Some(value)) => Some((pointer, serde_json::to_value(value)?)),
+            (None, None) => None,
+            _ => {
+                return Err(
+                    format!("{}: json_pointer and json_equals must be set together", url).into(),
+                )
+            }
+        };
+        if !request.drain_body
+            && (expect_body_contains.is_some()
+                || request.expect_body_not_contains.is_some()
+                || expect_body_regex.is_some()
+                || expect_json.is_some())
+        {
+            return Err(format!(
+                "{}: body assertions need the body, drain_body can not be false",
+                url
+            )
+            .into());
+        }
+        if !request.drain_body && request.max_response_bytes.is_some() {
+            return Err(format!(
+                "{}: max_response_bytes needs the body, drain_body can not be false",
+                url
+            )
+            .into());
+        }
+        let method = match &request.method {
+            Some(method) => Method::from_bytes(method.as_bytes())
+                .map_err(|_| format!("{}: invalid method {}", url, method))?,
+            None => Method::GET,
+        };
+        let capture_headers = request
+            .capture_headers
+            .iter()
+            .map(|header| {
+                HeaderName::from_bytes(header.as_bytes())
+                    .map_err(|_| format!("{}: invalid header to capture {}", url, header))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let extract_headers = request
+            .extract_headers
+            .iter()
+            .map(|(variable, header)| {
+                if variable.is_empty() || variable.contains('.') {
+                    return Err(format!("{}: invalid variable name {:?}", url, variable));
+                }
+                HeaderName::from_bytes(header.as_bytes())
+                    .map(|header| (variable.clone(), header))
+                    .map_err(|_| format!("{}: invalid header to extract {}", url, header))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let capture_headers_sample_rate = request.capture_headers_sample_rate.unwrap_or(1.0);
+        if !(0.0..=1.0).contains(&capture_headers_sample_rate) {
+            return Err(format!(
+                "{}: capture_headers_sample_rate of {} is not in [0, 1]",
+                url, capture_headers_sample_rate
+            )
+            .into());
+        }
+        let assert_sample_rate = request.assert_sample_rate.unwrap_or(1.0);
+        if !(0.0..=1.0).contains(&assert_sample_rate) {
+            return Err(format!(
+                "{}: assert_sample_rate of {} is not in [0, 1]",
+                url, assert_sample_rate
+            )
+            .into());
+        }
+        if let Some(max_latency) = request.max_latency_s {
+            if !(max_latency >= 0.0 && max_latency.is_finite()) {
+                return Err(format!("{}: max_latency_s of {} is invalid", url, max_latency).into());
+            }
+        }
+        if !(request.retry_backoff_s >= 0.0 && request.retry_backoff_s.is_finite()) {
+            return Err(format!(
+                "{}: retry_backoff_s of {} is invalid",
+                url, request.retry_backoff_s
+            )
+            .into());
+        }
+        let retry_jitter = match request.retry_jitter_s {
+            Some(jitter) if !(jitter >= 0.0 && jitter.is_finite()) => {
+                return Err(format!("{}: retry_jitter_s of {} is invalid", url, jitter).into())
+            }
+            Some(jitter) => Duration::from_secs_f64(jitter),
+            None => Duration::ZERO,
+        };
+        let delay = match &request.delay {
+            Some(_) if request.delay_s != 0.0 => {
+                return Err(format!("{}: sets both delay and delay_s", url).into())
+            }
+            Some(delay) => Delay::try_from(delay).map_err(|err| format!("{}: {}", url, err))?,
+            None => Delay::try_from(&DDelay::Constant(request.delay_s))
+                .map_err(|err| format!("{}: {}", url, err))?,
+        };
+        let query_params = request
+            .query_params
+            .iter()
+            .map(|(name, values)| {
+                if name.is_empty() {
+                    return Err(format!(
+                        "{}: query_params has a parameter without a name",
+                        url
+                    ));
+                }
+                if values.is_empty() {
+                    return Err(format!("{}: query_params {} has no values", url, name));
+                }
+                Ok((
+                    encode_query_component(name),
+                    values
+                        .iter()
+                        .map(|value| encode_query_component(value))
+                        .collect(),
+                ))
+            })
+            .collect::<Result<_, _>>()?;
+        let delay_on_status = request
+            .delay_on_status
+            .iter()
+            .map(|(status, delay)| {
+                if !(100..=599).contains(status) {
+                    return Err(format!(
+                        "{}: delay_on_status for invalid status {}",
+                        url, status
+                    ));
+                }
+                let delay = Delay::try_from(delay)
+                    .map_err(|err| format!("{}: delay_on_status {}: {}", url, status, err))?;
+                Ok((*status, delay))
+            })
+            .collect::<Result<_, _>>()?;
+        let body_template = match &request.body_template {
+            Some(template) => Some(
+                // Fields are checked against the data file and variables of the chain
+                Template::with_fields(template).map_err(|err| format!("{}: {}", url, err))?,
+            ),
+            None => None,
+        };
+        if request.compress_body != DCompression::None {
+            if body_template.is_none() {
+                return Err(
+                    format!("{}: compress_body needs a body_template to compress", url).into(),
+                );
+            }
+            if !compression::supported(request.compress_body) {
+                return Err(format!(
+                    "{}: amawk was built without {} compression, enable its feature",
+                    url, request.compress_body
+                )
+                .into());
+            }
+        }
+        Ok(Self {
+            uri: uris[0].clone(),
+            url_template,
+            urls: (uris.len() > 1).then(|| UrlPool::new(uris, request.url_selection)),
+            query_params,
+            delay,
+            delay_on_status,
+            host_header,
+            accept,
+            proxy,
+            max_latency: request.max_latency_s.map(Duration::from_secs_f64),
+            retries: request.retries,
+            retry_backoff: Duration::from_secs_f64(request.retry_backoff_s),
+            retry_jitter,
+            interval: None,
+            expect_body_contains,
+            expect_body_not_contains: request.expect_body_not_contains.clone(),
+            expect_status: success.status,
+            expect_body_regex,
+            expect_json,
+            max_body_bytes: request.max_body_bytes,
+            max_response_bytes: request.max_response_bytes,
+            follow_redirects: request.follow_redirects,
+            max_redirects: request.max_redirects,
+            http10: request.http_version == DHttpVersion::Http10,
+            method,
+            body_template,
+            compress_body: request.compress_body,
+            capture_headers,
+            capture_headers_sample_rate,
+            assert_sample_rate,
+            extract_headers,
+            timeout_as: request.timeout_as,
+            drain_body: request.drain_body,
+            run_if_previous_success: request.run_if == Some(DRunIf::PreviousSuccess),
+        })
+    }
+}
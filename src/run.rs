@@ -0,0 +1,2062 @@
+use crate::{
+    aggregate::Aggregator,
+    compression,
+    config::DTimeoutAs,
+    connector::{build_client, Clients, ConnectionUse, HttpClient, NoAddressInFamily},
+    request::{resolve_url, Delay, RankedRequest, Request, RequestGroup},
+    stats::{get_stat, SortBy, Statistics},
+};
+use futures::future::join_all;
+use hyper::body::{Bytes, HttpBody as _};
+use hyper::{
+    header::{
+        HeaderName, HeaderValue, ACCEPT, CONNECTION, CONTENT_ENCODING, CONTENT_LENGTH, HOST,
+        LOCATION,
+    },
+    Body, Uri, Version,
+};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    rngs::StdRng,
+    seq::SliceRandom,
+    Rng, SeedableRng,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    error::Error as _,
+    io,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    time::{sleep, sleep_until},
+};
+use tracing::{debug, info, instrument, warn};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum RequestStatus {
+    Sucess {
+        delay: Duration,
+        url: String,
+    },
+    HttpParseError,
+    InvalidStatusCode,
+    Timeout,
+    /// The OS ran out of file descriptors or socket buffers
+    ResourceExhausted,
+    /// Body length did not match the advertised `Content-Length`
+    TruncatedResponse {
+        expected: u64,
+        got: u64,
+    },
+    /// The body did not contain the expected text
+    UnexpectedBody {
+        expected: String,
+    },
+    /// The body contained the text of `expect_body_not_contains`
+    ForbiddenBody {
+        forbidden: String,
+    },
+    /// The status code is not one of the `success.status` codes
+    UnexpectedStatus {
+        status: u16,
+    },
+    /// The body did not match `success.body_regex`
+    BodyRegexMismatch {
+        pattern: String,
+    },
+    /// The body was larger than `max_response_bytes`, the rest of it was not read
+    ResponseTooLarge {
+        limit: u64,
+    },
+    /// The body is not JSON or has a different value at `success.json_pointer`
+    JsonMismatch {
+        pointer: String,
+    },
+    /// The expected text was not in the first `max_body_bytes` of the body and the rest was
+    /// not kept, so the body could not be fully evaluated
+    BodyTooLarge {
+        max_body_bytes: u64,
+    },
+    /// The connection closed before a complete response was received, typical of servers
+    /// that do not speak the HTTP version sent
+    ProtocolError(String),
+    /// A redirect pointed back at a url visited earlier in the same request
+    RedirectLoop,
+    /// More than `max_redirects` redirects were followed
+    TooManyRedirects {
+        max_redirects: u32,
+    },
+    /// The step did not run because its `run_if` condition was not met
+    Skipped,
+    /// The request timed out and its `timeout_as` is `ignore`, it counts as neither a
+    /// success nor a failure
+    TimeoutIgnored,
+    /// The host has no address in the selected `address_family`
+    NoAddressInFamily {
+        family: String,
+    },
+    /// The TLS handshake failed because client and server have no TLS version in common,
+    /// see `tls_min_version` and `tls_max_version`
+    TlsVersionMismatch(String),
+    /// The response lacked a header named in `extract_headers`
+    MissingHeader {
+        header: String,
+    },
+    /// Any other error along with the chain of errors that caused it, outermost first
+    Other(#[serde(deserialize_with = "deserialize_error_chain")] Vec<String>),
+}
+impl std::fmt::Display for RequestStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Sucess { delay, url } => format!(
+                    "Success{{duration: {}, url: {} }}",
+                    delay.as_secs_f64(),
+                    url
+                ),
+                Self::HttpParseError => "HttpParseError".to_string(),
+                Self::InvalidStatusCode => "Invalid Status Code".to_string(),
+                Self::Timeout => "Timeout".to_string(),
+                Self::ResourceExhausted => "Resource Exhausted".to_string(),
+                Self::TruncatedResponse { expected, got } => format!(
+                    "Truncated Response{{expected: {} bytes, got: {} bytes}}",
+                    expected, got
+                ),
+                Self::UnexpectedBody { expected } =>
+                    format!("Unexpected Body{{expected to contain: {}}}", expected),
+                Self::ForbiddenBody { forbidden } =>
+                    format!("Forbidden Body{{expected not to contain: {}}}", forbidden),
+                Self::BodyTooLarge { max_body_bytes } => format!(
+                    "Body Too Large{{body too large to fully evaluate, kept {} bytes}}",
+                    max_body_bytes
+                ),
+                Self::ResponseTooLarge { limit } =>
+                    format!("Response Too Large{{over the limit of {} bytes}}", limit),
+                Self::ProtocolError(error) => format!("Protocol Error{{{}}}", error),
+                Self::UnexpectedStatus { status } => format!("Unexpected Status{{{}}}", status),
+                Self::BodyRegexMismatch { pattern } =>
+                    format!("Body Regex Mismatch{{expected to match: {}}}", pattern),
+                Self::JsonMismatch { pointer } =>
+                    format!("Json Mismatch{{unexpected value at {}}}", pointer),
+                Self::Skipped => "Skipped".to_string(),
+                Self::TimeoutIgnored => "Timeout (ignored)".to_string(),
+                Self::NoAddressInFamily { family } => format!("No Address In Family{{{}}}", family),
+                Self::RedirectLoop => "Redirect Loop".to_string(),
+                Self::TooManyRedirects { max_redirects } =>
+                    format!("Too Many Redirects{{max: {}}}", max_redirects),
+                Self::TlsVersionMismatch(error) => format!("TLS Version Mismatch{{{}}}", error),
+                Self::MissingHeader { header } => format!("Missing Header{{{}}}", header),
+                Self::Other(chain) => format!("Other error: {}", chain.join(": ")),
+            }
+        )
+    }
+}
+/// Reads the error chain of [`RequestStatus::Other`], older output stored only the outermost
+/// cause or none at all
+fn deserialize_error_chain<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Chain {
+        Chain(Vec<String>),
+        Cause(Option<String>),
+    }
+    Ok(match Chain::deserialize(deserializer)? {
+        Chain::Chain(chain) => chain,
+        Chain::Cause(cause) => cause.into_iter().collect(),
+    })
+}
+/// Outcome of a single step of a chain
+#[derive(Clone, Debug, Serialize)]
+pub struct StepResult {
+    /// Url the step requested, as configured: without the `query_params` values and with
+    /// the tokens of a url template left in, so the per url counts stay bounded
+    pub url: String,
+    /// Wall clock time the step started
+    pub started_at: SystemTime,
+    pub status: RequestStatus,
+    /// Time spent on the request whether or not it succeeded, excludes the delay
+    pub elapsed: Duration,
+    /// The request succeeded but took longer than its `max_latency`
+    pub sla_breached: bool,
+    /// The response came over a connection that had already served a response, `None` if
+    /// no response arrived
+    pub reused_connection: Option<bool>,
+    /// Http status code of the response, `None` if no response arrived
+    pub status_code: Option<u16>,
+    /// Number of times the request was retried, the other fields describe the last attempt
+    pub retries: u32,
+    /// Redirects followed by the last attempt
+    pub redirects: u32,
+    /// Size of the body of the final response, `None` if no body was read
+    pub response_bytes: Option<u64>,
+    /// Size of the request body as sent, after compression, `None` if there was no body
+    pub request_bytes: Option<u64>,
+    /// The latency only covers the response head, the body was not read
+    pub headers_only: bool,
+    /// Id sent with the last attempt when `inject_request_id` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Response headers named in `capture_headers`, only for sampled requests
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Values of the variables in `extract_headers`, for the later steps of the chain
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    /// Whether the body assertions were run or skipped by `assert_sample_rate`, `None` if
+    /// the request has none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_checked: Option<bool>,
+    /// Time slept after the step, only recorded when it counts toward the chain latency
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub think_time: Option<Duration>,
+    /// How much later than asked the sleep for the think time woke up, `None` if there was
+    /// no think time. Lag beyond the timer resolution of 1ms means the runtime was busy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timer_lag: Option<Duration>,
+}
+impl StepResult {
+    /// The step ran and did not succeed
+    pub fn failed(&self) -> bool {
+        !matches!(
+            self.status,
+            RequestStatus::Sucess { .. } | RequestStatus::Skipped | RequestStatus::TimeoutIgnored
+        )
+    }
+    /// Result of a step that was not run
+    fn skipped(request: &Request) -> Self {
+        Self {
+            url: request.uri.to_string(),
+            started_at: SystemTime::now(),
+            status: RequestStatus::Skipped,
+            elapsed: Duration::ZERO,
+            sla_breached: false,
+            reused_connection: None,
+            status_code: None,
+            retries: 0,
+            redirects: 0,
+            response_bytes: None,
+            request_bytes: None,
+            headers_only: false,
+            request_id: None,
+            headers: BTreeMap::new(),
+            variables: BTreeMap::new(),
+            body_checked: None,
+            think_time: None,
+            timer_lag: None,
+        }
+    }
+}
+/// Outcome of a single http request
+struct Fetched {
+    status: RequestStatus,
+    reused_connection: Option<bool>,
+    status_code: Option<u16>,
+    redirects: u32,
+    response_bytes: Option<u64>,
+    /// Captured headers of the final response
+    headers: BTreeMap<String, String>,
+}
+/// Sent to the aggregator when a chain finishes
+pub struct ChainResult {
+    /// Name of the ranked request the chain belongs to
+    pub name: String,
+    /// Virtual user that ran the chain, only set in concurrency mode
+    pub worker: Option<usize>,
+    /// How much later than scheduled the chain started, only set when chains are dispatched
+    /// on a schedule
+    pub scheduling_delay: Option<Duration>,
+    pub steps: Vec<StepResult>,
+}
+/// Line of the raw log, a step along with the chain it belongs to so each line stands on its
+/// own
+#[derive(Serialize)]
+pub struct RawLogLine<'a> {
+    /// See [`RunOptions::run_id`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<&'a str>,
+    /// Name of the ranked request the chain belongs to
+    pub name: &'a str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: &'a BTreeMap<String, String>,
+    /// Position of the step in the chain, starting at 0
+    pub step: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worker: Option<usize>,
+    #[serde(flatten)]
+    pub result: &'a StepResult,
+}
+impl<'a> RawLogLine<'a> {
+    /// A line for every step of `chain`
+    fn of_chain(
+        run_id: Option<&'a str>,
+        chain: &'a ChainResult,
+        labels: &'a BTreeMap<String, String>,
+    ) -> impl Iterator<Item = RawLogLine<'a>> {
+        chain
+            .steps
+            .iter()
+            .enumerate()
+            .map(move |(step, result)| RawLogLine {
+                run_id,
+                name: &chain.name,
+                labels,
+                step,
+                worker: chain.worker,
+                result,
+            })
+    }
+}
+/// Writes every step of `chain` to `log` as a [`RawLogLine`]
+fn write_raw_log(
+    log: &mut dyn io::Write,
+    run_id: Option<&str>,
+    labels: &BTreeMap<String, String>,
+    chain: &ChainResult,
+) -> io::Result<()> {
+    for line in RawLogLine::of_chain(run_id, chain, labels) {
+        serde_json::to_writer(&mut *log, &line)?;
+        log.write_all(b"\n")?;
+    }
+    Ok(())
+}
+/// Picks which chain to run next, weighted by weight or by weight over cost
+#[derive(Clone)]
+struct ChainPicker {
+    requests: Arc<Vec<Arc<RankedRequest>>>,
+    /// Distribution of every interval of the weight drift, a single one without drift
+    distributions: Arc<Vec<WeightedIndex<f64>>>,
+    /// Length of each interval of the weight drift
+    interval: Option<Duration>,
+    /// Name, configured share and average share over the run of every chain, only with
+    /// weight drift
+    mix: Vec<(String, f64, f64)>,
+}
+impl ChainPicker {
+    /// The random walk of the weight drift is drawn from its own generator seeded from `rng`,
+    /// so it is the same for a fixed seed however the run goes
+    fn new(group: &RequestGroup, rng: &mut StdRng) -> Self {
+        let weights = group
+            .requests
+            .iter()
+            .map(|request| {
+                if group.cost_weighted {
+                    request.weight / request.cost
+                } else {
+                    request.weight
+                }
+            })
+            .collect::<Vec<_>>();
+        let distribution = |weights: &[f64]| {
+            WeightedIndex::new(weights)
+                .expect("weights and costs are validated when the config is loaded")
+        };
+        let requests = Arc::new(group.requests.clone());
+        let drift = match &group.weight_drift {
+            Some(drift) => drift,
+            None => {
+                return Self {
+                    distributions: Arc::new(vec![distribution(&weights)]),
+                    interval: None,
+                    mix: vec![],
+                    requests,
+                }
+            }
+        };
+        let intervals = (group.duration.as_secs_f64() / drift.interval.as_secs_f64())
+            .ceil()
+            .max(1.0) as usize;
+        let mut walk_rng = StdRng::seed_from_u64(rng.gen());
+        let limit = drift.max_factor.ln();
+        let mut log_factors = vec![0.0; weights.len()];
+        let total = weights.iter().sum::<f64>();
+        let mut average = vec![0.0; weights.len()];
+        let mut distributions = vec![];
+        for interval in 0..intervals {
+            if interval > 0 {
+                for log_factor in log_factors.iter_mut() {
+                    *log_factor = (*log_factor + drift.step * standard_normal(&mut walk_rng))
+                        .clamp(-limit, limit);
+                }
+            }
+            let drifted = weights
+                .iter()
+                .zip(log_factors.iter())
+                .map(|(weight, log_factor)| weight * log_factor.exp())
+                .collect::<Vec<_>>();
+            // The last interval is cut short by the end of the duration
+            let start = drift.interval.as_secs_f64() * interval as f64;
+            let length = drift
+                .interval
+                .as_secs_f64()
+                .min(group.duration.as_secs_f64() - start);
+            let share_of_run = if group.duration.is_zero() {
+                1.0
+            } else {
+                length / group.duration.as_secs_f64()
+            };
+            let drifted_total = drifted.iter().sum::<f64>();
+            for (average, weight) in average.iter_mut().zip(drifted.iter()) {
+                *average += weight / drifted_total * share_of_run;
+            }
+            distributions.push(distribution(&drifted));
+        }
+        Self {
+            distributions: Arc::new(distributions),
+            interval: Some(drift.interval),
+            mix: group
+                .requests
+                .iter()
+                .zip(weights.iter().zip(average.iter()))
+                .map(|(request, (weight, average))| {
+                    (request.name.clone(), weight / total, *average)
+                })
+                .collect(),
+            requests,
+        }
+    }
+    /// Returns the chain started `at` after the start of the run along with the order to run
+    /// its steps in
+    fn pick(&self, rng: &mut StdRng, at: Duration) -> (Arc<RankedRequest>, Vec<usize>) {
+        let interval = match self.interval {
+            Some(interval) => (at.as_nanos() / interval.as_nanos()) as usize,
+            None => 0,
+        };
+        let index = self.distributions[interval.min(self.distributions.len() - 1)].sample(rng);
+        let chain = self.requests[index].clone();
+        let mut order = (0..chain.requests.len()).collect::<Vec<_>>();
+        if chain.sequential && chain.shuffle_steps {
+            order.shuffle(rng);
+        }
+        (chain, order)
+    }
+}
+/// Sleeps until `deadline`, forever if it is `None` because it was too far out for an
+/// [`Instant`](tokio::time::Instant)
+async fn sleep_until_or_forever(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => futures::future::pending().await,
+    }
+}
+/// Draws from the standard normal distribution with the Box-Muller transform
+fn standard_normal(rng: &mut StdRng) -> f64 {
+    // 1 - u keeps the logarithm finite
+    let u: f64 = 1.0 - rng.gen::<f64>();
+    let v: f64 = rng.gen();
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+/// Options for [`run`], everything that is set overrides the group
+#[derive(Default)]
+pub struct RunOptions {
+    pub seed: Option<u64>,
+    pub concurrency: Option<usize>,
+    /// Requests that take longer, including reading the body, fail with a timeout
+    pub timeout: Option<Duration>,
+    pub pool_max_idle_per_host: Option<usize>,
+    pub pool_idle_timeout: Option<Duration>,
+    /// Keep the result of every step in [`Aggregator::detail`]
+    pub retain_detail: bool,
+    /// Break the statistics down by url
+    pub per_url: bool,
+    /// Include the latency distribution of every client sampled at this many points, see
+    /// [`crate::stats::StatisticsClient::cdf`]
+    pub cdf_points: Option<usize>,
+    /// Merge the clients of the statistics that share a value for this label
+    pub group_by: Option<String>,
+    /// A new `number_of_requests` sent here replaces the old one for the rest of the run, it
+    /// has no effect in concurrency mode
+    pub reload: Option<watch::Receiver<u32>>,
+    /// Hard deadline for the whole run, chains still running when it passes are abandoned
+    pub max_duration: Option<Duration>,
+    /// How often the number of requests in flight is sampled into
+    /// [`Aggregator::in_flight`], not sampled if `None`
+    pub in_flight_interval: Option<Duration>,
+    /// How often the resident memory of amawk itself is sampled into [`Aggregator::memory`],
+    /// not sampled if `None`
+    pub memory_interval: Option<Duration>,
+    /// Stop the run at the first step that fails, chains still running are abandoned
+    pub fail_fast: bool,
+    /// Only count chains and failures, see [`Aggregator::count_only`]. The statistics of the
+    /// [`RunResult`] are left empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{builder::RequestGroupBuilder, run, RunOptions};
+    /// use std::time::Duration;
+    ///
+    /// let group = RequestGroupBuilder::new()
+    ///     .ranked_request("home", 1.0, |b| b.request("http://127.0.0.1:1/", Duration::ZERO))
+    ///     .number_of_requests(20)
+    ///     .duration(Duration::from_millis(100))
+    ///     .build()
+    ///     .unwrap();
+    /// let options = RunOptions {
+    ///     count_only: true,
+    ///     ..RunOptions::default()
+    /// };
+    /// let result = tokio::runtime::Runtime::new()
+    ///     .unwrap()
+    ///     .block_on(run(&group, options));
+    /// let home = &result.aggregator.clients["home"];
+    /// assert_eq!((home.total, home.failed), (20, 20));
+    /// // Steps are not broken down by url either
+    /// assert!(result.aggregator.urls.is_empty());
+    /// assert!(result.statistics.clients.is_empty());
+    /// ```
+    pub count_only: bool,
+    /// Order of the clients in the statistics
+    pub sort_by: SortBy,
+    /// Every step is written here as a line of json as soon as its chain completes, see
+    /// [`RawLogLine`]
+    pub raw_log: Option<Box<dyn io::Write + Send>>,
+    /// Called with every step as soon as its chain completes, with the same fields as a line
+    /// of the raw log. It runs on the task that aggregates results, so it has to be fast or
+    /// hand the result off, a slow callback holds back aggregation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{builder::RequestGroupBuilder, run, RunOptions};
+    /// use std::{
+    ///     sync::{Arc, Mutex},
+    ///     time::Duration,
+    /// };
+    ///
+    /// let group = RequestGroupBuilder::new()
+    ///     .ranked_request("home", 1.0, |b| b.request("http://127.0.0.1:1/", Duration::ZERO))
+    ///     .number_of_requests(5)
+    ///     .duration(Duration::from_millis(100))
+    ///     .build()
+    ///     .unwrap();
+    /// let timings = Arc::new(Mutex::new(vec![]));
+    /// let recorded = timings.clone();
+    /// let options = RunOptions {
+    ///     on_result: Some(Arc::new(move |line| {
+    ///         recorded.lock().unwrap().push((line.name.to_string(), line.result.elapsed))
+    ///     })),
+    ///     ..RunOptions::default()
+    /// };
+    /// tokio::runtime::Runtime::new()
+    ///     .unwrap()
+    ///     .block_on(run(&group, options));
+    /// assert_eq!(timings.lock().unwrap().len(), 5);
+    /// ```
+    pub on_result: Option<OnResult>,
+    /// Every request sent here is answered with the statistics of the results received so
+    /// far, computed like the final ones, and the run goes on. Throughput is over the time
+    /// elapsed so far.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{builder::RequestGroupBuilder, run, RunOptions};
+    /// use std::time::Duration;
+    /// use tokio::sync::{mpsc, oneshot};
+    ///
+    /// let group = RequestGroupBuilder::new()
+    ///     .ranked_request("home", 1.0, |b| b.request("http://127.0.0.1:1/", Duration::ZERO))
+    ///     .number_of_requests(10)
+    ///     .duration(Duration::from_millis(400))
+    ///     .build()
+    ///     .unwrap();
+    /// let (snapshots, receiver) = mpsc::unbounded_channel();
+    /// let options = RunOptions {
+    ///     snapshots: Some(receiver),
+    ///     ..RunOptions::default()
+    /// };
+    /// let (snapshot, result) = tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let run = tokio::spawn(async move { run(&group, options).await });
+    ///     tokio::time::sleep(Duration::from_millis(200)).await;
+    ///     let (reply, snapshot) = oneshot::channel();
+    ///     snapshots.send(reply).unwrap();
+    ///     let snapshot = snapshot.await.unwrap();
+    ///     (snapshot, run.await.unwrap())
+    /// });
+    /// // Only part of the run was over when the snapshot was taken
+    /// let taken = snapshot.clients.first().map_or(0, |client| client.total);
+    /// assert!(taken < 10);
+    /// assert_eq!(result.statistics.clients[0].total, 10);
+    /// ```
+    pub snapshots: Option<mpsc::UnboundedReceiver<SnapshotRequest>>,
+    /// Identifies the run in its statistics, every line of the raw log and the prometheus
+    /// metrics rendered from it, so the files of one run can be told apart from others
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{builder::RequestGroupBuilder, prometheus, run, RunOptions};
+    /// use std::{
+    ///     sync::{Arc, Mutex},
+    ///     time::Duration,
+    /// };
+    ///
+    /// let group = RequestGroupBuilder::new()
+    ///     .ranked_request("home", 1.0, |b| b.request("http://127.0.0.1:1/", Duration::ZERO))
+    ///     .number_of_requests(2)
+    ///     .duration(Duration::from_millis(10))
+    ///     .build()
+    ///     .unwrap();
+    /// let logged = Arc::new(Mutex::new(vec![]));
+    /// let recorded = logged.clone();
+    /// let options = RunOptions {
+    ///     run_id: Some("nightly-42".to_string()),
+    ///     on_result: Some(Arc::new(move |line| {
+    ///         recorded.lock().unwrap().push(line.run_id.map(str::to_string))
+    ///     })),
+    ///     ..RunOptions::default()
+    /// };
+    /// let result = tokio::runtime::Runtime::new()
+    ///     .unwrap()
+    ///     .block_on(run(&group, options));
+    /// assert_eq!(result.statistics.run_id.as_deref(), Some("nightly-42"));
+    /// assert!(logged.lock().unwrap().iter().all(|id| id.as_deref() == Some("nightly-42")));
+    /// let metrics = prometheus::render(&result.statistics);
+    /// assert!(metrics.contains(r#"amawk_requests_total{name="home",run_id="nightly-42"} 2"#));
+    /// ```
+    pub run_id: Option<String>,
+}
+/// Callback of [`RunOptions::on_result`]
+pub type OnResult = Arc<dyn Fn(&RawLogLine<'_>) + Send + Sync>;
+/// Request of [`RunOptions::snapshots`], the statistics are sent back on it
+pub type SnapshotRequest = oneshot::Sender<Statistics>;
+/// Everything recorded by [`run`]
+pub struct RunResult {
+    pub aggregator: Aggregator,
+    pub statistics: Statistics,
+}
+/// Runs `group` and computes its statistics, this is the entry point for library users.
+///
+/// # Examples
+///
+/// ```
+/// use amawk::{builder::RequestGroupBuilder, run, RunOptions};
+/// use std::time::Duration;
+///
+/// let group = RequestGroupBuilder::new()
+///     .ranked_request("home", 1.0, |b| b.request("http://127.0.0.1:1/", Duration::ZERO))
+///     .number_of_requests(5)
+///     .duration(Duration::from_millis(100))
+///     .build()
+///     .unwrap();
+/// let options = RunOptions {
+///     seed: Some(7),
+///     timeout: Some(Duration::from_secs(1)),
+///     ..Default::default()
+/// };
+/// let result = tokio::runtime::Runtime::new()
+///     .unwrap()
+///     .block_on(run(&group, options));
+/// assert_eq!(result.statistics.clients[0].total, 5);
+/// ```
+pub async fn run(group: &RequestGroup, mut options: RunOptions) -> RunResult {
+    let mut group = group.clone();
+    group.seed = options.seed.or(group.seed);
+    group.concurrency = options.concurrency.or(group.concurrency);
+    group.pool_max_idle_per_host = options
+        .pool_max_idle_per_host
+        .or(group.pool_max_idle_per_host);
+    group.pool_idle_timeout = options.pool_idle_timeout.or(group.pool_idle_timeout);
+    let aggregator = run_request_group(&group, &mut options).await;
+    if options.count_only {
+        return RunResult {
+            aggregator,
+            statistics: Statistics::default(),
+        };
+    }
+    let statistics = statistics_of(&aggregator, &group.percentiles, &StatOptions::of(&options));
+    RunResult {
+        aggregator,
+        statistics,
+    }
+}
+/// The options of [`RunOptions`] that shape the statistics
+struct StatOptions {
+    run_id: Option<String>,
+    group_by: Option<String>,
+    per_url: bool,
+    cdf_points: Option<usize>,
+    sort_by: SortBy,
+}
+impl StatOptions {
+    fn of(options: &RunOptions) -> Self {
+        Self {
+            run_id: options.run_id.clone(),
+            group_by: options.group_by.clone(),
+            per_url: options.per_url,
+            cdf_points: options.cdf_points,
+            sort_by: options.sort_by,
+        }
+    }
+}
+/// Statistics of `aggregator` grouped, broken down and sorted as `options` asks
+fn statistics_of(
+    aggregator: &Aggregator,
+    percentiles: &[f64],
+    options: &StatOptions,
+) -> Statistics {
+    let mut statistics = match &options.group_by {
+        Some(label) => get_stat(
+            &aggregator.group_by(label),
+            percentiles,
+            options.per_url,
+            options.cdf_points,
+        ),
+        None => get_stat(aggregator, percentiles, options.per_url, options.cdf_points),
+    };
+    statistics.sort_clients(options.sort_by);
+    statistics.run_id = options.run_id.clone();
+    statistics
+}
+/// Sends the health check of `group`, if it has one, and fails unless it gets the expected
+/// status within `timeout`. Meant to be called before [`run`] so a run is not wasted on a
+/// service that is not ready.
+pub async fn check_health(
+    group: &RequestGroup,
+    timeout: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let health_check = match &group.health_check {
+        Some(health_check) => health_check,
+        None => return Ok(()),
+    };
+    let (clients, _, _) = build_client(group);
+    let get = clients
+        .get(group.proxy.as_ref())
+        .get(health_check.uri.clone());
+    let response = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, get)
+            .await
+            .map_err(|_| format!("health check {} timed out", health_check.uri))?,
+        None => get.await,
+    }
+    .map_err(|err| format!("health check {} failed: {}", health_check.uri, err))?;
+    if response.status().as_u16() != health_check.status {
+        return Err(format!(
+            "health check {} got status {}, expected {}",
+            health_check.uri,
+            response.status().as_u16(),
+            health_check.status
+        )
+        .into());
+    }
+    Ok(())
+}
+/// Outcome of a step sent by [`probe`]
+#[derive(Clone, Debug, Serialize)]
+pub struct ProbeResult {
+    /// Name of the ranked request the step belongs to
+    pub name: String,
+    pub result: StepResult,
+}
+/// Sends every step of every chain of `group` once, one after another and without retries or
+/// delays, so the success criteria can be checked before a run. Chains with a data file use
+/// its first row and extracted variables are passed on like in a run. Nothing is aggregated.
+pub async fn probe(group: &RequestGroup, timeout: Option<Duration>) -> Vec<ProbeResult> {
+    let (clients, _, _) = build_client(group);
+    let context = RunContext {
+        clients,
+        timeout,
+        request_id_header: group.inject_request_id.clone(),
+        include_think_time: group.include_think_time,
+        in_flight: Arc::new(AtomicI64::new(0)),
+    };
+    let mut rng = match group.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut results = vec![];
+    for chain in group.requests.iter() {
+        let row = chain.data.as_ref().map(|data| data.first());
+        let mut bound = None;
+        for request in chain.requests.iter() {
+            let mut request = request.clone();
+            request.retries = 0;
+            request.delay = Delay::Constant(Duration::ZERO);
+            request.delay_on_status.clear();
+            request.interval = None;
+            let result = run_request(&context, &request, bound.as_ref().or(row), &mut rng).await;
+            bind_variables(&mut bound, row, &result);
+            results.push(ProbeResult {
+                name: chain.name.clone(),
+                result,
+            });
+        }
+    }
+    results
+}
+/// Outcome of looking up a host for [`check_dns`]
+#[derive(Clone, Debug, Serialize)]
+pub struct DnsCheck {
+    pub host: String,
+    /// Addresses of the host in the address family of the group, or why there are none
+    pub result: Result<Vec<IpAddr>, String>,
+}
+/// Looks up every distinct host `group` connects to without sending any request, sorted by
+/// host. Requests sent through a proxy connect to the proxy, so its host is looked up
+/// instead of theirs, and hosts given as IP addresses are not looked up at all.
+///
+/// # Examples
+///
+/// ```
+/// use amawk::{builder::RequestGroupBuilder, run::check_dns};
+/// use std::time::Duration;
+///
+/// let group = RequestGroupBuilder::new()
+///     .ranked_request("home", 1.0, |b| {
+///         b.request("http://localhost:8080/", Duration::ZERO)
+///             .request("http://localhost:8080/login", Duration::ZERO)
+///             .request("http://127.0.0.1:8080/", Duration::ZERO)
+///     })
+///     .ranked_request("stale", 1.0, |b| b.request("http://gone.invalid/", Duration::ZERO))
+///     .number_of_requests(1)
+///     .duration(Duration::from_secs(1))
+///     .build()
+///     .unwrap();
+/// let checks = tokio::runtime::Runtime::new()
+///     .unwrap()
+///     .block_on(check_dns(&group));
+/// let hosts = checks.iter().map(|check| check.host.as_str()).collect::<Vec<_>>();
+/// assert_eq!(hosts, vec!["gone.invalid", "localhost"]);
+/// assert!(checks[0].result.is_err());
+/// assert!(checks[1].result.is_ok());
+/// ```
+pub async fn check_dns(group: &RequestGroup) -> Vec<DnsCheck> {
+    let mut hosts = BTreeSet::new();
+    let mut connects_to = |uri: &Uri| {
+        if let Some(host) = uri.host() {
+            // IPv6 addresses keep their brackets in urls
+            let address = host.trim_start_matches('[').trim_end_matches(']');
+            if address.parse::<IpAddr>().is_err() {
+                hosts.insert(host.to_string());
+            }
+        }
+    };
+    for request in group
+        .requests
+        .iter()
+        .flat_map(|chain| chain.requests.iter())
+    {
+        match &request.proxy {
+            Some(proxy) => connects_to(proxy),
+            None => request.uris().iter().for_each(&mut connects_to),
+        }
+    }
+    if let Some(health_check) = &group.health_check {
+        connects_to(group.proxy.as_ref().unwrap_or(&health_check.uri));
+    }
+    let family = group.address_family;
+    join_all(hosts.into_iter().map(|host| async move {
+        let result = match tokio::net::lookup_host((host.as_str(), 0)).await {
+            Ok(addresses) => {
+                let addresses = addresses
+                    .map(|address| address.ip())
+                    .filter(|ip| family.allows(ip))
+                    .collect::<Vec<_>>();
+                if addresses.is_empty() {
+                    Err(NoAddressInFamily {
+                        host: host.clone(),
+                        family,
+                    }
+                    .to_string())
+                } else {
+                    Ok(addresses)
+                }
+            }
+            Err(err) => Err(err.to_string()),
+        };
+        DnsCheck { host, result }
+    }))
+    .await
+}
+/// State shared by every request of a run
+#[derive(Clone)]
+struct RunContext {
+    /// Client of every proxy the requests are sent through
+    clients: Clients,
+    timeout: Option<Duration>,
+    request_id_header: Option<HeaderName>,
+    /// Record the think time of each step so it counts toward the chain latency
+    include_think_time: bool,
+    /// Requests currently waiting on a response
+    in_flight: Arc<AtomicI64>,
+}
+/// Counts a request as in flight until dropped, so timed out and abandoned requests are
+/// released as well
+struct InFlight(Arc<AtomicI64>);
+impl InFlight {
+    fn new(in_flight: &Arc<AtomicI64>) -> Self {
+        in_flight.fetch_add(1, Ordering::Relaxed);
+        Self(in_flight.clone())
+    }
+}
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+/// Dispatches chains over the duration of the group and streams their results into an
+/// [`Aggregator`] as they complete. Only the timeout, reload, snapshots, max duration, in
+/// flight interval and fail fast of `options` are used.
+pub async fn run_request_group(group: &RequestGroup, options: &mut RunOptions) -> Aggregator {
+    info!(
+        number_of_requests = group.number_of_requests,
+        duration_s = group.duration.as_secs_f64(),
+        concurrency = ?group.concurrency,
+        "starting run"
+    );
+    let mut rng = match group.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let picker = ChainPicker::new(group, &mut rng);
+    let weight_mix = picker.mix.clone();
+    let retain_detail = options.retain_detail;
+    let count_only = options.count_only;
+    let stat_options = StatOptions::of(options);
+    let mut snapshots = options.snapshots.take();
+    let reload = &mut options.reload;
+    let (clients, connector, dns_lookups) = build_client(group);
+    let context = RunContext {
+        clients,
+        timeout: options.timeout,
+        request_id_header: group.inject_request_id.clone(),
+        include_think_time: group.include_think_time,
+        in_flight: Arc::new(AtomicI64::new(0)),
+    };
+    let in_flight = context.in_flight.clone();
+    let in_flight_interval = options.in_flight_interval;
+    let memory_interval = options.memory_interval;
+    let fail_fast = options.fail_fast;
+    let raw_log = &mut options.raw_log;
+    let on_result = options.on_result.clone();
+    let run_id = options.run_id.clone();
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    // Chains started, the ones never received were abandoned at the max duration or the end
+    // of the cooldown
+    let started = Arc::new(AtomicU64::new(0));
+    let (stop_sender, stop) = watch::channel(false);
+    // Scheduling and latencies only use the monotonic clock, so a step of the system clock
+    // can not skew them. `SystemTime` is only recorded to display when requests were made.
+    let start = tokio::time::Instant::now();
+    let deadline = [
+        options.max_duration,
+        group
+            .cooldown
+            .map(|cooldown| group.duration.saturating_add(cooldown)),
+    ]
+    .iter()
+    .flatten()
+    .min()
+    .copied();
+    let hard_stop =
+        || sleep_until_or_forever(deadline.and_then(|deadline| start.checked_add(deadline)));
+    let dispatch_started = started.clone();
+    let mut dispatch_stop = stop.clone();
+    let dispatch = async move {
+        let started = dispatch_started;
+        match group.concurrency {
+            Some(concurrency) => {
+                // Closed loop, every virtual user runs one chain after another until the
+                // duration is over
+                // `None` if the duration is too long for an `Instant`, the users never stop
+                let deadline = start.checked_add(group.duration);
+                // Every connection of a user is its own loop, results are still reported
+                // under the user
+                let workers = (0..concurrency)
+                    .flat_map(|worker| std::iter::repeat_n(worker, group.connections_per_user));
+                for worker in workers {
+                    let picker = picker.clone();
+                    let context = context.clone();
+                    let sender = sender.clone();
+                    let started = started.clone();
+                    let mut stop = stop.clone();
+                    let mut rng = StdRng::seed_from_u64(rng.gen());
+                    let stagger = match group.ramp_start {
+                        Some(ramp_start) => rng.gen_range(Duration::ZERO..=ramp_start),
+                        None => Duration::ZERO,
+                    };
+                    tokio::spawn(async move {
+                        sleep(stagger).await;
+                        while deadline.is_none_or(|deadline| tokio::time::Instant::now() < deadline)
+                        {
+                            let (chain, order) = picker.pick(&mut rng, start.elapsed());
+                            started.fetch_add(1, Ordering::Relaxed);
+                            let steps = tokio::select! {
+                                steps = run_request_chain(&context, &chain, &order, &mut rng) => steps,
+                                _ = stop.changed() => break,
+                            };
+                            sender
+                                .send(ChainResult {
+                                    name: chain.name.clone(),
+                                    worker: Some(worker),
+                                    scheduling_delay: None,
+                                    steps,
+                                })
+                                .ok();
+                        }
+                    });
+                }
+            }
+            None => {
+                let duration = group.duration.as_secs_f64();
+                let mut offset = 0.0;
+                let mut remaining = group.number_of_requests;
+                while remaining > 0 {
+                    // Start times are uniform over the duration. Generating them as sorted
+                    // order statistics lets chains be dispatched without storing the whole
+                    // schedule.
+                    let u: f64 = 1.0 - rng.gen::<f64>();
+                    let next =
+                        offset + (duration - offset) * (1.0 - u.powf(1.0 / remaining as f64));
+                    let scheduled = start.checked_add(Duration::from_secs_f64(next));
+                    let reloaded = async {
+                        match reload.as_mut() {
+                            Some(reload) => reload.changed().await.is_ok(),
+                            None => futures::future::pending().await,
+                        }
+                    };
+                    tokio::select! {
+                        _ = sleep_until_or_forever(scheduled) => {}
+                        changed = reloaded => {
+                            if changed {
+                                // Only the share of the new total that falls in the rest of
+                                // the duration is left to dispatch
+                                let total = *reload.as_ref().unwrap().borrow();
+                                info!(number_of_requests = total, "config reloaded");
+                                offset = start.elapsed().as_secs_f64().min(duration);
+                                remaining = (total as f64 * (duration - offset) / duration).round() as u32;
+                            } else {
+                                *reload = None;
+                            }
+                            continue;
+                        }
+                    }
+                    offset = next;
+                    remaining -= 1;
+                    let scheduled = scheduled.expect("a schedule past the end of time never wakes");
+                    // The scheduled time rather than the current one keeps seeded picks the
+                    // same when dispatch runs late
+                    let (chain, order) = picker.pick(&mut rng, Duration::from_secs_f64(next));
+                    let mut chain_rng = StdRng::seed_from_u64(rng.gen());
+                    let sender = sender.clone();
+                    let context = context.clone();
+                    let started = started.clone();
+                    let mut stop = stop.clone();
+                    tokio::spawn(async move {
+                        // Measured once the task runs, so a busy runtime counts as well
+                        let scheduling_delay =
+                            tokio::time::Instant::now().saturating_duration_since(scheduled);
+                        started.fetch_add(1, Ordering::Relaxed);
+                        let steps = tokio::select! {
+                            steps = run_request_chain(&context, &chain, &order, &mut chain_rng) => steps,
+                            _ = stop.changed() => return,
+                        };
+                        sender
+                            .send(ChainResult {
+                                name: chain.name.clone(),
+                                worker: None,
+                                scheduling_delay: Some(scheduling_delay),
+                                steps,
+                            })
+                            .ok();
+                    });
+                }
+            }
+        }
+        start.elapsed()
+    };
+    let dispatch = async {
+        tokio::select! {
+            elapsed = dispatch => elapsed,
+            _ = hard_stop() => start.elapsed(),
+            // Stopped early by a failure in fail fast mode
+            _ = dispatch_stop.changed() => start.elapsed(),
+        }
+    };
+    let aggregate = async {
+        let mut aggregator = Aggregator::new(retain_detail);
+        aggregator.count_only = count_only;
+        aggregator.weight_mix = weight_mix;
+        aggregator.labels = group
+            .requests
+            .iter()
+            .map(|request| (request.name.clone(), request.labels.clone()))
+            .collect();
+        aggregator.slas = group
+            .requests
+            .iter()
+            .filter_map(|request| Some((request.name.clone(), request.sla.clone()?)))
+            .collect();
+        let mut received = 0;
+        let cooldown_start = start.checked_add(group.duration);
+        if group.cooldown.is_some() {
+            aggregator.cooldown_drained = Some(0);
+        }
+        let no_labels = BTreeMap::new();
+        let mut raw_log_error = None;
+        let mut log = |result: &ChainResult, labels: &HashMap<String, BTreeMap<String, String>>| {
+            let labels = labels.get(&result.name).unwrap_or(&no_labels);
+            if let Some(on_result) = on_result.as_ref() {
+                RawLogLine::of_chain(run_id.as_deref(), result, labels)
+                    .for_each(|line| on_result(&line));
+            }
+            if let Some(log) = raw_log.as_mut() {
+                if let Err(err) = write_raw_log(log.as_mut(), run_id.as_deref(), labels, result) {
+                    warn!(error = %err, "writing the raw log failed, it is incomplete");
+                    raw_log_error = Some(err.to_string());
+                    *raw_log = None;
+                }
+            }
+        };
+        let hard_stop = hard_stop();
+        tokio::pin!(hard_stop);
+        let stopped = loop {
+            tokio::select! {
+                result = receiver.recv() => match result {
+                    Some(result) => {
+                        received += 1;
+                        if let Some(drained) = aggregator.cooldown_drained.as_mut() {
+                            if cooldown_start.is_some_and(|cooldown_start| tokio::time::Instant::now() >= cooldown_start) {
+                                *drained += 1;
+                            }
+                        }
+                        let failure = if fail_fast {
+                            result.steps.iter().find(|step| step.failed()).cloned()
+                        } else {
+                            None
+                        };
+                        log(&result, &aggregator.labels);
+                        aggregator.record(result);
+                        if let Some(failure) = failure {
+                            warn!(url = %failure.url, status = %failure.status, "failing fast");
+                            aggregator.first_failure = Some(failure);
+                            break true;
+                        }
+                    }
+                    None => break false,
+                },
+                _ = &mut hard_stop => {
+                    warn!("max duration or cooldown reached, stopping");
+                    break true;
+                }
+                request = async {
+                    match snapshots.as_mut() {
+                        Some(snapshots) => snapshots.recv().await,
+                        None => futures::future::pending().await,
+                    }
+                } => match request {
+                    Some(reply) => {
+                        // Set for the snapshot only, they are set again once the run is over
+                        let elapsed = start.elapsed();
+                        aggregator.elapsed = elapsed;
+                        aggregator.dispatch_elapsed = elapsed;
+                        aggregator.duration = elapsed.min(group.duration);
+                        aggregator.concurrency = group.concurrency;
+                        if group.concurrency.is_none() && !group.duration.is_zero() {
+                            aggregator.target_rate = Some(
+                                group.number_of_requests as f64 / group.duration.as_secs_f64(),
+                            );
+                        }
+                        info!(requests = aggregator.requests, "snapshot");
+                        reply
+                            .send(statistics_of(&aggregator, &group.percentiles, &stat_options))
+                            .ok();
+                    }
+                    None => snapshots = None,
+                },
+            }
+        };
+        if stopped {
+            stop_sender.send(true).ok();
+            receiver.close();
+            while let Ok(result) = receiver.try_recv() {
+                received += 1;
+                if let Some(drained) = aggregator.cooldown_drained.as_mut() {
+                    if cooldown_start
+                        .is_some_and(|cooldown_start| tokio::time::Instant::now() >= cooldown_start)
+                    {
+                        *drained += 1;
+                    }
+                }
+                log(&result, &aggregator.labels);
+                aggregator.record(result);
+            }
+            aggregator.abandoned = started.load(Ordering::Relaxed).saturating_sub(received);
+            if aggregator.abandoned > 0 {
+                warn!(abandoned = aggregator.abandoned, "chains abandoned");
+            }
+        }
+        if let Some(log) = raw_log.as_mut() {
+            if let Err(err) = log.flush() {
+                warn!(error = %err, "writing the raw log failed, it is incomplete");
+                raw_log_error = Some(err.to_string());
+            }
+        }
+        aggregator.raw_log_error = raw_log_error;
+        aggregator
+    };
+    let mut in_flight_samples = vec![];
+    let sample = async {
+        match in_flight_interval {
+            Some(interval) => {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    ticker.tick().await;
+                    in_flight_samples.push((start.elapsed(), in_flight.load(Ordering::Relaxed)));
+                }
+            }
+            None => futures::future::pending().await,
+        }
+    };
+    let mut memory_samples = vec![];
+    let sample_memory = async {
+        match memory_interval {
+            Some(interval) => {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    ticker.tick().await;
+                    match crate::memory::resident_bytes() {
+                        Some(bytes) => memory_samples.push((start.elapsed(), bytes)),
+                        None => {
+                            warn!("resident memory can not be read on this platform");
+                            futures::future::pending::<()>().await;
+                        }
+                    }
+                }
+            }
+            None => futures::future::pending().await,
+        }
+    };
+    // Sampling goes on until every result is in
+    let (dispatch_elapsed, mut aggregator) = tokio::select! {
+        results = async { tokio::join!(dispatch, aggregate) } => results,
+        never = sample => never,
+        never = sample_memory => never,
+    };
+    aggregator.in_flight = in_flight_samples;
+    aggregator.memory = memory_samples;
+    aggregator.dispatch_elapsed = dispatch_elapsed;
+    aggregator.duration = group.duration;
+    aggregator.concurrency = group.concurrency;
+    // Users staggered past the end of the duration never start
+    aggregator.ramp_start = group
+        .ramp_start
+        .map(|ramp_start| ramp_start.min(group.duration));
+    if group.concurrency.is_none() && !group.duration.is_zero() {
+        aggregator.target_rate =
+            Some(group.number_of_requests as f64 / group.duration.as_secs_f64());
+    }
+    aggregator.new_connections = connector.established();
+    aggregator.max_concurrent_handshakes = connector.max_concurrent_handshakes();
+    aggregator.dns_lookups = dns_lookups.by_host();
+    aggregator.elapsed = start.elapsed();
+    info!(
+        requests = aggregator.requests,
+        elapsed_s = aggregator.elapsed.as_secs_f64(),
+        "run finished"
+    );
+    aggregator
+}
+/// Runs a chain, if the chain is sequential steps are run in the order given by `order`.
+/// Statuses are returned in the order the steps are declared. `rng` is only used for retry
+/// jitter.
+async fn run_request_chain(
+    context: &RunContext,
+    chain: &RankedRequest,
+    order: &[usize],
+    rng: &mut StdRng,
+) -> Vec<StepResult> {
+    let row = chain.data.as_ref().map(|data| data.pick(rng));
+    if chain.sequential {
+        let mut statuses: Vec<Option<StepResult>> = vec![None; chain.requests.len()];
+        let mut previous_success = true;
+        // The data row with the variables extracted so far, they win over its fields
+        let mut bound: Option<serde_json::Value> = None;
+        for idx in order.iter() {
+            let request = &chain.requests[*idx];
+            let step = if request.run_if_previous_success && !previous_success {
+                StepResult::skipped(request)
+            } else {
+                run_request(context, request, bound.as_ref().or(row), rng).await
+            };
+            bind_variables(&mut bound, row, &step);
+            previous_success = matches!(step.status, RequestStatus::Sucess { .. });
+            statuses[*idx] = Some(step);
+        }
+        statuses.drain(..).map(|s| s.unwrap()).collect()
+    } else {
+        let rngs = chain
+            .requests
+            .iter()
+            .map(|_| StdRng::seed_from_u64(rng.gen()))
+            .collect::<Vec<_>>();
+        join_all(
+            chain
+                .requests
+                .iter()
+                .zip(rngs)
+                .map(|(request, mut rng)| async move {
+                    run_request(context, request, row, &mut rng).await
+                }),
+        )
+        .await
+    }
+}
+/// Adds the variables extracted by `step` to `bound`, which starts out as a copy of `row`.
+/// Variables win over fields of the row with the same name and later steps over earlier ones.
+fn bind_variables(
+    bound: &mut Option<serde_json::Value>,
+    row: Option<&serde_json::Value>,
+    step: &StepResult,
+) {
+    if step.variables.is_empty() {
+        return;
+    }
+    let bound = bound.get_or_insert_with(|| row.cloned().unwrap_or_else(|| serde_json::json!({})));
+    for (variable, value) in step.variables.iter() {
+        bound[variable.as_str()] = serde_json::Value::String(value.clone());
+    }
+}
+/// Time to wait before retry number `retry`, counting from 0. With jitter the wait is
+/// uniform between the exponential backoff less the jitter and the backoff, a jitter at least
+/// as large as the backoff makes it uniform between 0 and the backoff ("full jitter"), so
+/// that clients that failed together do not retry together.
+fn retry_wait(request: &Request, retry: u32, rng: &mut StdRng) -> Duration {
+    // Saturates instead of overflowing after many doublings
+    let backoff = (request.retry_backoff.as_secs_f64() * 2f64.powf(f64::from(retry)))
+        .min(Duration::MAX.as_secs_f64());
+    let jitter = request.retry_jitter.as_secs_f64().min(backoff);
+    let wait = if jitter > 0.0 {
+        rng.gen_range(backoff - jitter..=backoff)
+    } else {
+        backoff
+    };
+    Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX)
+}
+#[instrument(level = "debug", skip_all, fields(url = %request.uri))]
+async fn run_request(
+    context: &RunContext,
+    request: &Request,
+    row: Option<&serde_json::Value>,
+    rng: &mut StdRng,
+) -> StepResult {
+    debug!("request started");
+    let started_at = SystemTime::now();
+    let now = Instant::now();
+    let mut retries = 0;
+    let picked = match &request.url_template {
+        Some(template) => template
+            .render_url(rng, row)
+            .parse()
+            .expect("percent encoded template values keep the url valid"),
+        None => request.pick_uri(rng).clone(),
+    };
+    let url = match &request.url_template {
+        Some(template) => template.source().to_string(),
+        None => picked.to_string(),
+    };
+    // Retries and redirects send the same url, body and headers
+    let rendered = Rendered {
+        uri: request.with_query(&picked, rng),
+        body: request.body_template.as_ref().map(|template| {
+            Bytes::from(compression::compress(
+                template.render_row(rng, row).as_bytes(),
+                request.compress_body,
+            ))
+        }),
+        host: request
+            .host_header
+            .as_ref()
+            .map(|host| host.render(rng, row)),
+        accept: request
+            .accept
+            .as_ref()
+            .map(|accept| accept.render(rng, row)),
+    };
+    // Only drawn when headers are captured so other seeded runs are unchanged
+    let capture_sampled =
+        !request.capture_headers.is_empty() && rng.gen_bool(request.capture_headers_sample_rate);
+    let mut capture_headers = if capture_sampled {
+        Cow::Borrowed(&request.capture_headers[..])
+    } else {
+        Cow::Borrowed(&[][..])
+    };
+    // Extracted headers are captured along with the others and split off afterwards
+    if !request.extract_headers.is_empty() {
+        capture_headers.to_mut().extend(
+            request
+                .extract_headers
+                .iter()
+                .map(|(_, header)| header.clone()),
+        );
+    }
+    // Likewise only drawn for requests with body assertions
+    let check_body = request.needs_body() && rng.gen_bool(request.assert_sample_rate);
+    let (
+        Fetched {
+            mut status,
+            reused_connection,
+            status_code,
+            redirects,
+            response_bytes,
+            mut headers,
+        },
+        request_id,
+    ) = loop {
+        // Drawn from the seeded generator so seeded runs send the same ids
+        let request_id = context.request_id_header.as_ref().map(|header| {
+            (
+                header,
+                uuid::Builder::from_random_bytes(rng.gen()).into_uuid(),
+            )
+        });
+        let in_flight = InFlight::new(&context.in_flight);
+        let get = get_url(
+            context.clients.get(request.proxy.as_ref()),
+            request,
+            &rendered,
+            request_id
+                .as_ref()
+                .map(|(header, id)| (*header, id.to_string())),
+            &capture_headers,
+            check_body,
+        );
+        let fetched = match context.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, get).await.unwrap_or(Fetched {
+                status: match request.timeout_as {
+                    DTimeoutAs::Failure => RequestStatus::Timeout,
+                    DTimeoutAs::Success => RequestStatus::Sucess {
+                        url: url.clone(),
+                        delay: timeout,
+                    },
+                    DTimeoutAs::Ignore => RequestStatus::TimeoutIgnored,
+                },
+                reused_connection: None,
+                status_code: None,
+                redirects: 0,
+                response_bytes: None,
+                headers: BTreeMap::new(),
+            }),
+            None => get.await,
+        };
+        drop(in_flight);
+        if matches!(
+            fetched.status,
+            RequestStatus::Sucess { .. } | RequestStatus::TimeoutIgnored
+        ) || retries == request.retries
+        {
+            break (fetched, request_id.map(|(_, id)| id.to_string()));
+        }
+        debug!(retry = retries + 1, status = %fetched.status, "retrying");
+        sleep(retry_wait(request, retries, rng)).await;
+        retries += 1;
+    };
+    let mut variables = BTreeMap::new();
+    for (variable, header) in request.extract_headers.iter() {
+        match headers.get(header.as_str()) {
+            Some(value) => {
+                variables.insert(variable.clone(), value.clone());
+            }
+            None if matches!(status, RequestStatus::Sucess { .. }) => {
+                status = RequestStatus::MissingHeader {
+                    header: header.to_string(),
+                };
+            }
+            None => {}
+        }
+    }
+    if !request.extract_headers.is_empty() {
+        headers.retain(|name, _| {
+            capture_sampled
+                && request
+                    .capture_headers
+                    .iter()
+                    .any(|header| header.as_str() == name)
+        });
+    }
+    let elapsed = now.elapsed();
+    debug!(
+        status = %status,
+        status_code = ?status_code,
+        elapsed_ms = elapsed.as_secs_f64() * 1000.0,
+        retries,
+        "request finished"
+    );
+    let sla_breached = match (&status, request.max_latency) {
+        (RequestStatus::Sucess { delay, .. }, Some(max_latency)) => *delay > max_latency,
+        _ => false,
+    };
+    let think_time = match request.interval {
+        Some(interval) => interval.saturating_sub(now.elapsed()),
+        None => request.delay_for(status_code).sample(rng),
+    };
+    let timer_lag = think(think_time).await;
+    StepResult {
+        url,
+        started_at,
+        status,
+        elapsed,
+        sla_breached,
+        reused_connection,
+        status_code,
+        retries,
+        redirects,
+        response_bytes,
+        request_bytes: rendered.body.map(|body| body.len() as u64),
+        headers_only: !request.drain_body,
+        request_id,
+        headers,
+        variables,
+        body_checked: request.needs_body().then_some(check_body),
+        think_time: context.include_think_time.then_some(think_time),
+        timer_lag,
+    }
+}
+/// Sleeps for `think_time` and returns how much later than intended it woke up, `None`
+/// without a think time or if the intended wake up is too far out for an [`Instant`]
+async fn think(think_time: Duration) -> Option<Duration> {
+    if think_time.is_zero() {
+        return None;
+    }
+    let intended = Instant::now().checked_add(think_time);
+    sleep(think_time).await;
+    intended.map(|intended| Instant::now().saturating_duration_since(intended))
+}
+/// What an execution of a request sends, rendered from its templates once so that retries
+/// and redirects send the same
+struct Rendered {
+    uri: Uri,
+    body: Option<Bytes>,
+    host: Option<HeaderValue>,
+    accept: Option<HeaderValue>,
+}
+/// Gets `rendered` for `request` and returns time along with what is known about the
+/// response. Redirects are followed if the request allows it, the time covers every hop.
+/// `request_id` and the body are sent with every hop, `capture_headers` are recorded from the
+/// final response. The body assertions are only run if `check_body` is set.
+async fn get_url(
+    client: &HttpClient,
+    request: &Request,
+    rendered: &Rendered,
+    request_id: Option<(&HeaderName, String)>,
+    capture_headers: &[HeaderName],
+    check_body: bool,
+) -> Fetched {
+    let now = Instant::now();
+    let first_uri = &rendered.uri;
+    let mut uri = first_uri.clone();
+    let mut visited = vec![];
+    let mut redirects = 0;
+    let mut resp = loop {
+        let mut builder = hyper::Request::builder()
+            .method(request.method.clone())
+            .uri(uri.clone());
+        if let Some(host) = &rendered.host {
+            // The override is meant for the configured host, not for hosts redirected to
+            if uri.authority() == first_uri.authority() {
+                builder = builder.header(HOST, host);
+            }
+        }
+        if let Some(accept) = &rendered.accept {
+            builder = builder.header(ACCEPT, accept.clone());
+        }
+        if let Some((header, id)) = &request_id {
+            builder = builder.header(*header, id.as_str());
+        }
+        if let Some(encoding) = compression::content_encoding(request.compress_body) {
+            builder = builder.header(CONTENT_ENCODING, encoding);
+        }
+        if request.http10 {
+            builder = builder
+                .version(Version::HTTP_10)
+                .header(CONNECTION, "close");
+        }
+        let http_request = builder
+            .body(
+                rendered
+                    .body
+                    .as_ref()
+                    .map_or_else(Body::empty, |body| Body::from(body.clone())),
+            )
+            .expect("request parts are validated when the config is loaded");
+        let mut resp = match client.request(http_request).await {
+            Ok(resp) => resp,
+            Err(error) => {
+                warn!(url = %uri, error = %error, "request error");
+                return Fetched {
+                    status: error_status(error),
+                    reused_connection: None,
+                    status_code: None,
+                    redirects,
+                    response_bytes: None,
+                    headers: BTreeMap::new(),
+                };
+            }
+        };
+        let location = resp
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            // Resolved against the url that was requested
+            .and_then(|location| resolve_url(&uri, location));
+        match location {
+            Some(location) if request.follow_redirects && resp.status().is_redirection() => {
+                let reused = resp
+                    .extensions()
+                    .get::<ConnectionUse>()
+                    .map(ConnectionUse::mark_used);
+                // Drain the body so the connection can be reused
+                while resp.body_mut().data().await.is_some() {}
+                let status = if location == uri || visited.contains(&location) {
+                    RequestStatus::RedirectLoop
+                } else if redirects == request.max_redirects {
+                    RequestStatus::TooManyRedirects {
+                        max_redirects: request.max_redirects,
+                    }
+                } else {
+                    debug!(from = %uri, to = %location, "following redirect");
+                    visited.push(std::mem::replace(&mut uri, location));
+                    redirects += 1;
+                    continue;
+                };
+                return Fetched {
+                    status,
+                    reused_connection: reused,
+                    status_code: Some(resp.status().as_u16()),
+                    redirects,
+                    response_bytes: None,
+                    headers: BTreeMap::new(),
+                };
+            }
+            _ => break resp,
+        }
+    };
+    let reused = resp
+        .extensions()
+        .get::<ConnectionUse>()
+        .map(ConnectionUse::mark_used);
+    let status_code = resp.status().as_u16();
+    let headers = capture_headers
+        .iter()
+        .filter_map(|name| {
+            let values = resp
+                .headers()
+                .get_all(name)
+                .iter()
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+                .collect::<Vec<_>>();
+            (!values.is_empty()).then(|| (name.to_string(), values.join(", ")))
+        })
+        .collect();
+    if !request.drain_body {
+        // The body is dropped unread, which also keeps the connection from being reused
+        return Fetched {
+            status: RequestStatus::Sucess {
+                url: format!("{}", uri),
+                delay: now.elapsed(),
+            },
+            reused_connection: reused,
+            status_code: Some(status_code),
+            redirects,
+            response_bytes: None,
+            headers,
+        };
+    }
+    // Responses to HEAD and 1xx, 204 and 304 responses advertise a length but have no body
+    let bodiless = request.method == hyper::Method::HEAD
+        || resp.status().is_informational()
+        || matches!(status_code, 204 | 304);
+    let expected = resp
+        .headers()
+        .get(CONTENT_LENGTH)
+        .filter(|_| !bodiless)
+        .and_then(|len| len.to_str().ok())
+        .and_then(|len| len.parse::<u64>().ok());
+    let mut got = 0u64;
+    // The body is only kept when it is checked and then only up to the limit
+    let mut body = vec![];
+    // A declared length over the limit fails without reading anything
+    let mut too_large = matches!(
+        (expected, request.max_response_bytes),
+        (Some(expected), Some(limit)) if expected > limit
+    );
+    while let Some(chunk) = if too_large {
+        None
+    } else {
+        resp.body_mut().data().await
+    } {
+        if let Ok(chunk) = chunk {
+            got += chunk.len() as u64;
+            if request.max_response_bytes.is_some_and(|limit| got > limit) {
+                too_large = true;
+                break;
+            }
+            if check_body {
+                let room = request.max_body_bytes.saturating_sub(body.len() as u64);
+                body.extend_from_slice(&chunk[..chunk.len().min(room as usize)]);
+            }
+        }
+    }
+    // A body check that fails on a cut off body may have passed on the whole body
+    let body_mismatch = |status| {
+        if got > body.len() as u64 {
+            RequestStatus::BodyTooLarge {
+                max_body_bytes: request.max_body_bytes,
+            }
+        } else {
+            status
+        }
+    };
+    let status = match expected {
+        _ if too_large => RequestStatus::ResponseTooLarge {
+            limit: request.max_response_bytes.unwrap_or_default(),
+        },
+        Some(expected) if expected != got => RequestStatus::TruncatedResponse { expected, got },
+        _ if !request.expect_status.is_empty() && !request.expect_status.contains(&status_code) => {
+            RequestStatus::UnexpectedStatus {
+                status: status_code,
+            }
+        }
+        _ if !check_body => RequestStatus::Sucess {
+            url: format!("{}", uri),
+            delay: now.elapsed(),
+        },
+        _ => match (
+            &request.expect_body_contains,
+            &request.expect_body_not_contains,
+            &request.expect_body_regex,
+            &request.expect_json,
+        ) {
+            (Some(text), _, _, _) if !contains(&body, text.as_bytes()) => {
+                body_mismatch(RequestStatus::UnexpectedBody {
+                    expected: text.clone(),
+                })
+            }
+            // Found in the kept part it is in the body for sure, but it may be in the rest
+            (_, Some(text), _, _) if contains(&body, text.as_bytes()) => {
+                RequestStatus::ForbiddenBody {
+                    forbidden: text.clone(),
+                }
+            }
+            (_, Some(_), _, _) if got > body.len() as u64 => RequestStatus::BodyTooLarge {
+                max_body_bytes: request.max_body_bytes,
+            },
+            (_, _, Some(regex), _) if !regex.is_match(&String::from_utf8_lossy(&body)) => {
+                body_mismatch(RequestStatus::BodyRegexMismatch {
+                    pattern: regex.as_str().to_string(),
+                })
+            }
+            (_, _, _, Some((pointer, value)))
+                if serde_json::from_slice::<serde_json::Value>(&body)
+                    .ok()
+                    .as_ref()
+                    .and_then(|json| json.pointer(pointer))
+                    != Some(value) =>
+            {
+                body_mismatch(RequestStatus::JsonMismatch {
+                    pointer: pointer.clone(),
+                })
+            }
+            _ => RequestStatus::Sucess {
+                url: format!("{}", uri),
+                delay: now.elapsed(),
+            },
+        },
+    };
+    Fetched {
+        status,
+        reused_connection: reused,
+        status_code: Some(status_code),
+        redirects,
+        response_bytes: Some(got),
+        headers,
+    }
+}
+/// Classifies an error returned by the client
+fn error_status(error: hyper::Error) -> RequestStatus {
+    if is_resource_exhausted(&error) {
+        warn_resource_exhausted();
+        RequestStatus::ResourceExhausted
+    } else if error.is_parse() {
+        RequestStatus::HttpParseError
+    } else if error.is_timeout() {
+        RequestStatus::Timeout
+    } else if error.is_parse_status() {
+        RequestStatus::InvalidStatusCode
+    } else if let Some(error) = find_source::<NoAddressInFamily>(&error) {
+        RequestStatus::NoAddressInFamily {
+            family: error.family.to_string(),
+        }
+    } else if let Some(error) =
+        find_source::<native_tls::Error>(&error).filter(|error| is_tls_version_mismatch(error))
+    {
+        RequestStatus::TlsVersionMismatch(error.to_string())
+    } else if error.is_incomplete_message() {
+        RequestStatus::ProtocolError(error.to_string())
+    } else {
+        let mut chain = vec![];
+        let mut source = error.source();
+        while let Some(error) = source {
+            chain.push(error.to_string());
+            source = error.source();
+        }
+        if chain.is_empty() {
+            chain.push(error.to_string());
+        }
+        RequestStatus::Other(chain)
+    }
+}
+/// Whether a failed handshake came down to the TLS version. native-tls has no error kinds, so
+/// this looks for the reasons OpenSSL, Secure Transport and SChannel give.
+fn is_tls_version_mismatch(error: &native_tls::Error) -> bool {
+    let error = error.to_string().to_lowercase();
+    [
+        "protocol version",
+        "unsupported protocol",
+        "no protocols available",
+        "wrong version number",
+        "version too low",
+        "no suitable",
+        "could not negotiate",
+    ]
+    .iter()
+    .any(|reason| error.contains(reason))
+}
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty()
+        || haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+}
+/// Finds an error of type `E` among the sources of `error`
+fn find_source<E: std::error::Error + 'static>(error: &hyper::Error) -> Option<&E> {
+    let mut source = error.source();
+    while let Some(error) = source {
+        if let Some(found) = error.downcast_ref::<E>() {
+            return Some(found);
+        }
+        // io errors hide what they wrap from `source`
+        if let Some(found) = error
+            .downcast_ref::<io::Error>()
+            .and_then(|error| error.get_ref())
+            .and_then(|inner| inner.downcast_ref::<E>())
+        {
+            return Some(found);
+        }
+        source = error.source();
+    }
+    None
+}
+/// Checks if the error was caused by the OS running out of file descriptors or buffers
+fn is_resource_exhausted(error: &hyper::Error) -> bool {
+    let mut source = error.source();
+    while let Some(error) = source {
+        if let Some(io_error) = error.downcast_ref::<io::Error>() {
+            if let Some(code) = io_error.raw_os_error() {
+                if code == libc::EMFILE || code == libc::ENFILE || code == libc::ENOBUFS {
+                    return true;
+                }
+            }
+        }
+        source = error.source();
+    }
+    false
+}
+static RESOURCE_EXHAUSTED_WARNED: AtomicBool = AtomicBool::new(false);
+fn warn_resource_exhausted() {
+    if !RESOURCE_EXHAUSTED_WARNED.swap(true, Ordering::Relaxed) {
+        warn!("ran out of file descriptors, raise the limit with `ulimit -n` or lower the concurrency");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DRequest;
+    use std::convert::TryFrom;
+
+    /// A request retried with the retry fields in `fields`
+    fn retried(fields: &str) -> Request {
+        let config: DRequest =
+            serde_yaml::from_str(&format!("{{url: 'http://localhost/', {}}}", fields)).unwrap();
+        Request::try_from(&config).unwrap()
+    }
+
+    #[test]
+    fn retry_wait_doubles_without_jitter() {
+        let request = retried("retries: 3, retry_backoff_s: 0.5");
+        let mut rng = StdRng::seed_from_u64(1);
+        let waits = (0..3)
+            .map(|retry| retry_wait(&request, retry, &mut rng))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            waits,
+            vec![
+                Duration::from_millis(500),
+                Duration::from_secs(1),
+                Duration::from_secs(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn retry_jitter_takes_at_most_the_jitter_off_the_backoff() {
+        let request = retried("retries: 3, retry_backoff_s: 1, retry_jitter_s: 0.25");
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let wait = retry_wait(&request, 2, &mut rng);
+            assert!(wait >= Duration::from_millis(3750) && wait <= Duration::from_secs(4));
+        }
+    }
+
+    #[test]
+    fn retry_jitter_larger_than_the_backoff_is_full_jitter() {
+        let request = retried("retries: 3, retry_backoff_s: 1, retry_jitter_s: 100");
+        let mut rng = StdRng::seed_from_u64(1);
+        let waits = (0..1000)
+            .map(|_| retry_wait(&request, 0, &mut rng))
+            .collect::<Vec<_>>();
+        assert!(waits.iter().all(|wait| *wait <= Duration::from_secs(1)));
+        assert!(waits.iter().any(|wait| *wait < Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn retry_wait_saturates_instead_of_overflowing() {
+        let request = retried("retries: 3000, retry_backoff_s: 1, retry_jitter_s: 0.5");
+        let mut rng = StdRng::seed_from_u64(1);
+        for retry in [64, 1100, 2000, u32::MAX].iter() {
+            assert_eq!(retry_wait(&request, *retry, &mut rng), Duration::MAX);
+        }
+    }
+
+    #[test]
+    fn delay_samples_saturate_at_the_ends_of_a_duration() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for (mu, expected) in [
+            (1000.0, Duration::MAX),
+            (f64::MAX, Duration::MAX),
+            (-1000.0, Duration::ZERO),
+        ]
+        .iter()
+        {
+            let delay = Delay::LogNormal {
+                mu: *mu,
+                sigma: 0.0,
+            };
+            assert_eq!(delay.sample(&mut rng), *expected, "mu {}", mu);
+        }
+        // Depending on the sign of the draw a huge sigma lands on either end
+        let delay = Delay::LogNormal {
+            mu: 0.0,
+            sigma: 1e308,
+        };
+        for _ in 0..100 {
+            let sample = delay.sample(&mut rng);
+            assert!(
+                sample == Duration::MAX || sample == Duration::ZERO,
+                "{:?}",
+                sample
+            );
+        }
+    }
+
+    /// How often each chain of a group of `rare` with weight 0.5 and `common` with weight 1.5
+    /// is picked in 4000 seeded picks, with `configure` applied to the builder
+    fn pick_counts(
+        configure: impl FnOnce(
+            crate::builder::RequestGroupBuilder,
+        ) -> crate::builder::RequestGroupBuilder,
+    ) -> HashMap<String, usize> {
+        let builder = crate::builder::RequestGroupBuilder::new()
+            .ranked_request("rare", 0.5, |b| {
+                b.request("http://localhost/", Duration::ZERO)
+            })
+            .ranked_request("common", 1.5, |b| {
+                b.cost(3.0).request("http://localhost/", Duration::ZERO)
+            });
+        let group = configure(builder).build().unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let picker = ChainPicker::new(&group, &mut rng);
+        let mut counts = HashMap::new();
+        for _ in 0..4000 {
+            let (chain, _) = picker.pick(&mut rng, Duration::ZERO);
+            *counts.entry(chain.name.clone()).or_default() += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn chains_are_picked_by_fractional_weight() {
+        let counts = pick_counts(|builder| builder);
+        // 0.5 / (0.5 + 1.5) of the picks
+        assert!((900..=1100).contains(&counts["rare"]), "{:?}", counts);
+    }
+
+    #[test]
+    fn cost_weighted_chains_are_picked_by_weight_over_cost() {
+        let counts = pick_counts(|builder| builder.cost_weighted(true));
+        // 0.5 / (0.5 + 1.5 / 3) of the picks
+        assert!((1900..=2100).contains(&counts["rare"]), "{:?}", counts);
+    }
+
+    #[tokio::test]
+    async fn saturated_think_times_sleep_without_panicking() {
+        let delay = Delay::LogNormal {
+            mu: 1000.0,
+            sigma: 0.0,
+        };
+        let think_time = delay.sample(&mut StdRng::seed_from_u64(1));
+        assert_eq!(think_time, Duration::MAX);
+        let slept = tokio::time::timeout(Duration::from_millis(10), think(think_time)).await;
+        assert!(slept.is_err());
+        assert_eq!(think(Duration::ZERO).await, None);
+    }
+
+    #[tokio::test]
+    async fn durations_too_long_for_an_instant_run_until_the_max_duration() {
+        let forever = Duration::from_secs(10u64.pow(19));
+        for concurrency in [None, Some(2)].iter() {
+            let mut builder = crate::builder::RequestGroupBuilder::new()
+                .ranked_request("home", 1.0, |b| {
+                    b.request("http://127.0.0.1:1/", Duration::ZERO)
+                })
+                .number_of_requests(1)
+                .duration(forever)
+                .cooldown(forever);
+            if let Some(concurrency) = concurrency {
+                builder = builder.concurrency(*concurrency);
+            }
+            let options = RunOptions {
+                max_duration: Some(Duration::from_millis(50)),
+                ..Default::default()
+            };
+            run(&builder.build().unwrap(), options).await;
+        }
+    }
+
+    #[test]
+    fn invalid_retry_backoff_and_jitter_are_rejected() {
+        for field in ["retry_backoff_s", "retry_jitter_s"].iter() {
+            for value in ["-1", ".nan", ".inf"].iter() {
+                let config: DRequest = serde_yaml::from_str(&format!(
+                    "{{url: 'http://localhost/', {}: {}}}",
+                    field, value
+                ))
+                .unwrap();
+                assert!(Request::try_from(&config).is_err(), "{}: {}", field, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{aggregate::ClientAggregate, config::DSla};
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, time::Duration};
+
+/// Acceptance criteria of a named request, checked after the run
+#[derive(Clone, Debug, Default)]
+pub struct Sla {
+    pub mean: Option<Duration>,
+    pub p95: Option<Duration>,
+    pub p99: Option<Duration>,
+    /// Highest fraction of chains that may fail, in [0, 1]
+    pub error_rate: Option<f64>,
+}
+impl TryFrom<&DSla> for Sla {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(sla: &DSla) -> Result<Self, Self::Error> {
+        if let Some(error_rate) = sla.error_rate {
+            if !(0.0..=1.0).contains(&error_rate) {
+                return Err(format!("sla error_rate {} is not in [0, 1]", error_rate).into());
+            }
+        }
+        // Negative, infinite, NaN and too large values are rejected
+        let millis = |name: &str, ms: Option<f64>| match ms {
+            Some(ms) => Duration::try_from_secs_f64(ms / 1000.0)
+                .map(Some)
+                .map_err(|_| format!("sla {} of {} is invalid", name, ms)),
+            None => Ok(None),
+        };
+        Ok(Self {
+            mean: millis("mean_ms", sla.mean_ms)?,
+            p95: millis("p95_ms", sla.p95_ms)?,
+            p99: millis("p99_ms", sla.p99_ms)?,
+            error_rate: sla.error_rate,
+        })
+    }
+}
+impl Sla {
+    /// Checks the chains of a client, returns a description of every violated threshold
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{aggregate::ClientAggregate, sla::Sla};
+    /// use std::time::Duration;
+    ///
+    /// let mut client = ClientAggregate::new();
+    /// client.latency.record(Duration::from_millis(400).as_nanos() as u64);
+    /// client.total = 1;
+    /// let sla = |p99_ms| Sla {
+    ///     p99: Some(Duration::from_millis(p99_ms)),
+    ///     ..Default::default()
+    /// };
+    /// assert!(sla(500).check(&client).is_empty());
+    /// assert_eq!(sla(300).check(&client).len(), 1);
+    /// ```
+    pub fn check(&self, client: &ClientAggregate) -> Vec<String> {
+        let mut violations = vec![];
+        let mut latency = |metric: &str, limit: Option<Duration>, actual: Duration| {
+            if let Some(limit) = limit {
+                if actual > limit {
+                    violations.push(format!(
+                        "{} {:.1}ms over {:.1}ms",
+                        metric,
+                        actual.as_secs_f64() * 1000.0,
+                        limit.as_secs_f64() * 1000.0
+                    ));
+                }
+            }
+        };
+        latency(
+            "mean",
+            self.mean,
+            Duration::from_secs_f64(client.moments.mean()),
+        );
+        latency(
+            "p95",
+            self.p95,
+            Duration::from_nanos(client.latency.value_at_percentile(95.0)),
+        );
+        latency(
+            "p99",
+            self.p99,
+            Duration::from_nanos(client.latency.value_at_percentile(99.0)),
+        );
+        if let Some(limit) = self.error_rate {
+            let error_rate = if client.total == 0 {
+                0.0
+            } else {
+                client.failed as f64 / client.total as f64
+            };
+            if error_rate > limit {
+                violations.push(format!("error rate {:.4} over {:.4}", error_rate, limit));
+            }
+        }
+        violations
+    }
+}
+
+/// Outcome of checking the SLA of a named request
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SlaStatus {
+    pub passed: bool,
+    pub violations: Vec<String>,
+}
@@ -0,0 +1,1138 @@
+use crate::{
+    aggregate::{Aggregator, ClientAggregate, Moments},
+    run::RequestStatus,
+    sla::SlaStatus,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime},
+};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatisticsClient {
+    pub name: String,
+    /// Labels of the ranked request, or the label grouped by
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    pub total: u64,
+    pub average_total_load_time: Duration,
+    /// Median latency of successful chains, 0 if none succeeded like the average
+    pub median: Duration,
+    pub standard_deviation: Duration,
+    /// Standard error of the mean latency of successful chains, `None` if fewer than 2
+    /// succeeded
+    pub stderr_mean: Option<Duration>,
+    /// Bounds of the 95% confidence interval of the mean latency, the low bound is clamped to
+    /// 0. `None` if fewer than 2 chains succeeded.
+    pub mean_ci_low: Option<Duration>,
+    pub mean_ci_high: Option<Duration>,
+    /// Latency of successful chains at each configured percentile
+    pub percentiles: Vec<Percentile>,
+    pub number_of_failed_requests: u64,
+    /// Average time failed chains took to fail, includes the successful steps before the failure
+    pub average_time_to_failure: Duration,
+    pub max_time_to_failure: Duration,
+    /// Responses whose body did not match the advertised `Content-Length`
+    pub number_of_truncated_responses: u64,
+    /// Responses larger than their `max_response_bytes`
+    pub number_of_oversized_responses: u64,
+    /// Requests that succeeded slower than their `max_latency_s`
+    pub sla_breaches: u64,
+    /// Fraction of responses that came over an already used connection
+    pub connection_reuse_ratio: f64,
+    pub common_errors: Vec<RequestStatus>,
+    /// Step latency broken down by the class of the response status code
+    pub status_classes: Vec<StatisticsStatusClass>,
+    /// Outcome of the SLA of the named request, `None` if it has none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sla: Option<SlaStatus>,
+    /// Distribution of the body sizes of individual responses
+    pub response_size: StatisticsSize,
+    /// Distribution of the sizes of the request bodies as sent, after compression, `None`
+    /// if no body was sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_size: Option<StatisticsSize>,
+    /// Steps whose latency only covers the response head because `drain_body` is false
+    pub headers_only_steps: u64,
+    /// Steps not run because their `run_if` condition was not met
+    pub skipped_steps: u64,
+    /// Steps whose body assertions ran
+    pub assertions_checked: u64,
+    /// Steps whose body assertions were skipped by `assert_sample_rate`
+    pub assertions_skipped: u64,
+    /// Chains that timed out with `timeout_as: ignore`, they are not part of `total`
+    pub ignored_timeouts: u64,
+    /// Start of the earliest request, for placing clients on a timeline
+    pub first_request_at: Option<SystemTime>,
+    /// Start of the latest request
+    pub last_request_at: Option<SystemTime>,
+    /// Cumulative distribution of the latency of successful chains, empty unless requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cdf: Vec<CdfPoint>,
+    /// The first step of every chain on its own, `None` for chains of a single step
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_step: Option<StatisticsFirstStep>,
+}
+/// Latency of the first declared step of the chains of a client across every execution
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatisticsFirstStep {
+    pub total: u64,
+    pub number_of_failed_requests: u64,
+    /// Average latency of successful first steps
+    pub average: Duration,
+    /// Latency of successful first steps at each configured percentile
+    pub percentiles: Vec<Percentile>,
+}
+/// Point of a cumulative distribution, `fraction` of the values are at most `latency`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CdfPoint {
+    pub latency: Duration,
+    pub fraction: f64,
+}
+/// Body sizes in bytes
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatisticsSize {
+    pub p50: u64,
+    pub p95: u64,
+    pub max: u64,
+}
+/// Latency of the steps of a client whose responses fall in one status class
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatisticsStatusClass {
+    /// Class such as `2xx`, `none` for steps that got no response
+    pub class: String,
+    pub total: u64,
+    pub percentiles: Vec<Percentile>,
+}
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Percentile {
+    pub percentile: f64,
+    pub latency: Duration,
+}
+/// Requests and failures for a single url across every chain
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatisticsUrl {
+    pub url: String,
+    pub total: u64,
+    pub number_of_failed_requests: u64,
+    pub sla_breaches: u64,
+}
+/// Chains run by a single virtual user
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatisticsWorker {
+    pub worker: usize,
+    pub chains: u64,
+    pub number_of_failed_requests: u64,
+}
+/// Totals across every client
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Summary {
+    /// Chains run
+    pub total: u64,
+    /// Chains that failed
+    pub failed: u64,
+    /// Percentage of chains that failed
+    pub error_percentage: f64,
+    /// Median latency of successful chains
+    pub p50: Duration,
+    /// 95th percentile latency of successful chains
+    pub p95: Duration,
+    /// Chains per second over the wall time of the run
+    pub requests_per_second: f64,
+    /// Chains per second the group asked for, `None` in concurrency mode
+    pub target_requests_per_second: Option<f64>,
+    /// Chains per second dispatched, over the configured duration or the time dispatching
+    /// took if it fell behind
+    pub achieved_requests_per_second: f64,
+    /// Mean of how late chains started compared to the schedule, `None` in concurrency mode.
+    /// A high value means the generator rather than the target is the bottleneck.
+    pub average_scheduling_delay: Option<Duration>,
+    /// Latest start of a chain compared to the schedule, `None` in concurrency mode
+    pub max_scheduling_delay: Option<Duration>,
+    /// Chains still running when the max duration or the cooldown passed, they are in no
+    /// other count
+    pub abandoned: u64,
+    /// Chains that finished during the cooldown, they are included in every other count.
+    /// `None` without a cooldown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown_drained: Option<u64>,
+    /// Virtual users of a concurrency run, `None` otherwise
+    pub concurrency: Option<usize>,
+    /// Window over which virtual users started their first chain, `None` if they all
+    /// started at once
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ramp_start: Option<Duration>,
+}
+impl Summary {
+    /// Header of the csv rows written by [`Summary::curve_row`]
+    pub const CURVE_HEADER: &'static str =
+        "config,concurrency,requests_per_second,p50_ms,p95_ms,error_percentage";
+    /// A csv row describing one point of a latency over throughput curve. Runs at increasing
+    /// concurrency each give a point, their rows can be concatenated under
+    /// [`Summary::CURVE_HEADER`].
+    pub fn curve_row(&self, config: &str) -> String {
+        format!(
+            "{},{},{:.1},{:.1},{:.1},{:.2}",
+            config,
+            self.concurrency
+                .map(|concurrency| concurrency.to_string())
+                .unwrap_or_default(),
+            self.requests_per_second,
+            self.p50.as_secs_f64() * 1000.0,
+            self.p95.as_secs_f64() * 1000.0,
+            self.error_percentage
+        )
+    }
+    /// Percentage the achieved rate fell short of the target, 0 if it kept up or there was
+    /// no target
+    pub fn rate_shortfall_percentage(&self) -> f64 {
+        match self.target_requests_per_second {
+            Some(target) if target > 0.0 => {
+                ((target - self.achieved_requests_per_second) / target * 100.0).max(0.0)
+            }
+            _ => 0.0,
+        }
+    }
+}
+/// Renders a single stable line such as `1000 reqs, 2.1% errors, p95 340ms, 480 rps`,
+/// followed by `, target 500 rps, achieved 490 rps` when there is a target rate and
+/// `, scheduling delay avg 2ms, max 40ms` when chains were dispatched on a schedule and
+/// `, 12 drained in cooldown` when there was a cooldown and `, 3 abandoned` when chains were
+/// abandoned at the max duration or the end of the cooldown
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} reqs, {:.1}% errors, p95 {:.0}ms, {:.0} rps",
+            self.total,
+            self.error_percentage,
+            self.p95.as_secs_f64() * 1000.0,
+            self.requests_per_second
+        )?;
+        if let Some(target) = self.target_requests_per_second {
+            write!(
+                f,
+                ", target {:.0} rps, achieved {:.0} rps",
+                target, self.achieved_requests_per_second
+            )?;
+        }
+        if let (Some(average), Some(max)) =
+            (self.average_scheduling_delay, self.max_scheduling_delay)
+        {
+            write!(
+                f,
+                ", scheduling delay avg {:.0}ms, max {:.0}ms",
+                average.as_secs_f64() * 1000.0,
+                max.as_secs_f64() * 1000.0
+            )?;
+        }
+        if let Some(drained) = self.cooldown_drained {
+            write!(f, ", {} drained in cooldown", drained)?;
+        }
+        if self.abandoned > 0 {
+            write!(f, ", {} abandoned", self.abandoned)?;
+        }
+        Ok(())
+    }
+}
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Statistics {
+    /// See [`crate::RunOptions::run_id`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    pub summary: Summary,
+    pub clients: Vec<StatisticsClient>,
+    /// Per url breakdown, empty unless requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<StatisticsUrl>,
+    /// Per virtual user breakdown, only used in concurrency mode
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub workers: Vec<StatisticsWorker>,
+    /// Connections opened during the run
+    pub new_connections: u64,
+    /// Most connections that were being established at once
+    pub max_concurrent_handshakes: u64,
+    /// DNS lookups by host, every new connection to a host that is not an IP address makes
+    /// one
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub dns_lookups: BTreeMap<String, u64>,
+    /// Responses that came over an already used connection
+    pub reused_connections: u64,
+    /// Requests in flight over time, empty unless sampling was requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub in_flight: Vec<InFlightSample>,
+    /// Resident memory of amawk over the run, `None` unless sampling was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<StatisticsMemory>,
+    /// Whether amawk itself kept up, `None` if there were neither think times nor a schedule
+    /// to measure it by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generator_health: Option<GeneratorHealth>,
+    /// Traffic mix of the named requests, empty unless their weights drifted
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub weight_mix: Vec<StatisticsMix>,
+}
+/// How late the timers of amawk fired, an overloaded runtime inflates the latencies it
+/// measures so they say more about the generator than the target
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeneratorHealth {
+    /// Sleeps for think times that were measured
+    pub timer_samples: u64,
+    /// How much later than asked the sleeps woke up, 0 without samples
+    pub average_timer_lag: Duration,
+    pub p99_timer_lag: Duration,
+    pub max_timer_lag: Duration,
+    /// The timer lag at the 99th percentile or the mean scheduling delay passed
+    /// [`GeneratorHealth::OVERLOADED_LAG`]
+    pub overloaded: bool,
+}
+impl GeneratorHealth {
+    /// Lag after which the generator counts as overloaded, well above the 1ms resolution of
+    /// the timers
+    pub const OVERLOADED_LAG: Duration = Duration::from_millis(10);
+    /// Judges the generator by the timer lag in `data` and the mean scheduling delay in
+    /// `summary`, `None` if neither was measured
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::{
+    ///     aggregate::Aggregator,
+    ///     stats::{GeneratorHealth, Summary},
+    /// };
+    /// use std::time::Duration;
+    ///
+    /// let mut data = Aggregator::new(false);
+    /// assert!(GeneratorHealth::measure(&data, &Summary::default()).is_none());
+    /// for lag_ms in [0, 1, 1, 30].iter() {
+    ///     let lag = Duration::from_millis(*lag_ms);
+    ///     data.timer_lag.record(lag.as_nanos() as u64);
+    ///     data.timer_lag_moments.record(lag.as_secs_f64());
+    /// }
+    /// let health = GeneratorHealth::measure(&data, &Summary::default()).unwrap();
+    /// assert_eq!(health.timer_samples, 4);
+    /// assert!(health.overloaded);
+    /// ```
+    pub fn measure(data: &Aggregator, summary: &Summary) -> Option<Self> {
+        let timer_samples = data.timer_lag.count();
+        if timer_samples == 0 && summary.average_scheduling_delay.is_none() {
+            return None;
+        }
+        let (average_timer_lag, p99_timer_lag, max_timer_lag) = if timer_samples == 0 {
+            (Duration::ZERO, Duration::ZERO, Duration::ZERO)
+        } else {
+            (
+                Duration::from_secs_f64(data.timer_lag_moments.mean()),
+                Duration::from_nanos(data.timer_lag.value_at_percentile(99.0)),
+                Duration::from_nanos(data.timer_lag.value_at_percentile(100.0)),
+            )
+        };
+        let scheduling_delay = summary.average_scheduling_delay.unwrap_or_default();
+        Some(Self {
+            timer_samples,
+            average_timer_lag,
+            p99_timer_lag,
+            max_timer_lag,
+            overloaded: p99_timer_lag > Self::OVERLOADED_LAG
+                || scheduling_delay > Self::OVERLOADED_LAG,
+        })
+    }
+}
+/// Share of the chains a named request was picked for when weights drift
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StatisticsMix {
+    pub name: String,
+    /// Share given by the configured weights
+    pub configured_share: f64,
+    /// Share the drifted weights gave on average over the run
+    pub average_share: f64,
+}
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct InFlightSample {
+    /// Time since the start of the run
+    pub at: Duration,
+    pub in_flight: i64,
+}
+/// Resident memory of the generator in bytes, steady growth over a soak points at amawk
+/// rather than the target
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatisticsMemory {
+    pub samples: u64,
+    pub min_bytes: u64,
+    pub max_bytes: u64,
+    /// Last sample minus the first, negative if memory was released
+    pub growth_bytes: i64,
+}
+impl StatisticsMemory {
+    /// Summarizes samples taken in order, `None` if there are none
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::stats::StatisticsMemory;
+    ///
+    /// let memory = StatisticsMemory::from_samples(&[30, 10, 40, 25]).unwrap();
+    /// assert_eq!((memory.min_bytes, memory.max_bytes, memory.growth_bytes), (10, 40, -5));
+    /// assert!(StatisticsMemory::from_samples(&[]).is_none());
+    /// ```
+    pub fn from_samples(samples: &[u64]) -> Option<Self> {
+        Some(Self {
+            samples: samples.len() as u64,
+            min_bytes: *samples.iter().min()?,
+            max_bytes: *samples.iter().max()?,
+            growth_bytes: *samples.last()? as i64 - *samples.first()? as i64,
+        })
+    }
+}
+/// Order of the clients in [`Statistics`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SortBy {
+    /// Alphabetically by name, what [`get_stat`] returns
+    #[default]
+    Name,
+    /// Slowest average latency first
+    Latency,
+    /// Most failed chains first
+    Errors,
+}
+impl Statistics {
+    /// Reorders the clients, clients that are equal by `sort_by` stay ordered by name
+    pub fn sort_clients(&mut self, sort_by: SortBy) {
+        self.clients.sort_by(|a, b| a.name.cmp(&b.name));
+        match sort_by {
+            SortBy::Name => {}
+            SortBy::Latency => self
+                .clients
+                .sort_by_key(|client| std::cmp::Reverse(client.average_total_load_time)),
+            SortBy::Errors => self.clients.sort_by(|a, b| {
+                b.number_of_failed_requests
+                    .cmp(&a.number_of_failed_requests)
+            }),
+        }
+    }
+    /// Names of the clients whose SLA was violated
+    pub fn failed_slas(&self) -> Vec<&str> {
+        self.clients
+            .iter()
+            .filter(|c| c.sla.as_ref().is_some_and(|sla| !sla.passed))
+            .map(|c| c.name.as_str())
+            .collect()
+    }
+}
+/// Writes `rows` as columns separated by `|`, each column as wide as its widest cell
+pub(crate) fn write_table(
+    f: &mut std::fmt::Formatter<'_>,
+    rows: &[Vec<String>],
+) -> std::fmt::Result {
+    let mut widths: Vec<usize> = vec![];
+    for row in rows.iter() {
+        for (i, cell) in row.iter().enumerate() {
+            let len = cell.chars().count();
+            match widths.get_mut(i) {
+                Some(width) => *width = (*width).max(len),
+                None => widths.push(len),
+            }
+        }
+    }
+    for (i, row) in rows.iter().enumerate() {
+        if i > 0 {
+            writeln!(f)?;
+        }
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        write!(f, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+/// Unit the `stat` output shows latencies in, the json output always has seconds and
+/// nanoseconds
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LatencyUnit {
+    #[default]
+    Seconds,
+    Milliseconds,
+    Microseconds,
+}
+impl LatencyUnit {
+    /// Abbreviation used in column headers
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Self::Seconds => "s",
+            Self::Milliseconds => "ms",
+            Self::Microseconds => "us",
+        }
+    }
+    /// `latency` as a number in this unit
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::stats::LatencyUnit;
+    /// use std::time::Duration;
+    ///
+    /// let latency = Duration::from_micros(23_456);
+    /// assert_eq!(LatencyUnit::Seconds.format(latency), "0.023456");
+    /// assert_eq!(LatencyUnit::Milliseconds.format(latency), "23.456");
+    /// assert_eq!(LatencyUnit::Microseconds.format(latency), "23456");
+    /// ```
+    pub fn format(self, latency: Duration) -> String {
+        match self {
+            Self::Seconds => latency.as_secs_f64().to_string(),
+            Self::Milliseconds => format!("{:.3}", latency.as_secs_f64() * 1e3),
+            Self::Microseconds => format!("{:.0}", latency.as_secs_f64() * 1e6),
+        }
+    }
+}
+/// The `stat` tables of [`Statistics`] with latencies in a chosen unit, see
+/// [`Statistics::display`]
+pub struct StatisticsDisplay<'a> {
+    statistics: &'a Statistics,
+    unit: LatencyUnit,
+}
+impl Statistics {
+    /// Displays the statistics with latencies in `unit`, displaying [`Statistics`] itself
+    /// uses seconds. The alternate form `{:#}` adds latency by status class either way.
+    pub fn display(&self, unit: LatencyUnit) -> StatisticsDisplay<'_> {
+        StatisticsDisplay {
+            statistics: self,
+            unit,
+        }
+    }
+}
+impl std::fmt::Display for Statistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.display(LatencyUnit::Seconds), f)
+    }
+}
+impl std::fmt::Display for StatisticsDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (statistics, unit) = (self.statistics, self.unit);
+        if let Some(run_id) = &statistics.run_id {
+            writeln!(f, "run id: {}", run_id)?;
+        }
+        let percentiles = statistics
+            .clients
+            .first()
+            .map(|c| {
+                c.percentiles
+                    .iter()
+                    .map(|p| format!("p{} ({})", p.percentile, unit.suffix()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let mut header = vec![
+            "name".to_string(),
+            "total number of requests".to_string(),
+            format!("avg load time ({})", unit.suffix()),
+            format!("median ({})", unit.suffix()),
+            format!("std dev ({})", unit.suffix()),
+            format!("std err ({})", unit.suffix()),
+            format!("mean 95% ci ({})", unit.suffix()),
+        ];
+        header.extend(percentiles.iter().cloned());
+        header.extend(
+            [
+                "number of failed requests",
+                &format!("avg time to failure ({})", unit.suffix()),
+                &format!("max time to failure ({})", unit.suffix()),
+                "truncated responses",
+                "oversized responses",
+                "sla breaches",
+                "conn reuse",
+                "size p50/p95/max (B)",
+                "Common Errors",
+            ]
+            .iter()
+            .map(|h| h.to_string()),
+        );
+        let mut rows = vec![header];
+        for c in statistics.clients.iter() {
+            let mut row = vec![
+                c.name.clone(),
+                c.total.to_string(),
+                unit.format(c.average_total_load_time),
+                unit.format(c.median),
+                unit.format(c.standard_deviation),
+                c.stderr_mean
+                    .map(|stderr| unit.format(stderr))
+                    .unwrap_or_else(|| "-".to_string()),
+                match (c.mean_ci_low, c.mean_ci_high) {
+                    (Some(low), Some(high)) => {
+                        format!("{}-{}", unit.format(low), unit.format(high))
+                    }
+                    _ => "-".to_string(),
+                },
+            ];
+            row.extend(c.percentiles.iter().map(|p| unit.format(p.latency)));
+            row.extend(vec![
+                c.number_of_failed_requests.to_string(),
+                unit.format(c.average_time_to_failure),
+                unit.format(c.max_time_to_failure),
+                c.number_of_truncated_responses.to_string(),
+                c.number_of_oversized_responses.to_string(),
+                c.sla_breaches.to_string(),
+                format!("{:.1}%", c.connection_reuse_ratio * 100.0),
+                format!(
+                    "{}/{}/{}",
+                    c.response_size.p50, c.response_size.p95, c.response_size.max
+                ),
+                c.common_errors
+                    .iter()
+                    .take(2)
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ]);
+            rows.push(row);
+        }
+        write_table(f, &rows)?;
+        write!(
+            f,
+            "\n\nnew connections: {}, reused connections: {}, max concurrent handshakes: {}, dns lookups: {}",
+            statistics.new_connections,
+            statistics.reused_connections,
+            statistics.max_concurrent_handshakes,
+            statistics.dns_lookups.values().sum::<u64>()
+        )?;
+        if let Some(peak) = statistics.in_flight.iter().map(|s| s.in_flight).max() {
+            let mean = statistics
+                .in_flight
+                .iter()
+                .map(|s| s.in_flight as f64)
+                .sum::<f64>()
+                / statistics.in_flight.len() as f64;
+            write!(f, "\nrequests in flight: peak {}, mean {:.1}", peak, mean)?;
+        }
+        if let Some(memory) = &statistics.memory {
+            const MIB: f64 = 1024.0 * 1024.0;
+            write!(
+                f,
+                "\ngenerator memory: min {:.1} MiB, max {:.1} MiB, growth {:+.1} MiB over {} samples",
+                memory.min_bytes as f64 / MIB,
+                memory.max_bytes as f64 / MIB,
+                memory.growth_bytes as f64 / MIB,
+                memory.samples
+            )?;
+        }
+        if let Some(health) = &statistics.generator_health {
+            let verdict = if health.overloaded {
+                "OVERLOADED, latencies include time amawk spent behind"
+            } else {
+                "ok"
+            };
+            write!(f, "\ngenerator health: {}", verdict)?;
+            if health.timer_samples > 0 {
+                write!(
+                    f,
+                    ", timer lag avg {:.1}ms, p99 {:.1}ms, max {:.1}ms over {} sleeps",
+                    health.average_timer_lag.as_secs_f64() * 1000.0,
+                    health.p99_timer_lag.as_secs_f64() * 1000.0,
+                    health.max_timer_lag.as_secs_f64() * 1000.0,
+                    health.timer_samples
+                )?;
+            }
+            if let Some(delay) = statistics.summary.average_scheduling_delay {
+                write!(
+                    f,
+                    ", scheduling delay avg {:.1}ms",
+                    delay.as_secs_f64() * 1000.0
+                )?;
+            }
+        }
+        if !statistics.weight_mix.is_empty() {
+            let mix = statistics
+                .weight_mix
+                .iter()
+                .map(|mix| {
+                    format!(
+                        "{} {:.1}% (configured {:.1}%)",
+                        mix.name,
+                        mix.average_share * 100.0,
+                        mix.configured_share * 100.0
+                    )
+                })
+                .collect::<Vec<_>>();
+            write!(f, "\naverage weight mix: {}", mix.join(", "))?;
+        }
+        let assertions_skipped = statistics
+            .clients
+            .iter()
+            .map(|c| c.assertions_skipped)
+            .sum::<u64>();
+        if assertions_skipped > 0 {
+            write!(
+                f,
+                "\nbody assertions: {} checked, {} skipped by assert_sample_rate",
+                statistics
+                    .clients
+                    .iter()
+                    .map(|c| c.assertions_checked)
+                    .sum::<u64>(),
+                assertions_skipped
+            )?;
+        }
+        if statistics.clients.iter().any(|c| c.first_step.is_some()) {
+            let mut header = vec![
+                "name".to_string(),
+                "first step requests".to_string(),
+                "failed".to_string(),
+                format!("avg ({})", unit.suffix()),
+            ];
+            header.extend(percentiles.iter().cloned());
+            let mut rows = vec![header];
+            for c in statistics.clients.iter() {
+                if let Some(first_step) = &c.first_step {
+                    let mut row = vec![
+                        c.name.clone(),
+                        first_step.total.to_string(),
+                        first_step.number_of_failed_requests.to_string(),
+                        unit.format(first_step.average),
+                    ];
+                    row.extend(
+                        first_step
+                            .percentiles
+                            .iter()
+                            .map(|p| unit.format(p.latency)),
+                    );
+                    rows.push(row);
+                }
+            }
+            write!(f, "\n\n")?;
+            write_table(f, &rows)?;
+        }
+        if !statistics.urls.is_empty() {
+            let mut rows = vec![vec![
+                "url".to_string(),
+                "total number of requests".to_string(),
+                "number of failed requests".to_string(),
+                "sla breaches".to_string(),
+            ]];
+            for u in statistics.urls.iter() {
+                rows.push(vec![
+                    u.url.clone(),
+                    u.total.to_string(),
+                    u.number_of_failed_requests.to_string(),
+                    u.sla_breaches.to_string(),
+                ]);
+            }
+            write!(f, "\n\n")?;
+            write_table(f, &rows)?;
+        }
+        // The alternate form `{:#}` also breaks latency down by status class
+        if f.alternate() {
+            let mut header = vec![
+                "name".to_string(),
+                "status".to_string(),
+                "total number of requests".to_string(),
+            ];
+            header.extend(percentiles.iter().cloned());
+            let mut rows = vec![header];
+            for c in statistics.clients.iter() {
+                for class in c.status_classes.iter() {
+                    let mut row =
+                        vec![c.name.clone(), class.class.clone(), class.total.to_string()];
+                    row.extend(class.percentiles.iter().map(|p| unit.format(p.latency)));
+                    rows.push(row);
+                }
+            }
+            write!(f, "\n\n")?;
+            write_table(f, &rows)?;
+        }
+        if statistics.clients.iter().any(|c| c.sla.is_some()) {
+            let mut rows = vec![vec![
+                "name".to_string(),
+                "sla".to_string(),
+                "violations".to_string(),
+            ]];
+            for c in statistics.clients.iter() {
+                if let Some(sla) = &c.sla {
+                    rows.push(vec![
+                        c.name.clone(),
+                        if sla.passed { "pass" } else { "fail" }.to_string(),
+                        sla.violations.join(", "),
+                    ]);
+                }
+            }
+            write!(f, "\n\n")?;
+            write_table(f, &rows)?;
+        }
+        if !statistics.workers.is_empty() {
+            let mut rows = vec![vec![
+                "worker".to_string(),
+                "chains".to_string(),
+                "number of failed requests".to_string(),
+            ]];
+            for w in statistics.workers.iter() {
+                rows.push(vec![
+                    w.worker.to_string(),
+                    w.chains.to_string(),
+                    w.number_of_failed_requests.to_string(),
+                ]);
+            }
+            write!(f, "\n\n")?;
+            write_table(f, &rows)?;
+        }
+        Ok(())
+    }
+}
+fn error_percentage(client: &ClientAggregate) -> f64 {
+    if client.total == 0 {
+        0.0
+    } else {
+        client.failed as f64 / client.total as f64 * 100.0
+    }
+}
+/// Summary across several runs that together took `elapsed`, a suite has no target rate
+pub fn suite_summary(runs: &[&Aggregator], elapsed: Duration) -> Summary {
+    let mut all = ClientAggregate::new();
+    for client in runs.iter().flat_map(|run| run.clients.values()) {
+        all.merge(client);
+    }
+    let rate = if elapsed.is_zero() {
+        0.0
+    } else {
+        all.total as f64 / elapsed.as_secs_f64()
+    };
+    Summary {
+        total: all.total,
+        failed: all.failed,
+        error_percentage: error_percentage(&all),
+        p50: Duration::from_nanos(all.latency.value_at_percentile(50.0)),
+        p95: Duration::from_nanos(all.latency.value_at_percentile(95.0)),
+        requests_per_second: rate,
+        target_requests_per_second: None,
+        achieved_requests_per_second: rate,
+        average_scheduling_delay: None,
+        max_scheduling_delay: None,
+        abandoned: runs.iter().map(|run| run.abandoned).sum(),
+        cooldown_drained: runs
+            .iter()
+            .filter_map(|run| run.cooldown_drained)
+            .reduce(|a, b| a + b),
+        concurrency: None,
+        ramp_start: None,
+    }
+}
+/// How much the results of the same config varied when it was run several times
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepeatSummary {
+    pub runs: usize,
+    /// Mean of the 95th percentile latency of every run
+    pub mean_p95: Duration,
+    /// Standard deviation of the 95th percentile latency across runs
+    pub p95_standard_deviation: Duration,
+    pub mean_error_percentage: f64,
+    pub error_percentage_standard_deviation: f64,
+    /// Run with the lowest 95th percentile latency, counting from 1
+    pub best_run: usize,
+    pub best_p95: Duration,
+    /// Run with the highest 95th percentile latency, counting from 1
+    pub worst_run: usize,
+    pub worst_p95: Duration,
+}
+impl std::fmt::Display for RepeatSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} runs, p95 mean {:.0}ms, std dev {:.0}ms, best run {} ({:.0}ms), worst run {} ({:.0}ms), errors mean {:.1}%, std dev {:.1}%",
+            self.runs,
+            self.mean_p95.as_secs_f64() * 1000.0,
+            self.p95_standard_deviation.as_secs_f64() * 1000.0,
+            self.best_run,
+            self.best_p95.as_secs_f64() * 1000.0,
+            self.worst_run,
+            self.worst_p95.as_secs_f64() * 1000.0,
+            self.mean_error_percentage,
+            self.error_percentage_standard_deviation
+        )
+    }
+}
+/// Combines the summaries of runs of the same config, in the order they ran
+///
+/// # Examples
+///
+/// ```
+/// use amawk::stats::{repeat_summary, Summary};
+/// use std::time::Duration;
+///
+/// let runs: Vec<Summary> = [100, 140, 120]
+///     .iter()
+///     .map(|ms| Summary {
+///         p95: Duration::from_millis(*ms),
+///         ..Default::default()
+///     })
+///     .collect();
+/// let repeat = repeat_summary(&runs.iter().collect::<Vec<_>>());
+/// assert_eq!(repeat.mean_p95, Duration::from_millis(120));
+/// assert_eq!((repeat.best_run, repeat.worst_run), (1, 2));
+/// ```
+pub fn repeat_summary(runs: &[&Summary]) -> RepeatSummary {
+    let mut p95 = Moments::default();
+    let mut errors = Moments::default();
+    for run in runs.iter() {
+        p95.record(run.p95.as_secs_f64());
+        errors.record(run.error_percentage);
+    }
+    let best = (0..runs.len())
+        .min_by_key(|run| runs[*run].p95)
+        .unwrap_or(0);
+    let worst = (0..runs.len())
+        .max_by_key(|run| runs[*run].p95)
+        .unwrap_or(0);
+    RepeatSummary {
+        runs: runs.len(),
+        mean_p95: Duration::from_secs_f64(p95.mean()),
+        p95_standard_deviation: Duration::from_secs_f64(p95.variance().sqrt()),
+        mean_error_percentage: errors.mean(),
+        error_percentage_standard_deviation: errors.variance().sqrt(),
+        best_run: best + 1,
+        best_p95: runs.get(best).map(|run| run.p95).unwrap_or_default(),
+        worst_run: worst + 1,
+        worst_p95: runs.get(worst).map(|run| run.p95).unwrap_or_default(),
+    }
+}
+pub fn get_stat(
+    data: &Aggregator,
+    percentiles: &[f64],
+    per_url: bool,
+    cdf_points: Option<usize>,
+) -> Statistics {
+    // The aggregator keeps clients and urls in hash maps, sorting them makes the output of
+    // two runs comparable line by line
+    let mut clients = data.clients.iter().collect::<Vec<_>>();
+    clients.sort_by(|a, b| a.0.cmp(b.0));
+    let mut urls = data.urls.iter().collect::<Vec<_>>();
+    urls.sort_by(|a, b| a.0.cmp(b.0));
+    let mut all = ClientAggregate::new();
+    for client in data.clients.values() {
+        all.merge(client);
+    }
+    let mut statistics = Statistics {
+        // Set by the caller, the aggregator does not know it
+        run_id: None,
+        summary: Summary {
+            total: all.total,
+            failed: all.failed,
+            error_percentage: error_percentage(&all),
+            p50: Duration::from_nanos(all.latency.value_at_percentile(50.0)),
+            p95: Duration::from_nanos(all.latency.value_at_percentile(95.0)),
+            requests_per_second: if data.elapsed.is_zero() {
+                0.0
+            } else {
+                all.total as f64 / data.elapsed.as_secs_f64()
+            },
+            target_requests_per_second: data.target_rate,
+            // Chains whose timeout was ignored were still dispatched
+            achieved_requests_per_second: match data.target_rate {
+                Some(_) if !data.duration.is_zero() => {
+                    (all.total + all.ignored_timeouts) as f64
+                        / data.dispatch_elapsed.max(data.duration).as_secs_f64()
+                }
+                _ if !data.elapsed.is_zero() => {
+                    (all.total + all.ignored_timeouts) as f64 / data.elapsed.as_secs_f64()
+                }
+                _ => 0.0,
+            },
+            average_scheduling_delay: data
+                .target_rate
+                .map(|_| Duration::from_secs_f64(data.scheduling_delay_moments.mean())),
+            max_scheduling_delay: data
+                .target_rate
+                .map(|_| Duration::from_nanos(data.scheduling_delay.value_at_percentile(100.0))),
+            abandoned: data.abandoned,
+            cooldown_drained: data.cooldown_drained,
+            concurrency: data.concurrency,
+            ramp_start: data.ramp_start,
+        },
+        clients: clients
+            .into_iter()
+            .map(|(name, client)| StatisticsClient {
+                name: name.clone(),
+                labels: data.labels.get(name).cloned().unwrap_or_default(),
+                total: client.total,
+                average_total_load_time: Duration::from_secs_f64(client.moments.mean()),
+                median: Duration::from_nanos(client.latency.value_at_percentile(50.0)),
+                standard_deviation: Duration::from_secs_f64(client.moments.variance().sqrt()),
+                stderr_mean: client.moments.standard_error().map(Duration::from_secs_f64),
+                mean_ci_low: client
+                    .moments
+                    .confidence_interval()
+                    .map(|(low, _)| Duration::from_secs_f64(low.max(0.0))),
+                mean_ci_high: client
+                    .moments
+                    .confidence_interval()
+                    .map(|(_, high)| Duration::from_secs_f64(high)),
+                percentiles: percentiles
+                    .iter()
+                    .map(|percentile| Percentile {
+                        percentile: *percentile,
+                        latency: Duration::from_nanos(
+                            client.latency.value_at_percentile(*percentile),
+                        ),
+                    })
+                    .collect(),
+                number_of_failed_requests: client.failed,
+                average_time_to_failure: Duration::from_secs_f64(
+                    client.time_to_failure_moments.mean(),
+                ),
+                max_time_to_failure: Duration::from_nanos(
+                    client.time_to_failure.value_at_percentile(100.0),
+                ),
+                number_of_truncated_responses: client.truncated,
+                number_of_oversized_responses: client.oversized,
+                sla_breaches: client.sla_breaches,
+                connection_reuse_ratio: if client.responses == 0 {
+                    0.0
+                } else {
+                    client.reused_connections as f64 / client.responses as f64
+                },
+                common_errors: client.errors.most_common().drain(..).cloned().collect(),
+                headers_only_steps: client.headers_only,
+                assertions_checked: client.assertions_checked,
+                assertions_skipped: client.assertions_skipped,
+                skipped_steps: client.skipped,
+                ignored_timeouts: client.ignored_timeouts,
+                first_request_at: client.first_request_at,
+                last_request_at: client.last_request_at,
+                first_step: client
+                    .first_step
+                    .as_ref()
+                    .map(|first_step| StatisticsFirstStep {
+                        total: first_step.total,
+                        number_of_failed_requests: first_step.failed,
+                        average: Duration::from_secs_f64(first_step.moments.mean()),
+                        percentiles: percentiles
+                            .iter()
+                            .map(|percentile| Percentile {
+                                percentile: *percentile,
+                                latency: Duration::from_nanos(
+                                    first_step.latency.value_at_percentile(*percentile),
+                                ),
+                            })
+                            .collect(),
+                    }),
+                cdf: cdf_points
+                    .map(|points| client.latency.cdf(points))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(latency, fraction)| CdfPoint {
+                        latency: Duration::from_nanos(latency),
+                        fraction,
+                    })
+                    .collect(),
+                response_size: StatisticsSize {
+                    p50: client.response_sizes.value_at_percentile(50.0),
+                    p95: client.response_sizes.value_at_percentile(95.0),
+                    max: client.response_sizes.value_at_percentile(100.0),
+                },
+                request_size: (client.request_sizes.count() > 0).then(|| StatisticsSize {
+                    p50: client.request_sizes.value_at_percentile(50.0),
+                    p95: client.request_sizes.value_at_percentile(95.0),
+                    max: client.request_sizes.value_at_percentile(100.0),
+                }),
+                sla: data.slas.get(name).map(|sla| {
+                    let violations = sla.check(client);
+                    SlaStatus {
+                        passed: violations.is_empty(),
+                        violations,
+                    }
+                }),
+                status_classes: client
+                    .status_classes
+                    .iter()
+                    .map(|(class, latency)| StatisticsStatusClass {
+                        class: class.clone(),
+                        total: latency.count(),
+                        percentiles: percentiles
+                            .iter()
+                            .map(|percentile| Percentile {
+                                percentile: *percentile,
+                                latency: Duration::from_nanos(
+                                    latency.value_at_percentile(*percentile),
+                                ),
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+        urls: if per_url {
+            urls.into_iter()
+                .map(|(url, counts)| StatisticsUrl {
+                    url: url.clone(),
+                    total: counts.total,
+                    number_of_failed_requests: counts.failed,
+                    sla_breaches: counts.sla_breaches,
+                })
+                .collect()
+        } else {
+            vec![]
+        },
+        workers: {
+            let mut workers = data
+                .workers
+                .iter()
+                .map(|(worker, counts)| StatisticsWorker {
+                    worker: *worker,
+                    chains: counts.chains,
+                    number_of_failed_requests: counts.failed,
+                })
+                .collect::<Vec<_>>();
+            workers.sort_by_key(|w| w.worker);
+            workers
+        },
+        new_connections: data.new_connections,
+        max_concurrent_handshakes: data.max_concurrent_handshakes,
+        dns_lookups: data.dns_lookups.clone(),
+        reused_connections: data
+            .clients
+            .values()
+            .map(|client| client.reused_connections)
+            .sum(),
+        in_flight: data
+            .in_flight
+            .iter()
+            .map(|(at, in_flight)| InFlightSample {
+                at: *at,
+                in_flight: *in_flight,
+            })
+            .collect(),
+        memory: StatisticsMemory::from_samples(
+            &data
+                .memory
+                .iter()
+                .map(|(_, bytes)| *bytes)
+                .collect::<Vec<_>>(),
+        ),
+        weight_mix: data
+            .weight_mix
+            .iter()
+            .map(|(name, configured_share, average_share)| StatisticsMix {
+                name: name.clone(),
+                configured_share: *configured_share,
+                average_share: *average_share,
+            })
+            .collect(),
+        generator_health: None,
+    };
+    statistics.generator_health = GeneratorHealth::measure(data, &statistics.summary);
+    statistics
+}
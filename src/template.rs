@@ -0,0 +1,268 @@
+use rand::{rngs::StdRng, Rng};
+use std::{convert::TryFrom, fmt::Write as _};
+
+const FIRST_NAMES: &[&str] = &[
+    "Ada", "Alan", "Barbara", "Claude", "Dennis", "Edsger", "Frances", "Grace", "John", "Ken",
+    "Linus", "Margaret", "Niklaus", "Radia", "Tim",
+];
+const LAST_NAMES: &[&str] = &[
+    "Allen",
+    "Hamilton",
+    "Hopper",
+    "Kernighan",
+    "Knuth",
+    "Lamport",
+    "Liskov",
+    "Lovelace",
+    "McCarthy",
+    "Perlman",
+    "Ritchie",
+    "Shannon",
+    "Thompson",
+    "Turing",
+    "Wirth",
+];
+
+/// Text with `{{token}}` placeholders that are replaced with generated data every time it is
+/// rendered. Supported tokens are `{{name}}`, `{{email}}`, `{{uuid}}` and
+/// `{{rand_int:MIN:MAX}}`, unknown tokens are rejected when the template is parsed unless it
+/// is parsed with [`Template::with_fields`].
+///
+/// # Examples
+///
+/// ```
+/// use amawk::template::Template;
+/// use rand::{rngs::StdRng, SeedableRng};
+/// use std::convert::TryFrom;
+///
+/// let template = Template::try_from(r#"{"age": {{rand_int:18:18}}}"#).unwrap();
+/// assert_eq!(template.render(&mut StdRng::seed_from_u64(1)), r#"{"age": 18}"#);
+/// assert!(Template::try_from("{{phone}}").is_err());
+/// ```
+#[derive(Clone, Debug)]
+pub struct Template {
+    /// The text the template was parsed from
+    source: String,
+    parts: Vec<Part>,
+}
+#[derive(Clone, Debug)]
+enum Part {
+    Text(String),
+    Name,
+    Email,
+    Uuid,
+    RandInt {
+        min: i64,
+        max: i64,
+    },
+    /// Path to a value of the data row, such as `["user", "id"]` for `{{user.id}}`
+    Field(Vec<String>),
+}
+impl TryFrom<&str> for Template {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    fn try_from(template: &str) -> Result<Self, Self::Error> {
+        parse(template, false)
+    }
+}
+fn parse(
+    template: &str,
+    fields: bool,
+) -> Result<Template, Box<dyn std::error::Error + Send + Sync>> {
+    let mut parts = vec![];
+    let mut rest = template;
+    while let Some(open) = rest.find("{{") {
+        if open > 0 {
+            parts.push(Part::Text(rest[..open].to_string()));
+        }
+        let close = rest[open..]
+            .find("}}")
+            .ok_or_else(|| format!("unclosed token in template {}", template))?;
+        parts.push(parse_token(&rest[open + 2..open + close], fields)?);
+        rest = &rest[open + close + 2..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest.to_string()));
+    }
+    Ok(Template {
+        source: template.to_string(),
+        parts,
+    })
+}
+fn parse_token(
+    token: &str,
+    fields: bool,
+) -> Result<Part, Box<dyn std::error::Error + Send + Sync>> {
+    let mut args = token.trim().split(':');
+    Ok(match (args.next(), args.next(), args.next(), args.next()) {
+        (Some("name"), None, None, None) => Part::Name,
+        (Some("email"), None, None, None) => Part::Email,
+        (Some("uuid"), None, None, None) => Part::Uuid,
+        (Some("rand_int"), Some(min), Some(max), None) => {
+            let min = min
+                .parse()
+                .map_err(|_| format!("invalid rand_int bound {} in {{{{{}}}}}", min, token))?;
+            let max = max
+                .parse()
+                .map_err(|_| format!("invalid rand_int bound {} in {{{{{}}}}}", max, token))?;
+            if min > max {
+                return Err(
+                    format!("rand_int minimum is over its maximum in {{{{{}}}}}", token).into(),
+                );
+            }
+            Part::RandInt { min, max }
+        }
+        _ if fields && is_field(token.trim()) => {
+            Part::Field(token.trim().split('.').map(str::to_string).collect())
+        }
+        _ => return Err(format!("unknown template token {{{{{}}}}}", token).into()),
+    })
+}
+/// Whether `token` is names made of letters, digits and `_` separated by `.`
+fn is_field(token: &str) -> bool {
+    token
+        .split('.')
+        .all(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+}
+/// Value at `path` in `row`, following object keys
+pub fn lookup<'a>(row: &'a serde_json::Value, path: &[String]) -> Option<&'a serde_json::Value> {
+    path.iter().try_fold(row, |value, name| value.get(name))
+}
+impl Template {
+    /// Parses like [`TryFrom`] but reads tokens such as `{{id}}` or `{{user.id}}` that are not
+    /// one of the generated tokens as fields of the data row passed to
+    /// [`Template::render_row`]. Generated tokens take precedence, a field called `name`
+    /// can not be referenced.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::template::Template;
+    /// use rand::{rngs::StdRng, SeedableRng};
+    ///
+    /// let template = Template::with_fields(r#"{"id": {{user.id}}, "tag": "{{tag}}"}"#).unwrap();
+    /// let row = serde_json::json!({"user": {"id": 7}, "tag": "a"});
+    /// let mut rng = StdRng::seed_from_u64(1);
+    /// assert_eq!(template.render_row(&mut rng, Some(&row)), r#"{"id": 7, "tag": "a"}"#);
+    /// assert_eq!(template.fields(), vec!["user.id", "tag"]);
+    /// ```
+    pub fn with_fields(template: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        parse(template, true)
+    }
+    /// Fields the template reads from the data row, written like the token
+    pub fn fields(&self) -> Vec<String> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Field(path) => Some(path.join(".")),
+                _ => None,
+            })
+            .collect()
+    }
+    /// The text the template was parsed from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    /// Whether rendering can give different text every time
+    pub fn has_tokens(&self) -> bool {
+        self.parts.iter().any(|part| !matches!(part, Part::Text(_)))
+    }
+    /// The template with every token left out
+    pub fn text(&self) -> String {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+    /// Replaces every token with data drawn from `rng`
+    pub fn render(&self, rng: &mut StdRng) -> String {
+        self.render_row(rng, None)
+    }
+    /// Replaces every token with data drawn from `rng` and fields with their value in `row`.
+    /// Strings are inserted without quotes, other values as json and missing fields as
+    /// nothing.
+    pub fn render_row(&self, rng: &mut StdRng, row: Option<&serde_json::Value>) -> String {
+        self.render_escaped(rng, row, |value, out| out.push_str(value))
+    }
+    /// Renders like [`Template::render_row`] for the path or query of a url, every inserted
+    /// value is percent encoded so the url stays valid
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use amawk::template::Template;
+    /// use rand::{rngs::StdRng, SeedableRng};
+    ///
+    /// let template = Template::with_fields("/search?q={{query}}").unwrap();
+    /// let row = serde_json::json!({"query": "a&b c"});
+    /// let mut rng = StdRng::seed_from_u64(1);
+    /// assert_eq!(template.render_url(&mut rng, Some(&row)), "/search?q=a%26b%20c");
+    /// ```
+    pub fn render_url(&self, rng: &mut StdRng, row: Option<&serde_json::Value>) -> String {
+        self.render_escaped(rng, row, |value, out| {
+            for byte in value.bytes() {
+                if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                    out.push(byte as char);
+                } else {
+                    write!(out, "%{:02X}", byte).unwrap();
+                }
+            }
+        })
+    }
+    /// Renders like [`Template::render_row`] for a header value, control characters are left
+    /// out of inserted values so the value stays valid
+    pub fn render_header(&self, rng: &mut StdRng, row: Option<&serde_json::Value>) -> String {
+        self.render_escaped(rng, row, |value, out| {
+            out.extend(value.chars().filter(|c| *c == '\t' || !c.is_control()))
+        })
+    }
+    /// Renders the template, every value replacing a token is added to the output by `escape`
+    fn render_escaped(
+        &self,
+        rng: &mut StdRng,
+        row: Option<&serde_json::Value>,
+        escape: fn(&str, &mut String),
+    ) -> String {
+        let mut out = String::new();
+        for part in self.parts.iter() {
+            let value = match part {
+                Part::Text(text) => {
+                    out.push_str(text);
+                    continue;
+                }
+                Part::Field(path) => match row.and_then(|row| lookup(row, path)) {
+                    Some(serde_json::Value::String(text)) => text.clone(),
+                    Some(value) => value.to_string(),
+                    None => continue,
+                },
+                Part::Name => {
+                    let (first, last) = name(rng);
+                    format!("{} {}", first, last)
+                }
+                Part::Email => {
+                    let (first, last) = name(rng);
+                    format!(
+                        "{}.{}{}@example.com",
+                        first.to_lowercase(),
+                        last.to_lowercase(),
+                        rng.gen_range(0..1000)
+                    )
+                }
+                Part::Uuid => uuid::Builder::from_random_bytes(rng.gen())
+                    .into_uuid()
+                    .to_string(),
+                Part::RandInt { min, max } => rng.gen_range(*min..=*max).to_string(),
+            };
+            escape(&value, &mut out);
+        }
+        out
+    }
+}
+fn name(rng: &mut StdRng) -> (&'static str, &'static str) {
+    (
+        FIRST_NAMES[rng.gen_range(0..FIRST_NAMES.len())],
+        LAST_NAMES[rng.gen_range(0..LAST_NAMES.len())],
+    )
+}
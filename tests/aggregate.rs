@@ -0,0 +1,123 @@
+use amawk::{
+    aggregate::{get_chain_status, Aggregator},
+    run::{ChainResult, RequestStatus, StepResult},
+    stats::{get_stat, suite_summary},
+};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime},
+};
+
+/// A step that took `elapsed` and ended with `status`
+fn step(status: RequestStatus, elapsed: Duration) -> StepResult {
+    StepResult {
+        url: "http://localhost/".to_string(),
+        started_at: SystemTime::now(),
+        status,
+        elapsed,
+        sla_breached: false,
+        reused_connection: None,
+        status_code: None,
+        retries: 0,
+        redirects: 0,
+        response_bytes: None,
+        request_bytes: None,
+        headers_only: false,
+        request_id: None,
+        headers: BTreeMap::new(),
+        variables: BTreeMap::new(),
+        body_checked: None,
+        think_time: None,
+        timer_lag: None,
+    }
+}
+fn success(millis: u64) -> StepResult {
+    let delay = Duration::from_millis(millis);
+    step(
+        RequestStatus::Sucess {
+            delay,
+            url: "http://localhost/".to_string(),
+        },
+        delay,
+    )
+}
+
+#[test]
+fn time_to_failure_stops_at_the_first_failure() {
+    let steps = [
+        success(10),
+        step(RequestStatus::Timeout, Duration::from_millis(100)),
+        success(1000),
+    ];
+    let chain = get_chain_status(&steps);
+    assert_eq!(chain.status, RequestStatus::Timeout);
+    assert_eq!(chain.successful_latency, Duration::from_millis(10));
+    assert_eq!(chain.time_to_failure, Some(Duration::from_millis(110)));
+}
+
+#[test]
+fn successful_chain_sums_every_step() {
+    let chain = get_chain_status(&[success(10), success(20)]);
+    assert_eq!(chain.successful_latency, Duration::from_millis(30));
+    assert_eq!(chain.time_to_failure, None);
+}
+
+/// Records `chains` chains of varied latency where every tenth fails with its own status
+fn record_chains(aggregator: &mut Aggregator, chains: u64) {
+    for i in 0..chains {
+        let steps = if i % 10 == 0 {
+            let status = RequestStatus::UnexpectedStatus {
+                status: (i % 1000) as u16,
+            };
+            vec![step(status, Duration::from_millis(5))]
+        } else {
+            vec![success(i % 5000)]
+        };
+        aggregator.record(ChainResult {
+            name: "home".to_string(),
+            worker: None,
+            scheduling_delay: Some(Duration::from_micros(i % 100)),
+            steps,
+        });
+    }
+}
+/// Sizes of everything the aggregator holds that could grow with the number of chains
+fn footprint(aggregator: &Aggregator) -> (usize, usize, usize, usize) {
+    let client = &aggregator.clients["home"];
+    (
+        aggregator.clients.len(),
+        aggregator.urls.len(),
+        client.errors.most_common().len(),
+        client.status_classes.len(),
+    )
+}
+
+#[test]
+fn memory_is_bounded_without_detail() {
+    let mut small = Aggregator::new(false);
+    record_chains(&mut small, 1_000);
+    let mut large = Aggregator::new(false);
+    record_chains(&mut large, 200_000);
+    assert_eq!(large.clients["home"].total, 200_000);
+    assert!(large.detail.is_none());
+    assert_eq!(footprint(&small), footprint(&large));
+    // 100 distinct errors were seen, only the most common are tracked
+    assert!(large.clients["home"].errors.most_common().len() < 100);
+}
+
+#[test]
+fn detail_keeps_every_chain() {
+    let mut aggregator = Aggregator::new(true);
+    record_chains(&mut aggregator, 1_000);
+    assert_eq!(aggregator.detail.unwrap()["home"].len(), 1_000);
+}
+
+#[test]
+fn summaries_count_failed_chains() {
+    let mut aggregator = Aggregator::new(false);
+    record_chains(&mut aggregator, 1_003);
+    let summary = get_stat(&aggregator, &[50.0], false, None).summary;
+    assert_eq!((summary.total, summary.failed), (1_003, 101));
+    let suite = suite_summary(&[&aggregator, &aggregator], Duration::from_secs(1));
+    assert_eq!((suite.total, suite.failed), (2_006, 202));
+}
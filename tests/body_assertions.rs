@@ -0,0 +1,42 @@
+mod common;
+
+use amawk::{config::DRequestGroup, request::RequestGroup, run::probe};
+use common::{Reply, StubServer};
+use std::convert::TryFrom;
+
+/// A group with a single request to `url` and the given assertions
+fn group(url: &str, assertions: &str) -> RequestGroup {
+    let config: DRequestGroup = serde_yaml::from_str(&format!(
+        "requests:
+  - proportion: 1
+    name: home
+    requests:
+      - {{url: '{}', {}}}
+number_of_requests: 1
+duration_s: 1",
+        url, assertions
+    ))
+    .unwrap();
+    RequestGroup::try_from(config).unwrap()
+}
+
+#[tokio::test]
+async fn forbidden_body_text_fails_a_successful_status() {
+    // A soft error, the status is 200 but the body has a stack trace
+    let server = StubServer::start(|_| Reply::ok("ok: false, Exception at line 3")).await;
+    let group = group(
+        &server.url("/"),
+        "expect_body_contains: 'ok', expect_body_not_contains: Exception",
+    );
+    let probed = &probe(&group, None).await[0];
+    assert!(probed.result.failed());
+    assert!(probed.result.status.to_string().contains("Exception"));
+}
+
+#[tokio::test]
+async fn body_without_forbidden_text_succeeds() {
+    let server = StubServer::start(|_| Reply::ok("ok: true")).await;
+    let group = group(&server.url("/"), "expect_body_not_contains: Exception");
+    let probed = &probe(&group, None).await[0];
+    assert!(!probed.result.failed(), "{}", probed.result.status);
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use amawk::{builder::RequestGroupBuilder, config::DChainLatency, run, RunOptions};
+use common::StubServer;
+use std::time::Duration;
+
+/// Average latency of a two step chain with `think` time after each step
+async fn average_latency(url: &str, think: Duration, chain_latency: DChainLatency) -> Duration {
+    let group = RequestGroupBuilder::new()
+        .ranked_request("journey", 1.0, |b| {
+            b.request(url, think).request(url, think)
+        })
+        .chain_latency(chain_latency)
+        .number_of_requests(1)
+        .duration(Duration::from_millis(10))
+        .build()
+        .unwrap();
+    let result = run(&group, RunOptions::default()).await;
+    result.statistics.clients[0].average_total_load_time
+}
+
+#[tokio::test]
+async fn think_time_counts_only_when_included() {
+    let server = StubServer::ok().await;
+    let url = server.url("/");
+    let think = Duration::from_millis(200);
+    let excluded = average_latency(&url, think, DChainLatency::ExcludeThinkTime).await;
+    let included = average_latency(&url, think, DChainLatency::IncludeThinkTime).await;
+    assert!(excluded < think);
+    assert!(included >= think * 2);
+}
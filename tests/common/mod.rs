@@ -0,0 +1,171 @@
+//! A stub HTTP/1.1 server shared by the integration tests
+#![allow(dead_code)]
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// A request as the stub server received it
+#[derive(Clone, Debug)]
+pub struct Received {
+    pub method: String,
+    /// Path and query of the request line
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+impl Received {
+    /// Value of the first header called `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+/// A response of the stub server
+#[derive(Clone, Debug)]
+pub struct Reply {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    /// Closes the connection after the response so the next request needs a new one
+    pub close: bool,
+}
+impl Reply {
+    /// A 200 with `body`
+    pub fn ok(body: &str) -> Self {
+        Self::status(200).body(body)
+    }
+    /// An empty response with `status`
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: String::new(),
+            close: false,
+        }
+    }
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = body.to_string();
+        self
+    }
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    pub fn close(mut self) -> Self {
+        self.close = true;
+        self
+    }
+}
+type Handler = dyn Fn(&Received) -> Reply + Send + Sync;
+/// A server on a free local port answering every request with its handler, until the
+/// test ends
+pub struct StubServer {
+    pub address: SocketAddr,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+impl StubServer {
+    pub async fn start(handler: impl Fn(&Received) -> Reply + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let received = Arc::new(Mutex::new(vec![]));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let log = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(_) => continue,
+                };
+                tokio::spawn(serve(stream, handler.clone(), log.clone()));
+            }
+        });
+        Self { address, received }
+    }
+    /// Answers every request with an empty 200
+    pub async fn ok() -> Self {
+        Self::start(|_| Reply::ok("")).await
+    }
+    /// Url of `path` on the server, `path` starts with a slash
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.address, path)
+    }
+    /// Every request received so far, in order
+    pub fn received(&self) -> Vec<Received> {
+        self.received.lock().unwrap().clone()
+    }
+}
+async fn serve(mut stream: TcpStream, handler: Arc<Handler>, log: Arc<Mutex<Vec<Received>>>) {
+    let mut buf = vec![];
+    while let Some(request) = read_request(&mut stream, &mut buf).await {
+        let reply = handler(&request);
+        // Like a real server these advertise the length of the body without sending it
+        let bodiless = request.method == "HEAD" || reply.status == 304;
+        log.lock().unwrap().push(request);
+        let mut response = format!("HTTP/1.1 {} Stub\r\n", reply.status);
+        for (name, value) in reply.headers.iter() {
+            response.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if reply.close {
+            response.push_str("connection: close\r\n");
+        }
+        response.push_str(&format!("content-length: {}\r\n\r\n", reply.body.len()));
+        if !bodiless {
+            response.push_str(&reply.body);
+        }
+        if stream.write_all(response.as_bytes()).await.is_err() || reply.close {
+            return;
+        }
+    }
+}
+/// Reads the next request of a keep-alive connection, bytes past it stay in `buf`
+async fn read_request(stream: &mut TcpStream, buf: &mut Vec<u8>) -> Option<Received> {
+    let end = loop {
+        if let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end;
+        }
+        read_more(stream, buf).await?;
+    };
+    let head = String::from_utf8_lossy(&buf[..end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?.to_string();
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect::<Vec<_>>();
+    let mut request = Received {
+        method,
+        target,
+        headers,
+        body: vec![],
+    };
+    let length = request
+        .header("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    buf.drain(..end + 4);
+    while buf.len() < length {
+        read_more(stream, buf).await?;
+    }
+    request.body = buf.drain(..length).collect();
+    Some(request)
+}
+async fn read_more(stream: &mut TcpStream, buf: &mut Vec<u8>) -> Option<()> {
+    let mut chunk = [0; 4096];
+    match stream.read(&mut chunk).await {
+        Ok(0) | Err(_) => None,
+        Ok(n) => {
+            buf.extend_from_slice(&chunk[..n]);
+            Some(())
+        }
+    }
+}
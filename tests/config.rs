@@ -0,0 +1,153 @@
+use amawk::{config::DRequestGroup, request::RequestGroup};
+use std::convert::TryFrom;
+
+/// Error loading a group with a single request with the fields in `request`
+fn load_error(request: &str) -> String {
+    let config: DRequestGroup = serde_yaml::from_str(&format!(
+        "requests:
+  - weight: 1
+    name: home
+    requests:
+      - {{{}}}
+number_of_requests: 1
+duration_s: 1",
+        request
+    ))
+    .unwrap();
+    match RequestGroup::try_from(config) {
+        Ok(_) => panic!("{} was accepted", request),
+        Err(err) => err.to_string(),
+    }
+}
+
+#[test]
+fn relative_url_without_base_url_is_rejected() {
+    assert_eq!(
+        load_error("url: '/health'"),
+        "/health: url has no scheme, use an absolute url or set base_url"
+    );
+}
+
+#[test]
+fn url_with_unsupported_scheme_is_rejected() {
+    assert_eq!(
+        load_error("url: 'ftp://files.example.com/report.csv'"),
+        "ftp://files.example.com/report.csv: unsupported scheme ftp"
+    );
+}
+
+#[test]
+fn strict_load_rejects_what_lenient_load_skips() {
+    let config: DRequestGroup = serde_yaml::from_str(
+        "requests:
+  - {weight: 1, name: good, requests: [{url: 'http://localhost/'}]}
+  - {weight: 1, name: bad, requests: [{url: 'ftp://localhost/'}]}
+number_of_requests: 1
+duration_s: 1",
+    )
+    .unwrap();
+    let (group, skipped) = RequestGroup::try_from_lenient(config.clone()).unwrap();
+    assert_eq!(group.requests.len(), 1);
+    assert_eq!(skipped.len(), 1);
+    let err = RequestGroup::try_from(config).err().unwrap().to_string();
+    assert!(
+        err.contains("ftp://localhost/: unsupported scheme ftp"),
+        "{}",
+        err
+    );
+    assert!(
+        skipped[0].contains("ftp://localhost/: unsupported scheme ftp"),
+        "{}",
+        skipped[0]
+    );
+}
+
+#[test]
+fn step_without_urls_is_named_in_the_error() {
+    assert_eq!(load_error("urls: []"), "home step 1: urls is empty");
+    assert_eq!(load_error("method: GET"), "home step 1: request has no url");
+}
+
+#[test]
+fn weights_too_extreme_to_pick_from_are_rejected() {
+    for (group, error) in [
+        (
+            "selection: cost_weighted
+requests:
+  - {name: tiny, weight: 1e-200, cost: 1e200, requests: [{url: 'http://localhost/'}]}",
+            "tiny has a weight / cost of 0, too extreme to pick from",
+        ),
+        (
+            "requests:
+  - {name: a, weight: 1e308, requests: [{url: 'http://localhost/'}]}
+  - {name: b, weight: 1e308, requests: [{url: 'http://localhost/'}]}",
+            "the weights of the requests add up to more than can be picked from",
+        ),
+    ]
+    .iter()
+    {
+        let config: DRequestGroup =
+            serde_yaml::from_str(&format!("{}\nnumber_of_requests: 1\nduration_s: 1", group))
+                .unwrap();
+        let err = RequestGroup::try_from(config).err().unwrap().to_string();
+        assert_eq!(err, *error);
+    }
+}
+
+#[test]
+fn invalid_max_latency_is_rejected() {
+    for (yaml, shown) in [("-1", "-1"), (".nan", "NaN"), (".inf", "inf")].iter() {
+        assert_eq!(
+            load_error(&format!(
+                "url: 'http://localhost/', max_latency_s: {}",
+                yaml
+            )),
+            format!("http://localhost/: max_latency_s of {} is invalid", shown)
+        );
+    }
+}
+
+#[test]
+fn invalid_capacity_max_p95_is_rejected() {
+    for (yaml, shown) in [
+        ("-5", "-5"),
+        ("0", "0"),
+        (".nan", "NaN"),
+        ("1e25", "10000000000000000000000000"),
+    ]
+    .iter()
+    {
+        let config: DRequestGroup = serde_yaml::from_str(&format!(
+            "requests:
+  - {{name: home, weight: 1, requests: [{{url: 'http://localhost/'}}]}}
+capacity: {{start_rps: 1, step_rps: 1, max_rps: 2, window_s: 1, max_p95_ms: {}}}
+number_of_requests: 1
+duration_s: 1",
+            yaml
+        ))
+        .unwrap();
+        assert_eq!(
+            RequestGroup::try_from(config).err().unwrap().to_string(),
+            format!("capacity max_p95_ms of {} is invalid", shown)
+        );
+    }
+}
+
+#[test]
+fn invalid_pool_idle_timeout_is_rejected() {
+    for (yaml, shown) in [("-1", "-1"), (".nan", "NaN"), (".inf", "inf")].iter() {
+        let config: DRequestGroup = serde_yaml::from_str(&format!(
+            "requests:
+  - {{name: home, weight: 1, requests: [{{url: 'http://localhost/'}}]}}
+pool_idle_timeout_s: {}
+number_of_requests: 1
+duration_s: 1",
+            yaml
+        ))
+        .unwrap();
+        assert_eq!(
+            RequestGroup::try_from(config).err().unwrap().to_string(),
+            format!("pool_idle_timeout_s of {} is invalid", shown)
+        );
+    }
+}
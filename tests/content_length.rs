@@ -0,0 +1,36 @@
+mod common;
+
+use amawk::{config::DRequestGroup, request::RequestGroup, run::probe, run::RequestStatus};
+use common::{Reply, StubServer};
+use std::convert::TryFrom;
+
+/// Status of probing a single request to `url` with the fields in `request`
+async fn probe_status(url: &str, request: &str) -> RequestStatus {
+    let config: DRequestGroup = serde_yaml::from_str(&format!(
+        "requests:
+  - weight: 1
+    name: home
+    requests:
+      - {{url: '{}', {}}}
+number_of_requests: 1
+duration_s: 1",
+        url, request
+    ))
+    .unwrap();
+    let group = RequestGroup::try_from(config).unwrap();
+    probe(&group, None).await.remove(0).result.status
+}
+
+#[tokio::test]
+async fn head_responses_are_not_truncated() {
+    let server = StubServer::start(|_| Reply::ok("hello")).await;
+    let status = probe_status(&server.url("/"), "method: HEAD").await;
+    assert!(matches!(status, RequestStatus::Sucess { .. }), "{}", status);
+}
+
+#[tokio::test]
+async fn not_modified_responses_are_not_truncated() {
+    let server = StubServer::start(|_| Reply::status(304).body("hello")).await;
+    let status = probe_status(&server.url("/"), "success: {status: [304]}").await;
+    assert!(matches!(status, RequestStatus::Sucess { .. }), "{}", status);
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use amawk::{config::DRequestGroup, request::RequestGroup, run, RunOptions};
+use common::{Reply, StubServer};
+use std::{
+    convert::TryFrom,
+    time::{Duration, Instant},
+};
+
+#[tokio::test]
+async fn rate_limited_chain_sleeps_the_delay_of_its_status() {
+    let server = StubServer::start(|_| Reply::status(429)).await;
+    let config: DRequestGroup = serde_yaml::from_str(&format!(
+        "requests:
+  - weight: 1
+    name: backoff
+    requests:
+      - {{url: '{}', delay_s: 0.01, delay_on_status: {{429: 0.5, 503: 2}}}}
+number_of_requests: 1
+duration_s: 0.01",
+        server.url("/")
+    ))
+    .unwrap();
+    let group = RequestGroup::try_from(config).unwrap();
+    let start = Instant::now();
+    let result = run(&group, RunOptions::default()).await;
+    assert_eq!(result.statistics.clients[0].total, 1);
+    // The chain slept the 429 delay rather than the base delay
+    assert!(start.elapsed() >= Duration::from_millis(500));
+}
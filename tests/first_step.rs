@@ -0,0 +1,37 @@
+mod common;
+
+use amawk::{builder::RequestGroupBuilder, run, RunOptions};
+use common::StubServer;
+use std::time::Duration;
+
+#[tokio::test]
+async fn first_step_is_reported_for_multi_step_chains_only() {
+    let server = StubServer::ok().await;
+    // The landing page succeeds, the step after it goes nowhere
+    let group = RequestGroupBuilder::new()
+        .ranked_request("journey", 1.0, |b| {
+            b.sequential(true)
+                .request(&server.url("/"), Duration::ZERO)
+                .request("http://127.0.0.1:1/", Duration::ZERO)
+        })
+        .ranked_request("single", 1.0, |b| {
+            b.request("http://127.0.0.1:1/", Duration::ZERO)
+        })
+        .number_of_requests(10)
+        .duration(Duration::from_millis(50))
+        .seed(1)
+        .build()
+        .unwrap();
+    let result = run(&group, RunOptions::default()).await;
+    let clients = &result.statistics.clients;
+    let journey = clients.iter().find(|c| c.name == "journey").unwrap();
+    let first_step = journey.first_step.as_ref().unwrap();
+    assert_eq!(journey.number_of_failed_requests, journey.total);
+    assert_eq!(
+        (first_step.total, first_step.number_of_failed_requests),
+        (journey.total, 0)
+    );
+    assert_eq!(first_step.percentiles.len(), journey.percentiles.len());
+    let single = clients.iter().find(|c| c.name == "single").unwrap();
+    assert!(single.first_step.is_none());
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use amawk::{builder::RequestGroupBuilder, run, RunOptions};
+use common::{Reply, StubServer};
+use std::time::Duration;
+
+#[tokio::test]
+async fn handshake_limit_caps_concurrent_handshakes() {
+    // Every response closes its connection, so every request opens a new one
+    let server = StubServer::start(|_| Reply::ok("").close()).await;
+    let url = server.url("/");
+    let group = RequestGroupBuilder::new()
+        .ranked_request("home", 1.0, |b| b.request(&url, Duration::ZERO))
+        .number_of_requests(20)
+        .duration(Duration::from_millis(10))
+        .max_concurrent_handshakes(1)
+        .build()
+        .unwrap();
+    let result = run(&group, RunOptions::default()).await;
+    assert_eq!(result.statistics.clients[0].number_of_failed_requests, 0);
+    assert_eq!(result.statistics.new_connections, 20);
+    assert_eq!(result.statistics.max_concurrent_handshakes, 1);
+}
+
+#[test]
+fn handshake_limit_of_zero_is_rejected() {
+    let group = RequestGroupBuilder::new()
+        .ranked_request("home", 1.0, |b| {
+            b.request("http://localhost/", Duration::ZERO)
+        })
+        .max_concurrent_handshakes(0)
+        .build();
+    assert!(group.is_err());
+}
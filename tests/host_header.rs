@@ -0,0 +1,44 @@
+mod common;
+
+use amawk::{config::DRequestGroup, request::RequestGroup, run::probe};
+use common::StubServer;
+use std::convert::TryFrom;
+
+/// Probes a group with a single request to `url` with the fields in `fields`
+async fn probe_request(url: &str, fields: &str) {
+    let config: DRequestGroup = serde_yaml::from_str(&format!(
+        "requests:
+  - weight: 1
+    name: home
+    requests:
+      - {{url: '{}'{}}}
+number_of_requests: 1
+duration_s: 1",
+        url, fields
+    ))
+    .unwrap();
+    let group = RequestGroup::try_from(config).unwrap();
+    for probed in probe(&group, None).await {
+        assert!(!probed.result.failed(), "{}", probed.result.status);
+    }
+}
+
+#[tokio::test]
+async fn host_header_overrides_the_connect_address() {
+    let server = StubServer::ok().await;
+    probe_request(&server.url("/"), ", host_header: api.example.com").await;
+    let host = server.received()[0].header("host").unwrap().to_string();
+    assert_eq!(host, "api.example.com");
+    assert_ne!(host, server.address.to_string());
+}
+
+#[tokio::test]
+async fn host_header_defaults_to_the_url() {
+    let server = StubServer::ok().await;
+    probe_request(&server.url("/"), "").await;
+    let received = server.received();
+    assert_eq!(
+        received[0].header("host"),
+        Some(server.address.to_string().as_str())
+    );
+}
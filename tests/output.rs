@@ -0,0 +1,95 @@
+mod common;
+
+use amawk::{
+    aggregate::Aggregator,
+    builder::RequestGroupBuilder,
+    capacity::Capacity,
+    output::{OutputFormatter, OutputRegistry},
+    run,
+    stats::{get_stat, suite_summary},
+    RunOptions, RunResult,
+};
+use common::StubServer;
+use std::time::Duration;
+
+/// The result of a run that recorded nothing
+fn empty_run() -> RunResult {
+    let aggregator = Aggregator::new(false);
+    let statistics = get_stat(&aggregator, &[50.0], false, None);
+    RunResult {
+        aggregator,
+        statistics,
+    }
+}
+
+/// Renders the config name alone
+struct Name;
+impl OutputFormatter for Name {
+    fn render(&self, config: &str, _result: &RunResult) -> String {
+        format!("ran {}", config)
+    }
+}
+
+#[test]
+fn suites_and_capacity_searches_have_default_renderings() {
+    let (a, b) = (empty_run(), empty_run());
+    let summary = suite_summary(&[&a.aggregator, &b.aggregator], Duration::from_secs(1));
+    let suite = Name.render_suite(&[("a.yml", &a), ("b.yml", &b)], &summary, &[]);
+    assert!(
+        suite.starts_with("a.yml\nran a.yml\n\nb.yml\nran b.yml\n\nsuite: "),
+        "{}",
+        suite
+    );
+    let capacity = Capacity {
+        steps: vec![],
+        sustainable_rps: None,
+        breached_rps: None,
+    };
+    assert!(Name.render_capacity(&capacity).ends_with("no windows ran"));
+}
+
+#[tokio::test]
+async fn builtin_formats_render_a_suite() {
+    let server = StubServer::ok().await;
+    let group = RequestGroupBuilder::new()
+        .ranked_request("home", 1.0, |b| b.request(&server.url("/"), Duration::ZERO))
+        .number_of_requests(4)
+        .duration(Duration::from_millis(10))
+        .build()
+        .unwrap();
+    let options = || RunOptions {
+        cdf_points: Some(2),
+        ..Default::default()
+    };
+    let (a, b) = (run(&group, options()).await, run(&group, options()).await);
+    let summary = suite_summary(&[&a.aggregator, &b.aggregator], Duration::from_secs(1));
+    let runs = [("a.yml", &a), ("b.yml", &b)];
+    let registry = OutputRegistry::builtin();
+    for name in registry.names() {
+        let output = registry
+            .get(name)
+            .unwrap()
+            .render_suite(&runs, &summary, &[]);
+        assert!(
+            output.contains("a.yml") && output.contains("b.yml"),
+            "{}: {}",
+            name,
+            output
+        );
+    }
+    let curve = registry
+        .get("curve")
+        .unwrap()
+        .render_suite(&runs, &summary, &[]);
+    assert_eq!(curve.lines().count(), 3);
+    let cdf = registry
+        .get("cdf")
+        .unwrap()
+        .render_suite(&runs, &summary, &[]);
+    let mut lines = cdf.lines();
+    assert_eq!(lines.next(), Some("config,name,latency_s,fraction"));
+    let configs = lines
+        .map(|line| line.split(',').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(configs, ["a.yml", "a.yml", "b.yml", "b.yml"]);
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use amawk::{config::DRequestGroup, request::RequestGroup, run::probe};
+use common::{Reply, StubServer};
+use std::convert::TryFrom;
+
+#[tokio::test]
+async fn requests_go_through_their_own_proxy() {
+    // Stand in proxies that answer every request with their name
+    let a = StubServer::start(|_| Reply::ok("proxy-a")).await;
+    let b = StubServer::start(|_| Reply::ok("proxy-b")).await;
+    let config: DRequestGroup = serde_yaml::from_str(&format!(
+        "proxy: {}
+requests:
+  - proportion: 1
+    name: mixed
+    requests:
+      - {{url: 'http://a.invalid/', expect_body_contains: proxy-a}}
+      - {{url: 'http://b.invalid/', expect_body_contains: proxy-b, proxy: '{}'}}
+number_of_requests: 1
+duration_s: 1",
+        a.url(""),
+        b.url("")
+    ))
+    .unwrap();
+    let group = RequestGroup::try_from(config).unwrap();
+    // Neither host resolves, so both only succeed through their proxy
+    for probed in probe(&group, None).await {
+        assert!(!probed.result.failed(), "{}", probed.result.status);
+    }
+    // Proxies are sent the absolute url
+    assert_eq!(a.received()[0].target, "http://a.invalid/");
+    assert_eq!(b.received()[0].target, "http://b.invalid/");
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use amawk::{
+    config::DRequestGroup,
+    request::RequestGroup,
+    run::{probe, RequestStatus, StepResult},
+};
+use common::{Received, Reply, StubServer};
+use std::convert::TryFrom;
+
+/// Result of a single request to `url` that follows redirects with the fields in `fields`
+async fn follow(url: &str, fields: &str) -> StepResult {
+    let config: DRequestGroup = serde_yaml::from_str(&format!(
+        "requests:
+  - weight: 1
+    name: home
+    requests:
+      - {{url: '{}', follow_redirects: true{}}}
+number_of_requests: 1
+duration_s: 1",
+        url, fields
+    ))
+    .unwrap();
+    let group = RequestGroup::try_from(config).unwrap();
+    probe(&group, None).await.remove(0).result
+}
+fn redirect(location: &str) -> Reply {
+    Reply::status(302).header("location", location)
+}
+fn targets(received: &[Received]) -> Vec<&str> {
+    received
+        .iter()
+        .map(|request| request.target.as_str())
+        .collect()
+}
+
+#[tokio::test]
+async fn three_hop_chain_is_followed() {
+    let server = StubServer::start(|request| match request.target.as_str() {
+        "/a" => redirect("/b"),
+        "/b" => redirect("/c"),
+        "/c" => redirect("/d"),
+        _ => Reply::ok("landed"),
+    })
+    .await;
+    let result = follow(&server.url("/a"), "").await;
+    assert!(!result.failed(), "{}", result.status);
+    assert_eq!(result.redirects, 3);
+    assert_eq!(targets(&server.received()), vec!["/a", "/b", "/c", "/d"]);
+}
+
+#[tokio::test]
+async fn two_node_loop_is_stopped() {
+    let server = StubServer::start(|request| match request.target.as_str() {
+        "/ping" => redirect("/pong"),
+        _ => redirect("/ping"),
+    })
+    .await;
+    let result = follow(&server.url("/ping"), ", max_redirects: 5").await;
+    assert_eq!(result.status, RequestStatus::RedirectLoop);
+    // Stopped as soon as the loop closed, well within max_redirects
+    assert_eq!(targets(&server.received()), vec!["/ping", "/pong"]);
+}
+
+#[tokio::test]
+async fn endless_chain_stops_at_max_redirects() {
+    // Every hop goes to a new url, so only the hop limit stops it
+    let server = StubServer::start(|request| {
+        let hop: u32 = request.target.trim_start_matches("/hop/").parse().unwrap();
+        redirect(&format!("/hop/{}", hop + 1))
+    })
+    .await;
+    let result = follow(&server.url("/hop/0"), ", max_redirects: 3").await;
+    assert_eq!(
+        result.status,
+        RequestStatus::TooManyRedirects { max_redirects: 3 }
+    );
+    assert_eq!(result.redirects, 3);
+    assert_eq!(server.received().len(), 4);
+}
@@ -0,0 +1,34 @@
+use amawk::{config::DSla, sla::Sla};
+use std::{convert::TryFrom, time::Duration};
+
+fn sla(yaml: &str) -> Result<Sla, String> {
+    let config: DSla = serde_yaml::from_str(yaml).unwrap();
+    Sla::try_from(&config).map_err(|err| err.to_string())
+}
+
+#[test]
+fn thresholds_are_read_in_milliseconds() {
+    let sla = sla("{mean_ms: 250, p95_ms: 500, p99_ms: 1000.5, error_rate: 0.01}").unwrap();
+    assert_eq!(sla.mean, Some(Duration::from_millis(250)));
+    assert_eq!(sla.p95, Some(Duration::from_millis(500)));
+    assert_eq!(sla.p99, Some(Duration::from_micros(1_000_500)));
+    assert_eq!(sla.error_rate, Some(0.01));
+}
+
+#[test]
+fn invalid_thresholds_are_rejected() {
+    for (yaml, error) in [
+        ("{mean_ms: -1}", "sla mean_ms of -1 is invalid"),
+        ("{p95_ms: .nan}", "sla p95_ms of NaN is invalid"),
+        ("{p99_ms: .inf}", "sla p99_ms of inf is invalid"),
+        (
+            "{p99_ms: 1e25}",
+            "sla p99_ms of 10000000000000000000000000 is invalid",
+        ),
+        ("{error_rate: 2}", "sla error_rate 2 is not in [0, 1]"),
+    ]
+    .iter()
+    {
+        assert_eq!(sla(yaml).err().as_deref(), Some(*error), "{}", yaml);
+    }
+}
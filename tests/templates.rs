@@ -0,0 +1,162 @@
+mod common;
+
+use amawk::{config::DRequestGroup, request::RequestGroup, run, RunOptions};
+use common::{Reply, StubServer};
+use std::convert::TryFrom;
+
+/// Loads a group with a single chain of the yaml `chain`, indented as a list item
+fn load(chain: &str) -> Result<RequestGroup, String> {
+    let config: DRequestGroup = serde_yaml::from_str(&format!(
+        "requests:
+  - weight: 1
+    name: home
+{}
+number_of_requests: 1
+duration_s: 0.01
+seed: 1",
+        chain
+    ))
+    .unwrap();
+    RequestGroup::try_from(config).map_err(|err| err.to_string())
+}
+async fn run_chain(chain: &str) {
+    let group = load(chain).unwrap();
+    let result = run(&group, RunOptions::default()).await;
+    assert_eq!(result.statistics.clients[0].number_of_failed_requests, 0);
+}
+
+#[tokio::test]
+async fn url_and_headers_are_rendered_for_every_execution() {
+    let server = StubServer::ok().await;
+    run_chain(&format!(
+        "    requests:
+      - url: '{}'
+        host_header: 'tenant-{{{{rand_int:7:7}}}}.example.com'
+        accept: 'application/vnd.v{{{{rand_int:2:2}}}}+json'",
+        server.url("/users/{{rand_int:42:42}}?name={{name}}")
+    ))
+    .await;
+    let received = &server.received()[0];
+    assert!(
+        received.target.starts_with("/users/42?name="),
+        "{}",
+        received.target
+    );
+    // The generated name has a space, which is percent encoded
+    assert!(received.target.contains("%20"), "{}", received.target);
+    assert_eq!(received.header("host"), Some("tenant-7.example.com"));
+    assert_eq!(received.header("accept"), Some("application/vnd.v2+json"));
+}
+
+#[tokio::test]
+async fn extracted_variables_are_rendered_into_urls_and_headers() {
+    let server = StubServer::start(|request| match request.target.as_str() {
+        "/login" => Reply::ok("").header("x-user", "a/b"),
+        _ => Reply::ok(""),
+    })
+    .await;
+    run_chain(&format!(
+        "    sequential: true
+    requests:
+      - {{url: '{}', extract_headers: {{user: X-User}}}}
+      - {{url: '{}', accept: 'text/{{{{user}}}}'}}",
+        server.url("/login"),
+        server.url("/users/{{user}}/orders")
+    ))
+    .await;
+    let received = server.received();
+    assert_eq!(received[1].target, "/users/a%2Fb/orders");
+    assert_eq!(received[1].header("accept"), Some("text/a/b"));
+}
+
+#[tokio::test]
+async fn data_rows_are_rendered_into_urls() {
+    let server = StubServer::ok().await;
+    let data = std::env::temp_dir().join(format!("amawk-templates-{}.json", std::process::id()));
+    std::fs::write(&data, r#"[{"user": {"id": 7}}]"#).unwrap();
+    run_chain(&format!(
+        "    data_file: '{}'
+    requests:
+      - url: '{}'",
+        data.display(),
+        server.url("/users/{{user.id}}")
+    ))
+    .await;
+    std::fs::remove_file(&data).unwrap();
+    assert_eq!(server.received()[0].target, "/users/7");
+}
+
+#[tokio::test]
+async fn results_record_the_configured_url() {
+    let server = StubServer::ok().await;
+    let url = server.url("/users/{{rand_int:1:1000}}");
+    let group = load(&format!(
+        "    requests:
+      - url: '{}'
+        query_params: {{q: [a, b, c]}}",
+        url
+    ))
+    .unwrap();
+    let options = RunOptions {
+        per_url: true,
+        ..Default::default()
+    };
+    let result = run(&group, options).await;
+    assert!(server.received()[0].target.contains("?q="));
+    let urls = &result.statistics.urls;
+    assert_eq!(urls.len(), 1);
+    assert_eq!(urls[0].url, url);
+}
+
+#[test]
+fn relative_url_templates_resolve_against_the_base_url() {
+    let config: DRequestGroup = serde_yaml::from_str(
+        "base_url: 'http://api.example.com/v1/'
+requests:
+  - weight: 1
+    name: home
+    requests:
+      - url: 'users/{{uuid}}'
+number_of_requests: 1
+duration_s: 1",
+    )
+    .unwrap();
+    let group = RequestGroup::try_from(config).unwrap();
+    let request = &group.requests[0].requests[0];
+    assert_eq!(
+        request.url_template.as_ref().unwrap().source(),
+        "http://api.example.com/v1/users/{{uuid}}"
+    );
+    assert_eq!(request.uri, "http://api.example.com/v1/users/");
+}
+
+#[test]
+fn invalid_templates_are_rejected_at_load() {
+    for (request, error) in [
+        (
+            "{url: 'http://{{uuid}}.example.com/'}",
+            "http://{{uuid}}.example.com/: template tokens are only supported in the path and query of a url",
+        ),
+        (
+            "{url: 'http://localhost/{{phone_number}}'}",
+            "home: unknown template token {{phone_number}}, fields need a data_file or an earlier step's extract_headers",
+        ),
+        (
+            "{url: 'http://localhost/', host_header: '{{user.host}}'}",
+            "home: unknown template token {{user.host}}, fields need a data_file or an earlier step's extract_headers",
+        ),
+        (
+            "{url: 'http://localhost/', accept: '{{rand_int:2:1}}'}",
+            "http://localhost/: invalid accept header {{rand_int:2:1}}: rand_int minimum is over its maximum in {{rand_int:2:1}}",
+        ),
+        (
+            "{urls: ['http://a.test/{{uuid}}', 'http://b.test/']}",
+            "http://a.test/{{uuid}}: template tokens are only supported in url",
+        ),
+    ]
+    .iter()
+    {
+        let chain = format!("    requests:\n      - {}", request);
+        assert_eq!(load(&chain).err().as_deref(), Some(*error), "{}", request);
+    }
+}